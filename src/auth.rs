@@ -3,11 +3,14 @@
 //! These API endpoints are used to manage users and roles.
 
 use http::{StatusCode, Uri};
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
 use serde_derive::{Deserialize, Serialize};
 use serde_json;
 
-use crate::client::{parse_empty_response, Client, ClusterInfo, Response};
+use crate::client::{
+    parse_empty_response, parse_json_body, read_body, response_endpoint, Client, ClusterInfo,
+    Response,
+};
 use crate::error::Error;
 
 /// The structure returned by the `GET /v2/auth/enable` endpoint.
@@ -17,6 +20,13 @@ struct AuthStatus {
     pub enabled: bool,
 }
 
+/// The body of an error response from the auth API.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+struct AuthError {
+    /// A description of the error.
+    message: String,
+}
+
 /// The type returned when the auth system is successfully enabled or disabled.
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum AuthChange {
@@ -33,9 +43,36 @@ pub struct User {
     #[serde(rename = "user")]
     name: String,
     /// The names of roles granted to the user.
+    #[serde(deserialize_with = "deserialize_role_names")]
     roles: Vec<String>,
 }
 
+/// A role granted to a user, which etcd lists either by name or in full depending on the
+/// endpoint.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UserRole {
+    Name(String),
+    Role(Role),
+}
+
+/// Deserializes the roles of a user into their names.
+fn deserialize_role_names<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let roles: Option<Vec<UserRole>> = Deserialize::deserialize(deserializer)?;
+
+    Ok(roles
+        .unwrap_or_default()
+        .into_iter()
+        .map(|role| match role {
+            UserRole::Name(name) => name,
+            UserRole::Role(role) => role.name,
+        })
+        .collect())
+}
+
 impl User {
     /// Returns the user's name.
     pub fn name(&self) -> &str {
@@ -217,6 +254,9 @@ impl Role {
     }
 
     /// Grants read permission for a key in etcd's key-value store to this role.
+    ///
+    /// A key ending with `*` is a prefix pattern, such as `/app/*`, which grants access to every
+    /// key that starts with the prefix.
     pub fn grant_kv_read_permission<K>(&mut self, key: K)
     where
         K: Into<String>,
@@ -225,6 +265,9 @@ impl Role {
     }
 
     /// Grants write permission for a key in etcd's key-value store to this role.
+    ///
+    /// A key ending with `*` is a prefix pattern, such as `/app/*`, which grants access to every
+    /// key that starts with the prefix.
     pub fn grant_kv_write_permission<K>(&mut self, key: K)
     where
         K: Into<String>,
@@ -256,6 +299,10 @@ struct Roles {
 }
 
 /// Parameters used to update an existing authorization role.
+///
+/// Only the permissions granted and revoked are sent, as etcd's `grant` and `revoke` fields, and
+/// etcd applies them to the role's current permissions. Unlike replacing the whole role, this
+/// means concurrent updates from different clients add up rather than overwrite each other.
 #[derive(Debug, Deserialize, Clone, Eq, Hash, PartialEq, Serialize)]
 pub struct RoleUpdate {
     /// The name of the role.
@@ -411,25 +458,51 @@ impl Permission {
 
 type EtcdAuthResult<T> = Result<Response<T>, Vec<Error>>;
 
+/// Changes the password of a user, keeping the user's roles, and returns the user.
+///
+/// This is a shorthand for `auth::update_user` with a `UserUpdate` that only sets the password.
+/// Requests authenticated with the old password fail afterwards.
+///
+/// # Errors
+///
+/// Fails with `Error::UserNotFound` if the user does not exist.
+pub async fn change_password<U, P>(
+    client: &Client,
+    user_name: U,
+    password: P,
+) -> EtcdAuthResult<User>
+where
+    U: Into<String>,
+    P: Into<String>,
+{
+    let mut update = UserUpdate::new(user_name);
+    update.update_password(password);
+    update_user(client, update).await
+}
+
 /// Creates a new role.
+///
+/// # Errors
+///
+/// Fails with `Error::RoleAlreadyExists` if a role with the same name already exists.
 pub async fn create_role(client: &Client, role: Role) -> EtcdAuthResult<Role> {
     let body = serde_json::to_string(&role).map_err(|e| vec![e.into()])?;
 
     client
         .first_ok(|client, endpoint| {
             let body = body.clone();
-            let url = build_url(endpoint, &format!("/roles/{}", role.name));
+            let url = build_url(&endpoint, &format!("/roles/{}", role.name));
             async move {
-                let response = client
-                    .http_client()
-                    .put(url)
-                    .body(body)
-                    .header(
-                        http::header::CONTENT_TYPE,
-                        "application/x-www-form-urlencoded",
-                    )
-                    .send()
-                    .await?;
+                let request = client.http_client().put(url).body(body).header(
+                    http::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                );
+                let response = client.send(request).await?;
+                if response.status() == StatusCode::CONFLICT {
+                    return Err(Error::RoleAlreadyExists(
+                        auth_error_message(response).await?,
+                    ));
+                }
                 parse_auth_response(response, |s| {
                     s == StatusCode::OK || s == StatusCode::CREATED
                 })
@@ -445,19 +518,14 @@ pub async fn create_user(client: &Client, user: NewUser) -> EtcdAuthResult<User>
 
     client
         .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, &format!("/users/{}", user.name));
+            let url = build_url(&endpoint, &format!("/users/{}", user.name));
             let body = body.clone();
             async move {
-                let response = client
-                    .http_client()
-                    .put(url)
-                    .body(body)
-                    .header(
-                        http::header::CONTENT_TYPE,
-                        "application/x-www-form-urlencoded",
-                    )
-                    .send()
-                    .await?;
+                let request = client.http_client().put(url).body(body).header(
+                    http::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                );
+                let response = client.send(request).await?;
                 parse_auth_response(response, |s| {
                     s == StatusCode::OK || s == StatusCode::CREATED
                 })
@@ -476,9 +544,9 @@ where
 
     client
         .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, &format!("/roles/{}", role_name));
+            let url = build_url(&endpoint, &format!("/roles/{}", role_name));
             async move {
-                let response = client.http_client().delete(url).send().await?;
+                let response = client.send(client.http_client().delete(url)).await?;
                 parse_empty_response(response).await
             }
         })
@@ -493,9 +561,9 @@ where
     let user_name = user_name.as_ref();
     client
         .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, &format!("/users/{}", user_name));
+            let url = build_url(&endpoint, &format!("/users/{}", user_name));
             async move {
-                let response = client.http_client().delete(url).send().await?;
+                let response = client.send(client.http_client().delete(url)).await?;
                 parse_empty_response(response).await
             }
         })
@@ -503,26 +571,38 @@ where
 }
 
 /// Attempts to disable the auth system.
+///
+/// # Errors
+///
+/// Fails with `Error::RootUserRequired` if the client is not authenticated as the root user.
 pub async fn disable(client: &Client) -> EtcdAuthResult<AuthChange> {
     client
         .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, "/enable");
+            let url = build_url(&endpoint, "/enable");
             async move {
-                let response = client.http_client().delete(url).send().await?;
-                parse_auth_change_response(response)
+                let response = client.send(client.http_client().delete(url)).await?;
+                parse_auth_change_response(response).await
             }
         })
         .await
 }
 
 /// Attempts to enable the auth system.
+///
+/// The root user must be created with `auth::create_user` first, since otherwise nobody could
+/// administer the cluster once auth is enabled.
+///
+/// # Errors
+///
+/// Fails with `Error::RootUserRequired` if no root user exists yet, or if auth is already enabled
+/// and the client is not authenticated as the root user.
 pub async fn enable(client: &Client) -> EtcdAuthResult<AuthChange> {
     client
         .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, "/enable");
+            let url = build_url(&endpoint, "/enable");
             async move {
-                let response = client.http_client().put(url).send().await?;
-                parse_auth_change_response(response)
+                let response = client.send(client.http_client().put(url)).await?;
+                parse_auth_change_response(response).await
             }
         })
         .await
@@ -536,10 +616,10 @@ where
     let role_name = role_name.as_ref();
 
     client
-        .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, &format!("/roles/{}", role_name));
+        .first_ok_idempotent(|client, endpoint| {
+            let url = build_url(&endpoint, &format!("/roles/{}", role_name));
             async move {
-                let response = client.http_client().get(url).send().await?;
+                let response = client.send(client.http_client().get(url)).await?;
                 parse_auth_response(response, |s| s == StatusCode::OK).await
             }
         })
@@ -549,11 +629,21 @@ where
 /// Get a role.
 pub async fn get_roles<N>(client: &Client) -> EtcdAuthResult<Vec<Role>> {
     client
-        .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, "/roles");
+        .first_ok_idempotent(|client, endpoint| {
+            let url = build_url(&endpoint, "/roles");
             async move {
-                let response = client.http_client().get(url).send().await?;
-                parse_auth_response(response, |s| s == StatusCode::OK).await
+                let response = client.send(client.http_client().get(url)).await?;
+                let response: Response<Roles> =
+                    parse_auth_response(response, |s| s == StatusCode::OK).await?;
+
+                Ok(Response {
+                    cluster_info: response.cluster_info,
+                    data: response.data.roles.unwrap_or_default(),
+                    endpoint: response.endpoint,
+                    cached: response.cached,
+                    dry_run: response.dry_run,
+                    retries: response.retries,
+                })
             }
         })
         .await
@@ -567,10 +657,10 @@ where
     let user_name = user_name.as_ref();
 
     client
-        .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, &format!("/users/{}", user_name));
+        .first_ok_idempotent(|client, endpoint| {
+            let url = build_url(&endpoint, &format!("/users/{}", user_name));
             async move {
-                let response = client.http_client().get(url).send().await?;
+                let response = client.send(client.http_client().get(url)).await?;
                 parse_auth_response(response, |s| s == StatusCode::OK).await
             }
         })
@@ -578,56 +668,112 @@ where
 }
 
 /// Gets all users.
-pub async fn get_users<N>(client: &Client) -> EtcdAuthResult<Vec<User>> {
+pub async fn get_users<N>(client: &Client) -> EtcdAuthResult<Vec<UserDetail>> {
     client
-        .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, "/users");
+        .first_ok_idempotent(|client, endpoint| {
+            let url = build_url(&endpoint, "/users");
             async move {
-                let response = client.http_client().get(url).send().await?;
-                parse_auth_response(response, |s| s == StatusCode::OK).await
+                let response = client.send(client.http_client().get(url)).await?;
+                let response: Response<Users> =
+                    parse_auth_response(response, |s| s == StatusCode::OK).await?;
+
+                Ok(Response {
+                    cluster_info: response.cluster_info,
+                    data: response.data.users.unwrap_or_default(),
+                    endpoint: response.endpoint,
+                    cached: response.cached,
+                    dry_run: response.dry_run,
+                    retries: response.retries,
+                })
             }
         })
         .await
 }
 
+/// Grants a role to a user, keeping the user's other roles, and returns the updated user.
+///
+/// This is a shorthand for `auth::update_user` with a `UserUpdate` that only grants the role.
+///
+/// # Errors
+///
+/// Fails with `Error::UserNotFound` if the user does not exist, or with `Error::RoleNotFound` if
+/// the role does not exist.
+pub async fn grant_user_role<U, R>(
+    client: &Client,
+    user_name: U,
+    role_name: R,
+) -> EtcdAuthResult<User>
+where
+    U: Into<String>,
+    R: Into<String>,
+{
+    let mut update = UserUpdate::new(user_name);
+    update.grant_role(role_name);
+    update_user(client, update).await
+}
+
+/// Revokes a role from a user, keeping the user's other roles, and returns the updated user.
+///
+/// This is a shorthand for `auth::update_user` with a `UserUpdate` that only revokes the role.
+///
+/// # Errors
+///
+/// Fails with `Error::UserNotFound` if the user does not exist, or with `Error::RoleNotFound` if
+/// the role does not exist.
+pub async fn revoke_user_role<U, R>(
+    client: &Client,
+    user_name: U,
+    role_name: R,
+) -> EtcdAuthResult<User>
+where
+    U: Into<String>,
+    R: Into<String>,
+{
+    let mut update = UserUpdate::new(user_name);
+    update.revoke_role(role_name);
+    update_user(client, update).await
+}
+
 /// Determines whether or not the auth system is enabled.
 pub async fn status(client: &Client) -> EtcdAuthResult<bool> {
     client
-        .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, "/enable");
+        .first_ok_idempotent(|client, endpoint| {
+            let url = build_url(&endpoint, "/enable");
             async move {
-                let response = client.http_client().get(url).send().await?;
+                let response = client.send(client.http_client().get(url)).await?;
                 let response: Response<AuthStatus> =
                     parse_auth_response(response, |s| s == StatusCode::OK).await?;
 
                 Ok(Response {
                     cluster_info: response.cluster_info,
                     data: response.data.enabled,
+                    endpoint: response.endpoint,
+                    cached: response.cached,
+                    dry_run: response.dry_run,
+                    retries: response.retries,
                 })
             }
         })
         .await
 }
 
-/// Updates an existing role.
+/// Updates an existing role by granting and revoking the permissions in `role`, returning the
+/// role with its resulting permissions.
+///
+/// Permissions not mentioned in `role` are left unchanged.
 pub async fn update_role(client: &Client, role: RoleUpdate) -> EtcdAuthResult<Role> {
     let body = serde_json::to_string(&role).map_err(|e| vec![e.into()])?;
 
     client
         .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, &format!("/roles/{}", role.name));
+            let url = build_url(&endpoint, &format!("/roles/{}", role.name));
             let body = body.clone();
             async move {
-                let response = client
-                    .http_client()
-                    .put(url)
-                    .body(body)
-                    .header(
-                        http::header::CONTENT_TYPE,
-                        "application/x-www-form-urlencoded",
-                    )
-                    .send()
-                    .await?;
+                let request = client.http_client().put(url).body(body).header(
+                    http::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                );
+                let response = client.send(request).await?;
                 parse_auth_response(response, |s| s == StatusCode::OK).await
             }
         })
@@ -640,19 +786,14 @@ pub async fn update_user(client: &Client, user: UserUpdate) -> EtcdAuthResult<Us
 
     client
         .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, &format!("/users/{}", user.name));
+            let url = build_url(&endpoint, &format!("/users/{}", user.name));
             let body = body.clone();
             async move {
-                let response = client
-                    .http_client()
-                    .put(url)
-                    .body(body)
-                    .header(
-                        http::header::CONTENT_TYPE,
-                        "application/x-www-form-urlencoded",
-                    )
-                    .send()
-                    .await?;
+                let request = client.http_client().put(url).body(body).header(
+                    http::header::CONTENT_TYPE,
+                    "application/x-www-form-urlencoded",
+                );
+                let response = client.send(request).await?;
                 parse_auth_response(response, |s| s == StatusCode::OK).await
             }
         })
@@ -673,29 +814,73 @@ where
 {
     let status_code = response.status();
     let cluster_info = ClusterInfo::from(response.headers());
-    let body = response.bytes().await?;
+    let endpoint = response_endpoint(&response)?;
+    let body = read_body(response).await?;
     if status_code_is_success(status_code) {
-        match serde_json::from_slice::<T>(&body) {
-            Ok(data) => Ok(Response { data, cluster_info }),
-            Err(error) => Err(Error::Serialization(error)),
+        let data = parse_json_body(&body)?;
+        Ok(Response {
+            data,
+            cluster_info,
+            endpoint,
+            cached: false,
+            dry_run: false,
+            retries: 0,
+        })
+    } else if status_code == StatusCode::NOT_FOUND {
+        // etcd's message tells whether the user or the role is missing.
+        match parse_json_body::<AuthError>(&body) {
+            Ok(error) if error.message.starts_with("Role ") => {
+                Err(Error::RoleNotFound(error.message))
+            }
+            Ok(error) if error.message.starts_with("User ") => {
+                Err(Error::UserNotFound(error.message))
+            }
+            _ => Err(Error::UnexpectedStatus(status_code)),
         }
     } else {
         Err(Error::UnexpectedStatus(status_code))
     }
 }
 
-fn parse_auth_change_response(response: reqwest::Response) -> Result<Response<AuthChange>, Error> {
+/// Reads the message of an error response from the auth API.
+async fn auth_error_message(response: reqwest::Response) -> Result<String, Error> {
+    let body = read_body(response).await?;
+    Ok(parse_json_body::<AuthError>(&body)?.message)
+}
+
+/// Parses the response of a call that enables or disables the auth system.
+async fn parse_auth_change_response(
+    response: reqwest::Response,
+) -> Result<Response<AuthChange>, Error> {
     let status = response.status();
     let cluster_info = ClusterInfo::from(response.headers());
-    match status {
-        StatusCode::OK => Ok(Response {
-            data: AuthChange::Changed,
-            cluster_info,
-        }),
-        StatusCode::CONFLICT => Ok(Response {
-            data: AuthChange::Unchanged,
-            cluster_info,
-        }),
-        _ => Err(Error::UnexpectedStatus(status)),
-    }
+    let endpoint = response_endpoint(&response)?;
+    let body = read_body(response).await?;
+
+    let message = || {
+        parse_json_body::<AuthError>(&body)
+            .map(|error| error.message)
+            .unwrap_or_default()
+    };
+    let data = match status {
+        StatusCode::OK => AuthChange::Changed,
+        // etcd also uses this status when enabling auth without a root user.
+        StatusCode::CONFLICT => match message() {
+            message if message.contains("root user") => {
+                return Err(Error::RootUserRequired(message))
+            }
+            _ => AuthChange::Unchanged,
+        },
+        StatusCode::UNAUTHORIZED => return Err(Error::RootUserRequired(message())),
+        _ => return Err(Error::UnexpectedStatus(status)),
+    };
+
+    Ok(Response {
+        data,
+        cluster_info,
+        endpoint,
+        cached: false,
+        dry_run: false,
+        retries: 0,
+    })
 }
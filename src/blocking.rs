@@ -0,0 +1,424 @@
+//! A blocking facade over the asynchronous client, for code that does not use futures.
+//!
+//! `blocking::Client` wraps a `Client` together with a single-threaded Tokio runtime, and each of
+//! its methods drives the corresponding asynchronous API call to completion on that runtime. The
+//! methods mirror the functions of the `kv` module that return a single result, and the
+//! `health`, `wait_healthy`, `versions`, and `shutdown` methods of `Client`, taking the same
+//! arguments apart from the client, and returning the same results.
+//!
+//! The functions that return streams, which are `kv::get_many`, `kv::keep_alive`, `kv::walk`,
+//! and `kv::watch_stream`, are not mirrored. For those, and for the other API modules, use the
+//! asynchronous client returned by `blocking::Client::inner`.
+//!
+//! # Panics
+//!
+//! The methods panic if they are called from within an asynchronous context, such as a task
+//! running on a Tokio runtime, since blocking there would stall the runtime. Dropping a client in
+//! such a context is fine: if it is the last clone, its runtime is shut down without waiting for
+//! its remaining background work.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use etcd::blocking::Client;
+//! use etcd::kv::GetOptions;
+//!
+//! let client = Client::new(&["http://etcd.example.com:2379"]);
+//!
+//! client.set("/foo", "bar", None).unwrap();
+//! let response = client.get("/foo", GetOptions::default()).unwrap();
+//! assert_eq!(response.data.node.value, Some("bar".to_string()));
+//! ```
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Serialize as SerializeValue};
+use tokio::runtime::{Builder, Handle, Runtime};
+
+use crate::client::{Health, Response};
+use crate::error::Error;
+use crate::kv::{
+    self, CompareAndDeleteOptions, CompareAndSwapOptions, DeleteOptions, ExportEntry, GetOptions,
+    ImportOptions, ImportSummary, KeyValueInfo, ListKeysOptions, WatchError, WatchOptions,
+};
+use crate::version::VersionInfo;
+
+type EtcdKeyValueResult<E = Vec<Error>> = Result<Response<KeyValueInfo>, E>;
+
+/// A blocking API client for etcd.
+///
+/// Cloning a blocking client is cheap, and clones share both the asynchronous client and the
+/// runtime.
+#[derive(Clone, Debug)]
+pub struct Client {
+    client: crate::Client,
+    runtime: Arc<OwnedRuntime>,
+}
+
+/// The runtime of a blocking client, which is shut down when the last clone of the client is
+/// dropped.
+#[derive(Debug)]
+struct OwnedRuntime(Option<Runtime>);
+
+impl Drop for OwnedRuntime {
+    fn drop(&mut self) {
+        let runtime = match self.0.take() {
+            Some(runtime) => runtime,
+            None => return,
+        };
+
+        // Dropping a runtime waits for its blocking tasks, which panics in an asynchronous
+        // context, so there it is shut down without waiting instead.
+        if Handle::try_current().is_ok() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl Client {
+    /// Constructs a new blocking client using the HTTP protocol. For more advanced configuration,
+    /// build a `Client` with [`crate::ClientBuilder`] and convert it with `From`.
+    ///
+    /// # Parameters
+    ///
+    /// * endpoints: URLs for one or more cluster members.
+    ///
+    /// # Errors
+    ///
+    /// Panics if no endpoints are provided, if any of the endpoints is an invalid URL, or if the
+    /// runtime could not be created.
+    pub fn new(endpoints: &[&str]) -> Self {
+        Client::from(crate::Client::new(endpoints))
+    }
+
+    /// Returns the asynchronous client that API calls are made with.
+    pub fn inner(&self) -> &crate::Client {
+        &self.client
+    }
+
+    /// Runs a future to completion on the client's runtime.
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future,
+    {
+        self.runtime
+            .0
+            .as_ref()
+            .expect("invariant: runtime used after shutdown")
+            .block_on(future)
+    }
+
+    /// Runs a basic health check against each etcd member. See `Client::health`.
+    pub fn health(&self) -> Vec<Result<Response<Health>, Error>> {
+        self.block_on(self.client.health())
+    }
+
+    /// Waits until at least one etcd member reports that it is healthy. See
+    /// `Client::wait_healthy`.
+    pub fn wait_healthy(&self, timeout: Duration, poll_interval: Duration) -> Result<(), Error> {
+        self.block_on(self.client.wait_healthy(timeout, poll_interval))
+    }
+
+    /// Returns version information from each etcd cluster member. See `Client::versions`.
+    pub fn versions(&self) -> Vec<Result<Response<VersionInfo>, Error>> {
+        self.block_on(self.client.versions())
+    }
+
+    /// Shuts the client down, waiting for the requests in flight to complete. See
+    /// `Client::shutdown`.
+    pub fn shutdown(&self) {
+        self.block_on(self.client.shutdown())
+    }
+
+    /// Removes the TTL of a node, so that it no longer expires, while keeping its value. See
+    /// `kv::clear_ttl`.
+    pub fn clear_ttl<K>(&self, key: K) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::clear_ttl(&self.client, key))
+    }
+
+    /// Deletes a node only if the given current value and/or current modified index match. See
+    /// `kv::compare_and_delete`.
+    pub fn compare_and_delete<K>(
+        &self,
+        key: K,
+        options: CompareAndDeleteOptions,
+    ) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::compare_and_delete(&self.client, key, options))
+    }
+
+    /// Updates a node only if the given current value and/or current modified index match. See
+    /// `kv::compare_and_swap`.
+    pub fn compare_and_swap<K, V>(
+        &self,
+        key: K,
+        value: V,
+        ttl: Option<u64>,
+        options: CompareAndSwapOptions,
+    ) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.block_on(kv::compare_and_swap(&self.client, key, value, ttl, options))
+    }
+
+    /// Creates a new key-value pair. See `kv::create`.
+    pub fn create<K, V>(&self, key: K, value: V, ttl: Option<u64>) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.block_on(kv::create(&self.client, key, value, ttl))
+    }
+
+    /// Creates a new empty directory. See `kv::create_dir`.
+    pub fn create_dir<K>(&self, key: K, ttl: Option<u64>) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::create_dir(&self.client, key, ttl))
+    }
+
+    /// Creates a new key-value pair in a directory with a numeric key name larger than any of its
+    /// sibling key-value pairs. See `kv::create_in_order`.
+    pub fn create_in_order<K, V>(&self, key: K, value: V, ttl: Option<u64>) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.block_on(kv::create_in_order(&self.client, key, value, ttl))
+    }
+
+    /// Deletes a node. See `kv::delete`.
+    pub fn delete<K>(&self, key: K, recursive: bool) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::delete(&self.client, key, recursive))
+    }
+
+    /// Deletes a directory. See `kv::delete_dir`.
+    pub fn delete_dir<K>(&self, key: K, recursive: bool) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::delete_dir(&self.client, key, recursive))
+    }
+
+    /// Deletes a directory and everything beneath it, returning the number of key-value pairs that
+    /// were removed. See `kv::delete_prefix`.
+    pub fn delete_prefix<K>(&self, prefix: K) -> Result<u64, Vec<Error>>
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::delete_prefix(&self.client, prefix))
+    }
+
+    /// Deletes a node, with fine control over the kind of node and the conditions for deleting
+    /// it. See `kv::delete_with_options`.
+    pub fn delete_with_options<K>(&self, key: K, options: DeleteOptions) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::delete_with_options(&self.client, key, options))
+    }
+
+    /// Exports every node at and beneath `root`. See `kv::export`.
+    pub fn export<K>(&self, root: K) -> Result<Vec<ExportEntry>, Vec<Error>>
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::export(&self.client, root))
+    }
+
+    /// Gets the value of a node. See `kv::get`.
+    pub fn get<K>(&self, key: K, options: GetOptions) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::get(&self.client, key, options))
+    }
+
+    /// Gets the bytes stored in a key-value pair by `set_bytes`. See `kv::get_bytes`.
+    pub fn get_bytes<K>(&self, key: K) -> Result<Vec<u8>, Vec<Error>>
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::get_bytes(&self.client, key))
+    }
+
+    /// Gets the contents of a directory created with `create_in_order`, in creation order. See
+    /// `kv::get_in_order`.
+    pub fn get_in_order<K>(&self, key: K) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::get_in_order(&self.client, key))
+    }
+
+    /// Gets the value of a key-value pair, creating it with `value` if it does not exist. See
+    /// `kv::get_or_create`.
+    pub fn get_or_create<K, V>(
+        &self,
+        key: K,
+        value: V,
+        ttl: Option<u64>,
+    ) -> Result<(String, bool), Vec<Error>>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.block_on(kv::get_or_create(&self.client, key, value, ttl))
+    }
+
+    /// Gets the value of a key-value pair and deserializes it from JSON. See `kv::get_typed`.
+    pub fn get_typed<T, K>(&self, key: K, options: GetOptions) -> Result<Response<T>, Vec<Error>>
+    where
+        T: DeserializeOwned,
+        K: AsRef<str>,
+    {
+        self.block_on(kv::get_typed(&self.client, key, options))
+    }
+
+    /// Imports nodes exported by `export`. See `kv::import`.
+    pub fn import<I>(&self, entries: I, options: ImportOptions) -> ImportSummary
+    where
+        I: IntoIterator<Item = ExportEntry>,
+    {
+        self.block_on(kv::import(&self.client, entries, options))
+    }
+
+    /// Atomically adds `delta` to the integer stored in a key, returning the new value. See
+    /// `kv::increment`.
+    pub fn increment<K>(&self, key: K, delta: i64) -> Result<i64, Vec<Error>>
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::increment(&self.client, key, delta))
+    }
+
+    /// Lists the keys of the nodes beneath a directory, without their values. See
+    /// `kv::list_keys`.
+    pub fn list_keys<K>(&self, dir: K, options: ListKeysOptions) -> Result<Vec<String>, Vec<Error>>
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::list_keys(&self.client, dir, options))
+    }
+
+    /// Refreshes the already set etcd key, bumping its TTL without triggering watcher updates.
+    /// See `kv::refresh`.
+    pub fn refresh<K>(&self, key: K, ttl: u64) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::refresh(&self.client, key, ttl))
+    }
+
+    /// Moves a key-value pair to a new key, keeping its value and remaining TTL. See `kv::rename`.
+    pub fn rename<F, T>(&self, from: F, to: T) -> Result<(), Vec<Error>>
+    where
+        F: AsRef<str>,
+        T: AsRef<str>,
+    {
+        self.block_on(kv::rename(&self.client, from, to))
+    }
+
+    /// Sets the value of a key-value pair. See `kv::set`.
+    pub fn set<K, V>(&self, key: K, value: V, ttl: Option<u64>) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.block_on(kv::set(&self.client, key, value, ttl))
+    }
+
+    /// Sets the value of a key-value pair to arbitrary bytes. See `kv::set_bytes`.
+    pub fn set_bytes<K>(&self, key: K, value: &[u8], ttl: Option<u64>) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::set_bytes(&self.client, key, value, ttl))
+    }
+
+    /// Sets the key to an empty directory. See `kv::set_dir`.
+    pub fn set_dir<K>(&self, key: K, ttl: Option<u64>) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::set_dir(&self.client, key, ttl))
+    }
+
+    /// Sets the value of a key-value pair to `value` serialized as JSON. See `kv::set_typed`.
+    pub fn set_typed<T, K>(&self, key: K, value: &T, ttl: Option<u64>) -> EtcdKeyValueResult
+    where
+        T: SerializeValue + ?Sized,
+        K: AsRef<str>,
+    {
+        self.block_on(kv::set_typed(&self.client, key, value, ttl))
+    }
+
+    /// Updates an existing key-value pair. See `kv::update`.
+    pub fn update<K, V>(&self, key: K, value: V, ttl: Option<u64>) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        self.block_on(kv::update(&self.client, key, value, ttl))
+    }
+
+    /// Updates a directory. See `kv::update_dir`.
+    pub fn update_dir<K>(&self, key: K, ttl: Option<u64>) -> EtcdKeyValueResult
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::update_dir(&self.client, key, ttl))
+    }
+
+    /// Waits until a node is deleted or expires. See `kv::wait_delete`.
+    pub fn wait_delete<K>(&self, key: K, timeout: Option<Duration>) -> Result<(), WatchError>
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::wait_delete(&self.client, key, timeout))
+    }
+
+    /// Watches a node for changes and returns the new value as soon as a change takes place. See
+    /// `kv::watch`.
+    ///
+    /// This blocks until the change takes place, so a timeout should usually be given in
+    /// `options.timeout`.
+    pub fn watch<K>(&self, key: K, options: WatchOptions) -> EtcdKeyValueResult<WatchError>
+    where
+        K: AsRef<str>,
+    {
+        self.block_on(kv::watch(&self.client, key, options))
+    }
+}
+
+impl From<crate::Client> for Client {
+    /// Wraps an asynchronous client, creating a runtime for it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime could not be created.
+    fn from(client: crate::Client) -> Self {
+        let runtime = Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("invariant: could not create runtime");
+
+        Client {
+            client,
+            runtime: Arc::new(OwnedRuntime(Some(runtime))),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+//! Decompression of gzip and deflate encoded response bodies.
+//!
+//! Only decoding is needed, since request bodies are never compressed. Bodies are decoded with a
+//! limit on their decompressed size, so that a small compressed response cannot exhaust memory.
+
+use std::io::Read;
+
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+
+/// The value of the `Accept-Encoding` header sent with each request.
+pub(crate) const ACCEPT_ENCODING: &str = "gzip, deflate";
+
+/// The largest decompressed response body the client accepts, in bytes, as set with
+/// `ClientBuilder::with_max_decompressed_size`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Limit(pub(crate) usize);
+
+/// Decodes a response body with the given `Content-Encoding`, failing if it decompresses to more
+/// than `limit` bytes.
+///
+/// Returns `None` if the encoding is not supported, in which case the body is used as is.
+pub(crate) fn decode(encoding: &str, body: &[u8], limit: usize) -> Option<Result<Vec<u8>, String>> {
+    let decoder: Box<dyn Read + '_> = match encoding.trim().to_ascii_lowercase().as_str() {
+        "gzip" | "x-gzip" => Box::new(MultiGzDecoder::new(body)),
+        // Some servers send raw DEFLATE data instead of the zlib format the coding calls for.
+        "deflate" if has_zlib_header(body) => Box::new(ZlibDecoder::new(body)),
+        "deflate" => Box::new(DeflateDecoder::new(body)),
+        _ => return None,
+    };
+
+    let result = read_limited(decoder, limit)
+        .map_err(|message| format!("invalid {} body: {}", encoding.trim(), message));
+
+    Some(result)
+}
+
+/// Reads a decoder to the end, failing if it yields more than `limit` bytes.
+fn read_limited<R>(decoder: R, limit: usize) -> Result<Vec<u8>, String>
+where
+    R: Read,
+{
+    let mut output = Vec::new();
+    let mut reader = decoder.take((limit as u64).saturating_add(1));
+
+    reader
+        .read_to_end(&mut output)
+        .map_err(|error| error.to_string())?;
+
+    if output.len() > limit {
+        return Err(format!(
+            "the decompressed body is larger than {} bytes",
+            limit
+        ));
+    }
+
+    Ok(output)
+}
+
+/// Returns whether a deflate body starts with a zlib header.
+fn has_zlib_header(body: &[u8]) -> bool {
+    body.len() >= 2
+        && body[0] & 0x0f == 8
+        && body[1] & 0x20 == 0
+        && (u16::from(body[0]) << 8 | u16::from(body[1])) % 31 == 0
+}
@@ -0,0 +1,173 @@
+//! Discovery of cluster members via DNS SRV records.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use hickory_resolver::{
+    config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+    error::{ResolveError, ResolveErrorKind},
+    system_conf, TokioAsyncResolver,
+};
+use http::Uri;
+use rand::{thread_rng, Rng};
+
+/// The SRV service names queried for cluster members, with the scheme each one is served over.
+const SERVICES: [(&str, &str); 2] = [
+    ("_etcd-client-ssl._tcp", "https"),
+    ("_etcd-client._tcp", "http"),
+];
+
+/// How long to wait for a DNS server to respond to a query.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A single SRV record.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct SrvRecord {
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: String,
+}
+
+/// Returns the first nameserver configured in `/etc/resolv.conf`, falling back to a local
+/// nameserver.
+pub(crate) fn system_nameserver() -> SocketAddr {
+    system_conf::read_system_conf()
+        .ok()
+        .and_then(|(config, _)| {
+            config
+                .name_servers()
+                .first()
+                .map(|name_server| name_server.socket_addr)
+        })
+        .unwrap_or_else(|| SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 53))
+}
+
+/// Looks up the etcd client SRV records for `domain` and returns an endpoint for each target,
+/// ordered by priority and weight.
+///
+/// Both `_etcd-client-ssl._tcp` (HTTPS) and `_etcd-client._tcp` (HTTP) records are looked up. If
+/// only one of the lookups fails, the endpoints from the other are returned. Returns an error
+/// describing the failures if no endpoints were found, or if a record's target and port do not
+/// form a valid endpoint.
+pub(crate) async fn discover_endpoints(
+    domain: &str,
+    nameserver: SocketAddr,
+) -> Result<Vec<Uri>, String> {
+    let domain = domain.trim_end_matches('.');
+    let mut endpoints = Vec::new();
+    let mut failures = Vec::new();
+
+    for &(service, scheme) in SERVICES.iter() {
+        let name = format!("{}.{}", service, domain);
+
+        match lookup_srv(&name, nameserver).await {
+            Ok(records) => {
+                for record in order_records(records) {
+                    let endpoint = format!(
+                        "{}://{}:{}",
+                        scheme,
+                        record.target.trim_end_matches('.'),
+                        record.port
+                    );
+                    let endpoint = endpoint.parse().map_err(|error| {
+                        format!("{}: invalid endpoint {:?}: {}", name, endpoint, error)
+                    })?;
+                    endpoints.push(endpoint);
+                }
+            }
+            Err(error) => failures.push(format!("{}: {}", name, error)),
+        }
+    }
+
+    if !endpoints.is_empty() {
+        Ok(endpoints)
+    } else if failures.is_empty() {
+        Err(format!("no SRV records found for {}", domain))
+    } else {
+        Err(failures.join(", "))
+    }
+}
+
+/// Orders SRV records by ascending priority, and randomly by weight within each priority, as
+/// described in RFC 2782.
+fn order_records(mut records: Vec<SrvRecord>) -> Vec<SrvRecord> {
+    records.sort_by_key(|record| record.priority);
+
+    let mut rng = thread_rng();
+    let mut ordered = Vec::with_capacity(records.len());
+
+    while !records.is_empty() {
+        let priority = records[0].priority;
+        let group_len = records
+            .iter()
+            .take_while(|record| record.priority == priority)
+            .count();
+        let mut group: Vec<SrvRecord> = records.drain(..group_len).collect();
+
+        while !group.is_empty() {
+            let total_weight: u32 = group.iter().map(|record| u32::from(record.weight)).sum();
+            let index = if total_weight == 0 {
+                rng.gen_range(0..group.len())
+            } else {
+                let mut choice = rng.gen_range(0..total_weight);
+                group
+                    .iter()
+                    .position(|record| {
+                        let weight = u32::from(record.weight);
+                        if choice < weight {
+                            true
+                        } else {
+                            choice -= weight;
+                            false
+                        }
+                    })
+                    .expect("invariant: weighted choice out of range")
+            };
+
+            ordered.push(group.remove(index));
+        }
+    }
+
+    ordered
+}
+
+/// Sends an SRV query for `name` to `nameserver` and returns the records in the answer.
+///
+/// The query is sent over UDP, and again over TCP if the response was truncated, as happens when
+/// there are too many records to fit in a datagram. A name that does not exist results in no
+/// records rather than an error, as does a record whose target is `.`, which RFC 2782 uses to
+/// mean that the service is not available.
+async fn lookup_srv(name: &str, nameserver: SocketAddr) -> Result<Vec<SrvRecord>, ResolveError> {
+    let name_servers = vec![
+        NameServerConfig::new(nameserver, Protocol::Udp),
+        NameServerConfig::new(nameserver, Protocol::Tcp),
+    ];
+    let config = ResolverConfig::from_parts(None, Vec::new(), name_servers);
+    let mut options = ResolverOpts::default();
+    options.timeout = QUERY_TIMEOUT;
+    options.use_hosts_file = false;
+    let resolver = TokioAsyncResolver::tokio(config, options);
+
+    // The trailing dot keeps the name from being looked up relative to a search domain.
+    let lookup = match resolver.srv_lookup(format!("{}.", name)).await {
+        Ok(lookup) => lookup,
+        Err(ref error) if matches!(error.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+            return Ok(Vec::new())
+        }
+        Err(error) => return Err(error),
+    };
+
+    Ok(lookup
+        .iter()
+        .filter(|record| !record.target().is_root())
+        .map(|record| SrvRecord {
+            priority: record.priority(),
+            weight: record.weight(),
+            port: record.port(),
+            target: record.target().to_ascii(),
+        })
+        .collect())
+}
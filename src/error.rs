@@ -4,12 +4,16 @@ use std::convert::From;
 use std::error::Error as StdError;
 use std::fmt::{Display, Error as FmtError, Formatter};
 
-use http::{uri::InvalidUri, StatusCode};
+use http::{uri::InvalidUri, StatusCode, Uri};
 use serde_derive::{Deserialize, Serialize};
 use serde_json::Error as SerializationError;
 use tokio::time::error::Elapsed;
 use url::ParseError as UrlError;
 
+use crate::client::ClusterInfo;
+use crate::v3::Status;
+use crate::version::Version;
+
 /// An error returned by an etcd API endpoint.
 ///
 /// This is a logical error, as opposed to other types of errors that may occur when using this
@@ -18,10 +22,19 @@ use url::ParseError as UrlError;
 pub struct ApiError {
     /// The key that was being operated upon or reason for the failure.
     pub cause: Option<String>,
+    /// Information about the state of the cluster from the error response's HTTP headers.
+    ///
+    /// Its `etcd_index` is the cluster's current index, which can be used to resynchronize a
+    /// watch whose index has been cleared from etcd's event history.
+    #[serde(skip)]
+    pub cluster_info: Option<Box<ClusterInfo>>,
     /// The etcd error code.
     #[serde(rename = "errorCode")]
     pub error_code: u64,
-    /// The etcd index.
+    /// The etcd index at the time of the error.
+    ///
+    /// etcd includes it in every error. After a failed compare-and-swap, for example, watching
+    /// the key from `index + 1` waits for the next change to it before trying again.
     pub index: u64,
     /// A human-friendly description of the error.
     pub message: String,
@@ -39,43 +52,310 @@ impl StdError for ApiError {
     }
 }
 
+/// The etcd error code returned when a key does not exist.
+const KEY_NOT_FOUND: u64 = 100;
+/// The etcd error code returned when the conditions of a compare-and-swap or compare-and-delete
+/// operation did not match.
+const COMPARE_FAILED: u64 = 101;
+/// The etcd error code returned when a key-value operation targets a directory.
+pub(crate) const NOT_A_FILE: u64 = 102;
+/// The etcd error code returned when a directory operation targets a key-value pair.
+pub(crate) const NOT_A_DIRECTORY: u64 = 104;
+/// The etcd error code returned when creating a key that already exists.
+const KEY_ALREADY_EXISTS: u64 = 105;
+/// The etcd error code returned when deleting a directory that still has children.
+const DIRECTORY_NOT_EMPTY: u64 = 108;
+/// The etcd error code returned when an internal Raft error occurs.
+const RAFT_INTERNAL: u64 = 300;
+/// The etcd error code returned during a leader election.
+const LEADER_ELECT: u64 = 301;
+
 /// An error returned when an operation fails for some reaosn.
 #[derive(Debug)]
 pub enum Error {
     /// An error returned by an etcd API endpoint.
     Api(ApiError),
-    /// An error at the HTTP protocol layer.
+    /// An error returned when a request is made after `Client::shutdown` was called on the client
+    /// or one of its clones.
+    ClientClosed,
+    /// An error returned when a response came from a different etcd cluster than the one the
+    /// client is pinned to. See `ClientBuilder::with_pinned_cluster_id`.
+    ClusterIdMismatch {
+        /// The ID of the cluster the client is pinned to.
+        expected: String,
+        /// The ID of the cluster that sent the response.
+        actual: String,
+    },
+    /// An error returned by `v3::watch::watch` when the revision to watch from has been compacted,
+    /// with the oldest revision that can still be watched.
+    Compacted(i64),
+    /// An error returned by an etcd API endpoint when the conditions of a compare-and-swap or
+    /// compare-and-delete operation did not match the current state of the node.
+    CompareFailed(ApiError),
+    /// An error returned when building a client configured with both basic authentication and a
+    /// bearer token.
+    ConflictingAuth,
+    /// An error returned when a connection to an etcd cluster member could not be established, so
+    /// the request was never sent.
+    ///
+    /// API calls fail with one error for each endpoint they were tried on, so if no endpoint could
+    /// be reached, all of them are of this variant, each naming the endpoint it came from.
+    Connection {
+        /// The endpoint that could not be reached.
+        endpoint: Uri,
+        /// The underlying error, describing why the connection failed.
+        source: reqwest::Error,
+    },
+    /// An error returned when a compressed response body could not be decompressed, describing
+    /// why. Only returned with the `compression` feature.
+    Decompression(String),
+    /// An error returned by an etcd API endpoint when deleting a directory that is not empty
+    /// without deleting recursively.
+    DirectoryNotEmpty(ApiError),
+    /// An error returned when no cluster members could be discovered, describing why.
+    Discovery(String),
+    /// An error at the HTTP protocol layer, after a connection to an etcd cluster member was
+    /// established.
     Http(reqwest::Error),
+    /// An error returned when building a client with a bearer token that contains characters
+    /// that cannot be sent in an HTTP header.
+    InvalidBearerToken,
     /// An error returned when invalid conditions have been provided for a compare-and-delete or
     /// compare-and-swap operation.
     InvalidConditions,
+    /// An error returned by the members API when the given peer URLs are invalid, with etcd's
+    /// error message.
+    InvalidPeerUrls(String),
+    /// An error returned when building a client with a proxy URI that cannot be used, such as one
+    /// with an unsupported scheme.
+    InvalidProxy(reqwest::Error),
     /// An error returned when an etcd cluster member's endpoint is not a valid URI.
     InvalidUri(InvalidUri),
     /// An error returned when the URL for a specific API endpoint cannot be generated.
     InvalidUrl(UrlError),
+    /// An error returned when an etcd version number could not be parsed, with the version.
+    InvalidVersion(String),
+    /// An error returned by an etcd API endpoint when creating a key that already exists.
+    KeyAlreadyExists(ApiError),
+    /// An error returned by an etcd API endpoint when the key does not exist.
+    KeyNotFound(ApiError),
+    /// An error returned by the members API when the member does not exist, with etcd's error
+    /// message.
+    MemberNotFound(String),
     /// An error returned when attempting to create a client without at least one member endpoint.
     NoEndpoints,
+    /// An error returned by an etcd API endpoint, or by `kv::delete_dir`, when a directory
+    /// operation targets a key-value pair.
+    NotADirectory(ApiError),
+    /// An error returned by an etcd API endpoint when a key-value operation targets a directory.
+    NotAFile(ApiError),
+    /// An error returned by `kv::increment` when the current value of the key is not an integer,
+    /// with the value.
+    NotAnInteger(String),
+    /// An error returned by `kv::get_bytes` when the value was not written by `kv::set_bytes`, with
+    /// the value.
+    NotBinary(String),
+    /// An error returned by the members API when the given peer URLs are already used by another
+    /// member, with etcd's error message.
+    PeerUrlsExist(String),
+    /// An error returned by the auth API when creating a role that already exists, with etcd's
+    /// error message.
+    RoleAlreadyExists(String),
+    /// An error returned by the auth API when the role does not exist, with etcd's error message.
+    RoleNotFound(String),
+    /// An error returned by the auth API when enabling or disabling auth requires the root user,
+    /// either because no root user exists yet or because the client is not authenticated as it,
+    /// with etcd's error message.
+    RootUserRequired(String),
     /// An error returned when attempting to deserializing invalid JSON.
-    Serialization(SerializationError),
+    Serialization {
+        /// The underlying JSON error.
+        source: SerializationError,
+        /// The raw body of the response that could not be deserialized, to help diagnose
+        /// differences between etcd's responses and this crate's types.
+        ///
+        /// For `kv::get_typed`, this is the value of the key instead. This is empty if the error
+        /// occurred while serializing a request.
+        body: String,
+    },
+    /// An error returned when a request did not complete within the configured timeout.
+    Timeout,
+    /// An error returned when the client's TLS configuration is invalid, such as malformed PEM
+    /// material or a minimum version the TLS backend does not support, or when a TLS handshake
+    /// with an etcd member fails, such as because the member does not support the client's minimum
+    /// TLS version.
+    Tls(reqwest::Error),
+    /// An error returned when a request was redirected more times than the client allows.
+    TooManyRedirects,
     /// An error returned when an unexpected HTTP status code is returned by the server.
     UnexpectedStatus(StatusCode),
+    /// An error returned by `Client::wait_healthy` when a cluster member reported that it is not
+    /// healthy, with the endpoint of the member.
+    Unhealthy(Uri),
+    /// An error returned by `Client::health_endpoint` when the endpoint is not one of the client's
+    /// endpoints, with the endpoint.
+    UnknownEndpoint(Uri),
+    /// An error returned by the auth API when the user does not exist, with etcd's error message.
+    UserNotFound(String),
+    /// An error returned by an etcd v3 API endpoint, with its gRPC status.
+    V3(Status),
+    /// An error returned when writing a value larger than the client allows, before the request is
+    /// sent. See `ClientBuilder::with_max_value_size`.
+    ValueTooLarge {
+        /// The size of the value in bytes.
+        size: usize,
+        /// The size of the largest value the client allows.
+        limit: usize,
+    },
+    /// An error returned by `Client::require_min_version` when the cluster runs an older version
+    /// of etcd than required.
+    VersionTooOld {
+        /// The version that was found, either of a member's server or of the whole cluster.
+        found: Version,
+        /// The minimum version that was required.
+        required: Version,
+    },
+}
+
+impl Error {
+    /// Returns the error returned by the etcd API endpoint, if this error originated from one.
+    ///
+    /// This includes the typed variants for well known etcd error codes, such as
+    /// `Error::KeyNotFound`, and allows inspecting the error code, cause, and index.
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match *self {
+            Error::Api(ref error)
+            | Error::CompareFailed(ref error)
+            | Error::DirectoryNotEmpty(ref error)
+            | Error::KeyAlreadyExists(ref error)
+            | Error::KeyNotFound(ref error)
+            | Error::NotADirectory(ref error)
+            | Error::NotAFile(ref error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the operation that failed with this error may succeed if it is tried
+    /// again, such as in a caller's own retry loop.
+    ///
+    /// This is the classification the client uses for requests that only read data when it was
+    /// built with a `RetryPolicy`:
+    ///
+    /// * `Error::Connection`, `Error::Http`, and `Error::Timeout` are retryable, since the cluster
+    ///   member may be reachable again.
+    /// * `Error::Api` is retryable if its error code is 300 (an internal Raft error) or 301 (a
+    ///   leader election is in progress).
+    /// * `Error::UnexpectedStatus` is retryable if the status is a server error, such as 503.
+    /// * Every other variant is not retryable, including `Error::CompareFailed` and
+    ///   `Error::KeyNotFound`, since trying again gives the same result until the data or the
+    ///   request changes.
+    ///
+    /// Only `Error::Connection` guarantees that the request was never sent. A write that failed
+    /// with another retryable error may already have been applied, so retrying it is only safe if
+    /// applying it twice is harmless.
+    pub fn is_retryable(&self) -> bool {
+        match *self {
+            Error::Connection { .. } | Error::Http(_) | Error::Timeout => true,
+            Error::Api(ref error) => {
+                error.error_code == RAFT_INTERNAL || error.error_code == LEADER_ELECT
+            }
+            Error::UnexpectedStatus(status) => status.is_server_error(),
+            _ => false,
+        }
+    }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
         match *self {
             Error::Api(ref error) => write!(f, "{}", error),
+            Error::ClientClosed => write!(f, "the client has been shut down"),
+            Error::ClusterIdMismatch {
+                ref expected,
+                ref actual,
+            } => write!(
+                f,
+                "expected a response from etcd cluster {}, but got one from cluster {}",
+                expected, actual
+            ),
+            Error::Compacted(revision) => write!(
+                f,
+                "the revision has been compacted, the oldest available revision is {}",
+                revision
+            ),
+            Error::CompareFailed(ref error) => write!(f, "{}", error),
+            Error::ConflictingAuth => write!(
+                f,
+                "basic authentication and a bearer token cannot both be configured"
+            ),
+            Error::Connection {
+                ref endpoint,
+                ref source,
+            } => write!(f, "could not connect to {}: {}", endpoint, source),
+            Error::Decompression(ref reason) => {
+                write!(f, "could not decompress the response body: {}", reason)
+            }
+            Error::DirectoryNotEmpty(ref error) => write!(f, "{}", error),
+            Error::Discovery(ref reason) => {
+                write!(f, "could not discover cluster members: {}", reason)
+            }
             Error::Http(ref error) => write!(f, "{}", error),
-            ref error @ Error::InvalidConditions => write!(f, "{}", error.to_string()),
+            Error::InvalidBearerToken => {
+                write!(f, "the bearer token is not a valid HTTP header value")
+            }
+            Error::InvalidConditions => write!(f, "current value or modified index is required"),
+            Error::InvalidPeerUrls(ref message) => write!(f, "{}", message),
+            Error::InvalidProxy(ref error) => write!(f, "invalid proxy: {}", error),
             Error::InvalidUri(ref error) => write!(f, "{}", error),
             Error::InvalidUrl(ref error) => write!(f, "{}", error),
-            ref error @ Error::NoEndpoints => write!(f, "{}", error.to_string()),
-            Error::Serialization(ref error) => write!(f, "{}", error),
+            Error::InvalidVersion(ref version) => write!(f, "invalid etcd version: {}", version),
+            Error::KeyAlreadyExists(ref error) => write!(f, "{}", error),
+            Error::KeyNotFound(ref error) => write!(f, "{}", error),
+            Error::MemberNotFound(ref message) => write!(f, "{}", message),
+            Error::NoEndpoints => write!(f, "at least one endpoint is required to create a Client"),
+            Error::NotADirectory(ref error) => write!(f, "{}", error),
+            Error::NotAFile(ref error) => write!(f, "{}", error),
+            Error::NotAnInteger(ref value) => write!(f, "the value is not an integer: {}", value),
+            Error::NotBinary(ref value) => {
+                write!(f, "the value is not base64-encoded bytes: {}", value)
+            }
+            Error::PeerUrlsExist(ref message) => write!(f, "{}", message),
+            Error::RoleAlreadyExists(ref message) => write!(f, "{}", message),
+            Error::RoleNotFound(ref message) => write!(f, "{}", message),
+            Error::RootUserRequired(ref message) => write!(f, "{}", message),
+            Error::Serialization { ref source, .. } => write!(f, "{}", source),
+            Error::Timeout => write!(f, "the request timed out"),
+            Error::Tls(ref error) => match tls_error(error) {
+                Some(reason) => write!(f, "{}: {}", error, reason),
+                None => write!(f, "{}", error),
+            },
+            Error::TooManyRedirects => write!(f, "the request was redirected too many times"),
             Error::UnexpectedStatus(ref status) => write!(
                 f,
                 "the etcd server returned an unexpected HTTP status code: {}",
                 status
             ),
+            Error::Unhealthy(ref endpoint) => {
+                write!(f, "the etcd cluster member at {} is not healthy", endpoint)
+            }
+            Error::UnknownEndpoint(ref endpoint) => {
+                write!(f, "{} is not one of the client's endpoints", endpoint)
+            }
+            Error::UserNotFound(ref message) => write!(f, "{}", message),
+            Error::V3(ref status) => write!(f, "{}", status),
+            Error::ValueTooLarge { size, limit } => write!(
+                f,
+                "the value is {} bytes, but at most {} bytes are allowed",
+                size, limit
+            ),
+            Error::VersionTooOld {
+                ref found,
+                ref required,
+            } => write!(
+                f,
+                "etcd {} or later is required, but found {}",
+                required, found
+            ),
         }
     }
 }
@@ -84,21 +364,108 @@ impl StdError for Error {
     fn description(&self) -> &str {
         match *self {
             Error::Api(_) => "the etcd server returned an error",
+            Error::ClientClosed => "the client has been shut down",
+            Error::ClusterIdMismatch { .. } => "the response came from a different etcd cluster",
+            Error::Compacted(_) => "the revision has been compacted",
+            Error::CompareFailed(_) => "the conditions of the operation did not match",
+            Error::ConflictingAuth => {
+                "basic authentication and a bearer token cannot both be configured"
+            }
+            Error::Connection { .. } => "could not connect to an etcd cluster member",
+            Error::Decompression(_) => "the response body could not be decompressed",
+            Error::DirectoryNotEmpty(_) => "the directory is not empty",
+            Error::Discovery(_) => "no cluster members could be discovered",
             Error::Http(_) => "an error occurred during the HTTP request",
+            Error::InvalidBearerToken => "the bearer token is not a valid HTTP header value",
             Error::InvalidConditions => "current value or modified index is required",
+            Error::InvalidPeerUrls(_) => "the peer URLs are invalid",
+            Error::InvalidProxy(_) => "the proxy URI cannot be used",
             Error::InvalidUri(_) => "a supplied endpoint could not be parsed as a URI",
             Error::InvalidUrl(_) => "a URL for the request could not be generated",
+            Error::InvalidVersion(_) => "an etcd version could not be parsed",
+            Error::KeyAlreadyExists(_) => "the key already exists",
+            Error::KeyNotFound(_) => "the key does not exist",
+            Error::MemberNotFound(_) => "the member does not exist",
             Error::NoEndpoints => "at least one endpoint is required to create a Client",
-            Error::Serialization(_) => "an error occurred deserializing JSON",
+            Error::NotADirectory(_) => "the operation requires a directory, but the node is a key",
+            Error::NotAFile(_) => "the operation requires a key, but the node is a directory",
+            Error::NotAnInteger(_) => "the value is not an integer",
+            Error::NotBinary(_) => "the value is not base64-encoded bytes",
+            Error::PeerUrlsExist(_) => "the peer URLs are already used by another member",
+            Error::RoleAlreadyExists(_) => "the role already exists",
+            Error::RoleNotFound(_) => "the role does not exist",
+            Error::RootUserRequired(_) => "the root user is required to change the auth system",
+            Error::Serialization { .. } => "an error occurred deserializing JSON",
+            Error::Timeout => "the request timed out",
+            Error::Tls(_) => "TLS could not be configured or established",
+            Error::TooManyRedirects => "the request was redirected too many times",
             Error::UnexpectedStatus(_) => "the etcd server returned an unexpected HTTP status code",
+            Error::Unhealthy(_) => "the etcd cluster member is not healthy",
+            Error::UnknownEndpoint(_) => "the endpoint is not one of the client's endpoints",
+            Error::UserNotFound(_) => "the user does not exist",
+            Error::V3(_) => "the etcd server returned an error",
+            Error::ValueTooLarge { .. } => "the value is larger than allowed",
+            Error::VersionTooOld { .. } => "the etcd cluster is older than required",
+        }
+    }
+}
+
+impl From<ApiError> for Error {
+    fn from(error: ApiError) -> Error {
+        match error.error_code {
+            KEY_NOT_FOUND => Error::KeyNotFound(error),
+            COMPARE_FAILED => Error::CompareFailed(error),
+            NOT_A_FILE => Error::NotAFile(error),
+            NOT_A_DIRECTORY => Error::NotADirectory(error),
+            KEY_ALREADY_EXISTS => Error::KeyAlreadyExists(error),
+            DIRECTORY_NOT_EMPTY => Error::DirectoryNotEmpty(error),
+            _ => Error::Api(error),
         }
     }
 }
 
 impl From<reqwest::Error> for Error {
     fn from(error: reqwest::Error) -> Error {
-        Error::Http(error)
+        if error.is_timeout() {
+            Error::Timeout
+        } else if error.is_redirect() {
+            Error::TooManyRedirects
+        } else if tls_error(&error).is_some() {
+            Error::Tls(error)
+        } else if error.is_connect() {
+            match error
+                .url()
+                .and_then(|url| url.origin().ascii_serialization().parse().ok())
+            {
+                Some(endpoint) => Error::Connection {
+                    endpoint,
+                    source: error,
+                },
+                None => Error::Http(error),
+            }
+        } else {
+            Error::Http(error)
+        }
+    }
+}
+
+/// Returns the error from the TLS backend that caused an HTTP error, if any.
+fn tls_error(error: &reqwest::Error) -> Option<&(dyn StdError + 'static)> {
+    #[cfg(feature = "tls")]
+    {
+        let mut source = error.source();
+        while let Some(error) = source {
+            if error.is::<native_tls::Error>() {
+                return Some(error);
+            }
+            source = error.source();
+        }
     }
+
+    #[cfg(not(feature = "tls"))]
+    let _ = error;
+
+    None
 }
 
 impl From<UrlError> for Error {
@@ -109,7 +476,10 @@ impl From<UrlError> for Error {
 
 impl From<SerializationError> for Error {
     fn from(error: SerializationError) -> Error {
-        Error::Serialization(error)
+        Error::Serialization {
+            source: error,
+            body: String::new(),
+        }
     }
 }
 
@@ -137,8 +507,11 @@ impl From<Elapsed> for WatchError {
 impl Display for WatchError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
         match *self {
-            WatchError::Timeout => write!(f, "{}", self.to_string()),
-            ref other => other.fmt(f),
+            WatchError::Other(ref errors) => match errors.first() {
+                Some(error) => write!(f, "{}", error),
+                None => write!(f, "the watch request failed"),
+            },
+            WatchError::Timeout => write!(f, "operation timed out"),
         }
     }
 }
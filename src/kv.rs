@@ -4,22 +4,44 @@
 //! of key-value pairs. For example, "/foo" is a key if it has a value, but it is a directory if
 //! there other other key-value pairs "underneath" it, such as "/foo/bar".
 
-use std::time::Duration;
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime},
+};
 
+use futures_util::stream::{self, FuturesUnordered, Stream, StreamExt};
 use http::{StatusCode, Uri};
+use serde::{de::DeserializeOwned, Serialize as SerializeValue};
 use serde_derive::{Deserialize, Serialize};
-use tokio::time::timeout;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::time::{sleep, timeout};
+use url::Url;
 
 pub use crate::error::WatchError;
 
-use crate::client::{parse_etcd_response, Client, Response};
-use crate::error::Error;
+use crate::client::{parse_etcd_response, parse_json_body, Client, ClusterInfo, Response};
+use crate::error::{ApiError, Error, NOT_A_DIRECTORY, NOT_A_FILE};
 use crate::options::{
-    ComparisonConditions, DeleteOptions, GetOptions as InternalGetOptions, SetOptions,
+    ComparisonConditions, DeleteOptions as InternalDeleteOptions, GetOptions as InternalGetOptions,
+    SetOptions,
 };
 
 type EtcdKeyValueResult<E = Vec<Error>> = Result<Response<KeyValueInfo>, E>;
 
+/// The etcd error code returned when a watch index has been cleared from the event history.
+const EVENT_INDEX_CLEARED: u64 = 401;
+
+/// The maximum number of gets `kv::get_many` has in flight at the same time.
+const MAX_CONCURRENT_GETS: usize = 16;
+
+/// The number of times `kv::increment` tries to write the counter before giving up.
+const MAX_INCREMENT_ATTEMPTS: u32 = 10;
+/// The number of times `kv::get_or_create` tries to create or get the key before giving up.
+const MAX_GET_OR_CREATE_ATTEMPTS: u32 = 10;
+
+/// The prefix that marks a value written by `kv::set_bytes` as base64-encoded bytes.
+const BYTES_PREFIX: &str = "base64:";
+
 /// Information about the result of a successful key-value API operation.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct KeyValueInfo {
@@ -28,39 +50,119 @@ pub struct KeyValueInfo {
     /// The etcd `Node` that was operated upon.
     pub node: Node,
     /// The previous state of the target node.
+    ///
+    /// etcd includes this when the operation replaced or removed an existing node, such as a set,
+    /// update, compare-and-swap, or delete of a key that existed. It is `None` for gets, for
+    /// operations that created the node, and for dry-run writes.
     #[serde(rename = "prevNode")]
     pub prev_node: Option<Node>,
 }
 
+impl KeyValueInfo {
+    /// Returns the modified index of the node that was operated upon: the index of the last change
+    /// to this node.
+    ///
+    /// This is the index to compare against in a compare-and-swap, since it only changes when the
+    /// node does. It is not the cluster-wide `Response::etcd_index`, which changes whenever any
+    /// node changes, so a compare-and-swap on it fails as soon as an unrelated key is written.
+    ///
+    /// # Examples
+    ///
+    /// A compare-and-swap loop that appends to a value, retrying when another client changed the
+    /// key in between:
+    ///
+    /// ```no_run
+    /// use etcd::kv::{self, CompareAndSwapOptions, GetOptions};
+    /// use etcd::{Client, Error};
+    ///
+    /// async fn append(client: &Client, key: &str, suffix: &str) -> Result<(), Vec<Error>> {
+    ///     loop {
+    ///         let response = kv::get(client, key, GetOptions::default()).await?;
+    ///         let value = response.data.node.value.clone().unwrap_or_default();
+    ///         let options = CompareAndSwapOptions {
+    ///             prev_index: response.data.node_modified_index(),
+    ///             ..Default::default()
+    ///         };
+    ///
+    ///         match kv::compare_and_swap(client, key, value + suffix, None, options).await {
+    ///             Ok(_) => return Ok(()),
+    ///             Err(ref errors) if errors.iter().any(|e| matches!(e, Error::CompareFailed(_))) => {}
+    ///             Err(errors) => return Err(errors),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn node_modified_index(&self) -> Option<u64> {
+        self.node.modified_index
+    }
+}
+
 /// The type of action that was taken in response to a key value API request.
 ///
 /// "Node" refers to the key or directory being acted upon.
-#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(from = "String", into = "String")]
 pub enum Action {
     /// Atomic deletion of a node based on previous state.
-    #[serde(rename = "compareAndDelete")]
     CompareAndDelete,
     /// Atomtic update of a node based on previous state.
-    #[serde(rename = "compareAndSwap")]
     CompareAndSwap,
     /// Creation of a node that didn't previously exist.
-    #[serde(rename = "create")]
     Create,
     /// Deletion of a node.
-    #[serde(rename = "delete")]
     Delete,
     /// Expiration of a node.
-    #[serde(rename = "expire")]
     Expire,
     /// Retrieval of a node.
-    #[serde(rename = "get")]
     Get,
     /// Assignment of a node, which may have previously existed.
-    #[serde(rename = "set")]
     Set,
     /// Update of an existing node.
-    #[serde(rename = "update")]
     Update,
+    /// An action this crate does not know about, such as one added in a later version of etcd.
+    Other(String),
+}
+
+impl Action {
+    /// Returns the name etcd uses for the action, such as `compareAndSwap`.
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Action::CompareAndDelete => "compareAndDelete",
+            Action::CompareAndSwap => "compareAndSwap",
+            Action::Create => "create",
+            Action::Delete => "delete",
+            Action::Expire => "expire",
+            Action::Get => "get",
+            Action::Set => "set",
+            Action::Update => "update",
+            Action::Other(ref action) => action,
+        }
+    }
+}
+
+impl From<String> for Action {
+    fn from(action: String) -> Self {
+        match action.as_str() {
+            "compareAndDelete" => Action::CompareAndDelete,
+            "compareAndSwap" => Action::CompareAndSwap,
+            "create" => Action::Create,
+            "delete" => Action::Delete,
+            "expire" => Action::Expire,
+            "get" => Action::Get,
+            "set" => Action::Set,
+            "update" => Action::Update,
+            _ => Action::Other(action),
+        }
+    }
+}
+
+impl From<Action> for String {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::Other(action) => action,
+            action => action.as_str().to_string(),
+        }
+    }
 }
 
 /// An etcd key or directory.
@@ -71,7 +173,8 @@ pub struct Node {
     pub created_index: Option<u64>,
     /// Whether or not the node is a directory.
     pub dir: Option<bool>,
-    /// An ISO 8601 timestamp for when the key will expire.
+    /// An RFC 3339 timestamp for when the key will expire. See `Node::expiration_time` for the
+    /// parsed time.
     pub expiration: Option<String>,
     /// The name of the key.
     pub key: Option<String>,
@@ -80,25 +183,247 @@ pub struct Node {
     pub modified_index: Option<u64>,
     /// Child nodes of a directory.
     pub nodes: Option<Vec<Node>>,
-    /// The key's time to live in seconds.
+    /// The key's remaining time to live in seconds, as of when the response was sent.
     pub ttl: Option<i64>,
     /// The value of the key.
     pub value: Option<String>,
 }
 
+impl Node {
+    /// Returns whether the node is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.dir == Some(true)
+    }
+
+    /// Returns the child nodes of a directory, or an empty slice for a key.
+    ///
+    /// Only the immediate children of a directory are included in a response, unless it was read
+    /// with `GetOptions::recursive`.
+    pub fn children(&self) -> &[Node] {
+        self.nodes.as_deref().unwrap_or_default()
+    }
+
+    /// Returns an iterator over every key below a directory, descending into its subdirectories.
+    ///
+    /// Keys are yielded depth-first, in the order of `children` at each level, and directories
+    /// themselves are not yielded. A key that is not a directory has nothing below it. The node
+    /// should come from a recursive get, since the subdirectories in other responses appear empty.
+    pub fn iter_recursive(&self) -> impl Iterator<Item = &Node> {
+        let mut pending = vec![self.children().iter()];
+
+        std::iter::from_fn(move || {
+            while let Some(children) = pending.last_mut() {
+                match children.next() {
+                    Some(node) if node.is_dir() => pending.push(node.children().iter()),
+                    Some(node) => return Some(node),
+                    None => {
+                        pending.pop();
+                    }
+                }
+            }
+
+            None
+        })
+    }
+
+    /// Returns the time when the key will expire, parsed from `expiration`.
+    ///
+    /// Returns `None` if the key does not expire or if `expiration` is not a valid RFC 3339
+    /// timestamp.
+    pub fn expiration_time(&self) -> Option<SystemTime> {
+        self.expiration.as_deref().and_then(parse_rfc3339)
+    }
+
+    /// Returns how much longer the key will live, or `None` if it does not expire.
+    ///
+    /// This is computed from `expiration` when present, since it does not lose precision and stays
+    /// accurate however long ago the response was received. Otherwise, `ttl` is used, which only
+    /// has a precision of one second and reflects the time the response was sent. A key that has
+    /// already expired has a remaining time to live of zero.
+    pub fn remaining_ttl(&self) -> Option<Duration> {
+        match self.expiration_time() {
+            Some(expiration) => Some(
+                expiration
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default(),
+            ),
+            None => self.ttl.map(|ttl| Duration::from_secs(ttl.max(0) as u64)),
+        }
+    }
+}
+
+/// A node captured by `kv::export`.
+///
+/// Entries can be serialized, such as to JSON, and restored with `kv::import`.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct ExportEntry {
+    /// Whether or not the node is a directory.
+    pub dir: bool,
+    /// An RFC 3339 timestamp for when the node will expire, if it has a TTL.
+    pub expiration: Option<String>,
+    /// The name of the node.
+    pub key: String,
+    /// The node's remaining time to live in seconds as of the export, if it has a TTL.
+    pub ttl: Option<u64>,
+    /// The value of the key, or `None` for a directory.
+    pub value: Option<String>,
+}
+
+impl From<&Node> for ExportEntry {
+    fn from(node: &Node) -> Self {
+        ExportEntry {
+            dir: node.is_dir(),
+            expiration: node.expiration.clone(),
+            key: node.key.clone().unwrap_or_default(),
+            ttl: node.ttl.map(|ttl| ttl.max(0) as u64),
+            value: node.value.clone(),
+        }
+    }
+}
+
+/// The outcome of `kv::import`, listing the name of every node by what happened to it.
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Nodes that were created, or overwritten if `ImportOptions::overwrite` was set.
+    pub created: Vec<String>,
+    /// Nodes that could not be imported, with the errors of each.
+    pub failed: Vec<(String, Vec<Error>)>,
+    /// Nodes that were left as they were because they already existed.
+    pub skipped: Vec<String>,
+}
+
+/// Conditions for `kv::compare_and_delete`.
+///
+/// When both conditions are given, both must match for the operation to succeed.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CompareAndDeleteOptions {
+    /// If given, the node must currently have this value for the operation to succeed.
+    pub prev_value: Option<String>,
+    /// If given, the node must currently be at this modified index for the operation to succeed.
+    ///
+    /// A node that was deleted and created again since it was read has a new modified index, so
+    /// giving the index that was read only deletes the node if it is the same one.
+    pub prev_index: Option<u64>,
+    /// If given, overrides the client's request timeout for this operation.
+    ///
+    /// The timeout covers the entire request, from connecting to reading the response body. A
+    /// write that times out may still have been applied.
+    pub timeout: Option<Duration>,
+}
+
+/// Conditions for `kv::compare_and_swap`.
+///
+/// When both conditions are given, both must match for the operation to succeed.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CompareAndSwapOptions {
+    /// If given, the node must currently have this value for the operation to succeed.
+    pub prev_value: Option<String>,
+    /// If given, the node must currently be at this modified index for the operation to succeed.
+    pub prev_index: Option<u64>,
+    /// If given, overrides the client's request timeout for this operation.
+    ///
+    /// The timeout covers the entire request, from connecting to reading the response body. A
+    /// write that times out may still have been applied.
+    pub timeout: Option<Duration>,
+}
+
+/// Options for customizing the behavior of `kv::delete_with_options`.
+///
+/// When both conditions are given, both must match for the operation to succeed.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DeleteOptions {
+    /// If true and the node is a directory, the directory and all child key-value pairs and
+    /// directories will be deleted as well.
+    ///
+    /// Corresponds to etcd's `recursive` query parameter.
+    pub recursive: bool,
+    /// If true, the node may be an empty directory. Directories can only be deleted with this or
+    /// `recursive` set.
+    ///
+    /// Corresponds to etcd's `dir` query parameter.
+    pub dir: bool,
+    /// If given, the node must currently have this value for the operation to succeed.
+    ///
+    /// Corresponds to etcd's `prevValue` query parameter.
+    pub prev_value: Option<String>,
+    /// If given, the node must currently be at this modified index for the operation to succeed.
+    ///
+    /// Corresponds to etcd's `prevIndex` query parameter.
+    pub prev_index: Option<u64>,
+    /// If given, overrides the client's request timeout for this operation.
+    ///
+    /// The timeout covers the entire request, from connecting to reading the response body. A
+    /// write that times out may still have been applied.
+    pub timeout: Option<Duration>,
+}
+
 /// Options for customizing the behavior of `kv::get`.
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
 pub struct GetOptions {
-    /// If true and the node is a directory, child nodes will be returned as well.
+    /// If true and the node is a directory, child nodes will be returned as well, including the
+    /// contents of child directories at any depth.
+    ///
+    /// Hidden nodes, whose names begin with an underscore, are never included. See `kv::get`.
+    ///
+    /// Corresponds to etcd's `recursive` query parameter.
     pub recursive: bool,
     /// If true and the node is a directory, any child nodes returned will be sorted
-    /// alphabetically.
+    /// alphabetically by key name, at every level of the tree.
+    ///
+    /// Corresponds to etcd's `sorted` query parameter.
     pub sort: bool,
     /// If true, the etcd node serving the response will synchronize with the quorum before
     /// returning the value.
     ///
-    /// This is slower but avoids possibly stale data from being returned.
+    /// This is slower but avoids possibly stale data from being returned. Without it, the value is
+    /// served from the local state of whichever member receives the request, which may lag behind
+    /// the leader. With it, the read is linearizable: it is routed through the leader and reflects
+    /// every write committed before it, which is necessary for read-after-write consistency in
+    /// recipes like leader election. This applies to recursive listings of directories as well
+    /// as to single keys.
+    ///
+    /// Corresponds to etcd's `quorum` query parameter. The v2 API has no other way to request a
+    /// consistent read: the `consistent` query parameter of etcd 0.4 was removed in etcd 2.0, and
+    /// is ignored if sent.
     pub strong_consistency: bool,
+    /// If given, overrides the client's request timeout for this operation.
+    ///
+    /// The timeout covers the entire request, from connecting to reading the response body.
+    pub timeout: Option<Duration>,
+}
+
+/// Options for customizing the behavior of `kv::import`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ImportOptions {
+    /// If true, existing keys are replaced by the imported ones. Otherwise, they are skipped.
+    ///
+    /// Existing directories are always kept, along with any of their contents that are not
+    /// imported, since the imported contents are written into them.
+    pub overwrite: bool,
+    /// If true, nodes that had a TTL when exported are given their remaining TTL as of the export.
+    /// Otherwise, all nodes are imported without a TTL.
+    pub preserve_ttls: bool,
+}
+
+/// Options for customizing the behavior of `kv::list_keys`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ListKeysOptions {
+    /// Whether to include the keys of directories, in addition to those of key-value pairs.
+    pub include_dirs: bool,
+}
+
+/// Options for customizing the behavior of `kv::walk`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct WalkOptions {
+    /// The maximum number of directories to get at the same time. Must be at least one.
+    pub concurrency: usize,
+}
+
+impl Default for WalkOptions {
+    /// Gets up to 4 directories at the same time.
+    fn default() -> Self {
+        WalkOptions { concurrency: 4 }
+    }
 }
 
 /// Options for customizing the behavior of `kv::watch`.
@@ -106,32 +431,93 @@ pub struct GetOptions {
 pub struct WatchOptions {
     /// If given, the watch operation will return the first change at the index or greater,
     /// allowing you to watch for changes that happened in the past.
+    ///
+    /// This is etcd's `waitIndex`. etcd only retains the most recent 1000 change events, so an
+    /// index older than that window fails with an `EventIndexCleared` error, as detected by
+    /// `kv::cleared_index`.
     pub index: Option<u64>,
     /// Whether or not to watch all child keys as well.
+    ///
+    /// If `false`, the watch only returns a change to the exact key being watched, and changes to
+    /// its descendants are ignored. If `true`, a change to the key or any of its descendants, at
+    /// any depth, is returned, and the returned node is the descendant that changed.
     pub recursive: bool,
+    /// Whether to leave the values of the changed node and of its previous state out of the
+    /// result, for callers that only need the key and the action.
+    ///
+    /// The watch asks etcd to leave the values out with its `noValueOnSuccess` query parameter.
+    /// etcd 2 only honors that parameter for writes, so watches still transfer the values, and
+    /// the client removes them from the result instead. The result is the same either way, but
+    /// the bandwidth is only reduced by servers that honor the parameter for watches.
+    pub without_values: bool,
     /// If given, the watch operation will time out if it's still waiting after the duration.
+    ///
+    /// If `None`, the watch polls indefinitely, however long the node goes without changing. In
+    /// either case, the client's request timeout does not apply to watches, so that normal
+    /// requests can stay short while watches long-poll.
     pub timeout: Option<Duration>,
 }
 
+/// Removes the TTL of a node, so that it no longer expires, while keeping its value.
+///
+/// etcd has no operation that only removes a TTL, so the node is read first, and then written back
+/// with the same value and no TTL. Another client may write the node between the read and the
+/// write. To avoid overwriting that change with the old value, the write is a compare-and-swap on
+/// the modified index that was read, which fails if the node has changed in the meantime. A
+/// directory has no value to overwrite, so it is simply updated without a TTL.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the node to make permanent.
+///
+/// # Errors
+///
+/// Fails with `Error::KeyNotFound` if the node does not exist or expires before it is written, or
+/// with `Error::CompareFailed` if another client changed the node after it was read. In the latter
+/// case, the node keeps the other client's value and TTL, and the caller may try again.
+pub async fn clear_ttl<K>(client: &Client, key: K) -> EtcdKeyValueResult
+where
+    K: AsRef<str>,
+{
+    let key = key.as_ref();
+    let node = uncached_get(client, key, GetOptions::default())
+        .await?
+        .data
+        .node;
+
+    if node.is_dir() {
+        return update_dir(client, key, None).await;
+    }
+
+    let options = CompareAndSwapOptions {
+        prev_value: None,
+        prev_index: node.modified_index,
+        ..Default::default()
+    };
+    let value = node.value.unwrap_or_default();
+
+    compare_and_swap(client, key, value, None, options).await
+}
+
 /// Deletes a node only if the given current value and/or current modified index match.
 ///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
 /// * key: The name of the node to delete.
-/// * current_value: If given, the node must currently have this value for the operation to
-/// succeed.
-/// * current_modified_index: If given, the node must currently be at this modified index for the
-/// operation to succeed.
+/// * options: The conditions that must match for the operation to succeed.
 ///
 /// # Errors
 ///
-/// Fails if the conditions didn't match or if no conditions were given.
+/// Fails with `Error::CompareFailed` if the conditions didn't match, or with
+/// `Error::InvalidConditions` if no conditions were given. In the latter case no request is made.
+/// The `index` of the `ApiError` in `Error::CompareFailed` is the cluster's current index, and its
+/// `cause` names the mismatched values, such as `[5 != 7]` for a modified index of 7 instead of 5.
 pub async fn compare_and_delete<K>(
     client: &Client,
     key: K,
-    current_value: Option<&str>,
-    current_modified_index: Option<u64>,
+    options: CompareAndDeleteOptions,
 ) -> EtcdKeyValueResult
 where
     K: AsRef<str>,
@@ -139,11 +525,12 @@ where
     raw_delete(
         client,
         key,
-        DeleteOptions {
+        InternalDeleteOptions {
             conditions: Some(ComparisonConditions {
-                value: current_value,
-                modified_index: current_modified_index,
+                value: options.prev_value.as_deref(),
+                modified_index: options.prev_index,
             }),
+            timeout: options.timeout,
             ..Default::default()
         },
     )
@@ -159,21 +546,18 @@ where
 /// * key: The name of the node to update.
 /// * value: The new value for the node.
 /// * ttl: If given, the node will expire after this many seconds.
-/// * current_value: If given, the node must currently have this value for the operation to
-/// succeed.
-/// * current_modified_index: If given, the node must currently be at this modified index for the
-/// operation to succeed.
+/// * options: The conditions that must match for the operation to succeed.
 ///
 /// # Errors
 ///
-/// Fails if the conditions didn't match or if no conditions were given.
+/// Fails with `Error::CompareFailed` if the conditions didn't match, or with
+/// `Error::InvalidConditions` if no conditions were given. In the latter case no request is made.
 pub async fn compare_and_swap<K, V>(
     client: &Client,
     key: K,
     value: V,
     ttl: Option<u64>,
-    current_value: Option<&str>,
-    current_modified_index: Option<u64>,
+    options: CompareAndSwapOptions,
 ) -> EtcdKeyValueResult
 where
     K: AsRef<str>,
@@ -186,9 +570,10 @@ where
         key,
         SetOptions {
             conditions: Some(ComparisonConditions {
-                value: current_value,
-                modified_index: current_modified_index,
+                value: options.prev_value.as_deref(),
+                modified_index: options.prev_index,
             }),
+            timeout: options.timeout,
             ttl,
             value: Some(value),
             ..Default::default()
@@ -206,9 +591,13 @@ where
 /// * value: The new value for the node.
 /// * ttl: If given, the node will expire after this many seconds.
 ///
+/// This sends `prevExist=false`, so etcd only creates the key if it does not exist yet, atomically.
+/// Use `kv::set` to write a key whether or not it exists.
+///
 /// # Errors
 ///
-/// Fails if the key already exists.
+/// Fails with `Error::KeyAlreadyExists` if the key already exists, or with `Error::ValueTooLarge`
+/// if the value is larger than the client allows.
 pub async fn create<K, V>(client: &Client, key: K, value: V, ttl: Option<u64>) -> EtcdKeyValueResult
 where
     K: AsRef<str>,
@@ -231,6 +620,9 @@ where
 
 /// Creates a new empty directory.
 ///
+/// This is useful as the basis of recipes that rely on directory semantics, such as locks and
+/// queues.
+///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
@@ -239,7 +631,7 @@ where
 ///
 /// # Errors
 ///
-/// Fails if the key already exists.
+/// Fails with `Error::KeyAlreadyExists` if the key already exists.
 pub async fn create_dir<K>(client: &Client, key: K, ttl: Option<u64>) -> EtcdKeyValueResult
 where
     K: AsRef<str>,
@@ -250,7 +642,7 @@ where
         SetOptions {
             dir: Some(true),
             prev_exist: Some(false),
-            ttl: ttl,
+            ttl,
             ..Default::default()
         },
     )
@@ -264,7 +656,8 @@ where
 /// key name like "00000000000000000001" automatically generated. The second value created with
 /// this function under the same directory will have a key name like "00000000000000000002".
 ///
-/// This behavior is guaranteed by the server.
+/// This behavior is guaranteed by the server. The generated key name is returned as the `key` of
+/// the response's node, and can be used to later get, delete, or watch the key-value pair.
 ///
 /// # Parameters
 ///
@@ -292,7 +685,7 @@ where
         key,
         SetOptions {
             create_in_order: true,
-            ttl: ttl,
+            ttl,
             value: Some(value),
             ..Default::default()
         },
@@ -307,7 +700,7 @@ where
 /// * client: A `Client` to use to make the API call.
 /// * key: The name of the node to delete.
 /// * recursive: If true, and the key is a directory, the directory and all child key-value
-/// pairs and directories will be deleted as well.
+///   pairs and directories will be deleted as well.
 ///
 /// # Errors
 ///
@@ -316,44 +709,216 @@ pub async fn delete<K>(client: &Client, key: K, recursive: bool) -> EtcdKeyValue
 where
     K: AsRef<str>,
 {
+    let options = DeleteOptions {
+        recursive,
+        ..Default::default()
+    };
+
+    delete_with_options(client, key, options).await
+}
+
+/// Deletes a node, with fine control over the kind of node and the conditions for deleting it.
+///
+/// With no conditions this behaves like `kv::delete`, and with conditions like
+/// `kv::compare_and_delete`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the node to delete.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// Fails with `Error::CompareFailed` if the conditions didn't match, with `Error::NotAFile` if
+/// conditions were given for a directory, or if the node is a directory and neither `dir` nor
+/// `recursive` is set.
+pub async fn delete_with_options<K>(
+    client: &Client,
+    key: K,
+    options: DeleteOptions,
+) -> EtcdKeyValueResult
+where
+    K: AsRef<str>,
+{
+    let conditions = if options.prev_value.is_some() || options.prev_index.is_some() {
+        Some(ComparisonConditions {
+            value: options.prev_value.as_deref(),
+            modified_index: options.prev_index,
+        })
+    } else {
+        None
+    };
+
     raw_delete(
         client,
         key,
-        DeleteOptions {
-            recursive: Some(recursive),
-            ..Default::default()
+        InternalDeleteOptions {
+            conditions,
+            dir: if options.dir { Some(true) } else { None },
+            recursive: Some(options.recursive),
+            timeout: options.timeout,
         },
     )
     .await
 }
 
-/// Deletes an empty directory or a key-value pair at the given key.
+/// Deletes a directory.
+///
+/// etcd would delete a key-value pair given the same parameters, so the node is read first to
+/// check that it is a directory. If the node is replaced by a key-value pair in between, the
+/// key-value pair is deleted instead.
 ///
 /// # Parameters
 ///
-/// * client: A `Client` to use to make the API call.
-/// * key: The name of the node to delete.
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the directory to delete.
+/// * recursive: If true, all child key-value pairs and directories will be deleted as well.
+///   Otherwise, only an empty directory can be deleted.
 ///
 /// # Errors
 ///
-/// Fails if the directory is not empty.
-pub async fn delete_dir<K>(client: &Client, key: K) -> EtcdKeyValueResult
+/// Fails with `Error::DirectoryNotEmpty` if the directory is not empty and `recursive` is
+/// `false`, or with `Error::NotADirectory` if the node is a key-value pair, in which case it is not
+/// deleted.
+pub async fn delete_dir<K>(client: &Client, key: K, recursive: bool) -> EtcdKeyValueResult
 where
     K: AsRef<str>,
 {
+    let key = key.as_ref();
+    let response = uncached_get(client, key, GetOptions::default()).await?;
+
+    if !response.data.node.is_dir() {
+        let error = api_error(
+            key,
+            NOT_A_DIRECTORY,
+            "Not a directory",
+            response.cluster_info,
+        );
+        return Err(vec![error]);
+    }
+
     raw_delete(
         client,
         key,
-        DeleteOptions {
+        InternalDeleteOptions {
             dir: Some(true),
+            recursive: if recursive { Some(true) } else { None },
             ..Default::default()
         },
     )
     .await
 }
 
+/// Deletes a directory and everything beneath it, returning the number of key-value pairs that
+/// were removed.
+///
+/// Keys in etcd's v2 API form a tree, so the prefix names a directory, and the whole directory is
+/// deleted with a single recursive delete. Partial names are not matched: deleting `/foo/ba` does
+/// not delete `/foo/bar`. If the prefix names a key-value pair, it is deleted on its own.
+///
+/// etcd does not report what a recursive delete removed, so the tree is read with a recursive
+/// `kv::get` first, and its key-value pairs are counted. Directories are not counted. Nodes written
+/// beneath the prefix between the read and the delete are deleted without being counted.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * prefix: The name of the directory to delete.
+///
+/// # Errors
+///
+/// Returns 0 rather than failing if the prefix does not exist, including if another client
+/// deleted it after it was read. Fails with the error returned by etcd if the node cannot be
+/// deleted, such as the root directory, which is read-only.
+pub async fn delete_prefix<K>(client: &Client, prefix: K) -> Result<u64, Vec<Error>>
+where
+    K: AsRef<str>,
+{
+    let prefix = prefix.as_ref();
+    let options = GetOptions {
+        recursive: true,
+        strong_consistency: true,
+        ..Default::default()
+    };
+    let root = match uncached_get(client, prefix, options).await {
+        Ok(response) => response.data.node,
+        Err(ref errors) if errors.iter().any(|e| matches!(*e, Error::KeyNotFound(_))) => {
+            return Ok(0)
+        }
+        Err(errors) => return Err(errors),
+    };
+
+    let count = if root.is_dir() {
+        root.iter_recursive().count() as u64
+    } else {
+        1
+    };
+
+    let options = DeleteOptions {
+        recursive: true,
+        ..Default::default()
+    };
+    match delete_with_options(client, prefix, options).await {
+        Ok(_) => Ok(count),
+        Err(ref errors) if errors.iter().any(|e| matches!(*e, Error::KeyNotFound(_))) => Ok(0),
+        Err(errors) => Err(errors),
+    }
+}
+
+/// Exports every node at and beneath `root`, such as for a backup.
+///
+/// The tree is read with a single recursive, strongly consistent get, so the export is a
+/// consistent snapshot. Directories are included, so empty directories are preserved, and each
+/// directory comes before its contents, in order by key. Exporting `/` exports the whole key
+/// space, without an entry for the root directory itself.
+///
+/// Hidden nodes are not exported, since etcd omits them from directory listings. See `kv::get`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * root: The name of the node to export.
+///
+/// # Errors
+///
+/// Fails if the node doesn't exist.
+pub async fn export<K>(client: &Client, root: K) -> Result<Vec<ExportEntry>, Vec<Error>>
+where
+    K: AsRef<str>,
+{
+    let options = GetOptions {
+        recursive: true,
+        sort: true,
+        strong_consistency: true,
+        ..Default::default()
+    };
+    let response = uncached_get(client, root.as_ref(), options).await?;
+
+    let mut entries = Vec::new();
+    let mut pending = vec![&response.data.node];
+    while let Some(node) = pending.pop() {
+        if node.key.is_some() {
+            entries.push(ExportEntry::from(node));
+        }
+        if let Some(ref nodes) = node.nodes {
+            pending.extend(nodes.iter().rev());
+        }
+    }
+
+    Ok(entries)
+}
+
 /// Gets the value of a node.
 ///
+/// Nodes whose names begin with an underscore, such as `/dir/_hidden`, are hidden: etcd omits them
+/// from directory listings, including recursive ones, and offers no way to include them. A hidden
+/// node can only be retrieved by its full key. Tools that need a complete copy of the key space,
+/// such as backups, must know the names of any hidden nodes.
+///
+/// If the client was built with `ClientBuilder::with_read_cache`, the result may be served from
+/// its cache, with `Response::cached` set.
+///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
@@ -362,11 +927,36 @@ where
 ///
 /// # Errors
 ///
-/// Fails if the key doesn't exist.
+/// Fails if the key doesn't exist, or with `Error::Timeout` if the request times out.
 pub async fn get<K>(client: &Client, key: K, options: GetOptions) -> EtcdKeyValueResult
 where
     K: AsRef<str>,
 {
+    let key = key.as_ref();
+    let cache = match client.read_cache() {
+        Some(cache) if !options.strong_consistency => cache,
+        _ => return uncached_get(client, key, options).await,
+    };
+
+    if let Some(response) = cache.get(key, options) {
+        return Ok(response);
+    }
+    let generation = cache.generation();
+    let response = uncached_get(client, key, options).await?;
+    cache.insert(key, options, generation, &response);
+
+    Ok(response)
+}
+
+/// Gets the value of a node from etcd, bypassing the client's cache.
+///
+/// Everything that reads a node to act on it, rather than to return it to the caller of `get`,
+/// uses this, so that a stale cached value can never drive a write or a watch.
+pub(crate) async fn uncached_get(
+    client: &Client,
+    key: &str,
+    options: GetOptions,
+) -> EtcdKeyValueResult {
     raw_get(
         client,
         key,
@@ -374,93 +964,669 @@ where
             recursive: options.recursive,
             sort: Some(options.sort),
             strong_consistency: options.strong_consistency,
+            timeout: options.timeout,
             ..Default::default()
         },
     )
     .await
 }
 
-/// Sets the value of a key-value pair.
+/// Gets the values of several nodes concurrently.
 ///
-/// Any previous value and TTL will be replaced.
+/// Results are yielded as they complete, which may not be the order in which the keys were given,
+/// so each result is paired with the key it corresponds to. A failure to get one key, such as it
+/// not existing, does not affect the others. At most 16 keys are requested at the same time, so
+/// that getting many keys does not open as many connections at once.
 ///
 /// # Parameters
 ///
-/// * client: A `Client` to use to make the API call.
-/// * key: The name of the key-value pair to set.
-/// * value: The new value for the key-value pair.
-/// * ttl: If given, the node will expire after this many seconds.
-///
-/// # Errors
-///
-/// Fails if the node is a directory.
-pub async fn set<K, V>(client: &Client, key: K, value: V, ttl: Option<u64>) -> EtcdKeyValueResult
+/// * client: A `Client` to use to make the API calls.
+/// * keys: The names of the nodes to retrieve.
+/// * options: Options to customize the behavior of each operation.
+pub fn get_many<K>(
+    client: &Client,
+    keys: &[K],
+    options: GetOptions,
+) -> impl Stream<Item = (String, EtcdKeyValueResult)>
 where
     K: AsRef<str>,
-    V: AsRef<str>,
 {
-    let value = value.as_ref();
-    raw_set(
-        client,
-        key,
-        SetOptions {
-            ttl,
-            value: Some(value),
-            ..Default::default()
-        },
-    )
-    .await
+    let keys: Vec<String> = keys.iter().map(|key| key.as_ref().to_string()).collect();
+    let client = client.clone();
+
+    stream::iter(keys)
+        .map(move |key| {
+            let client = client.clone();
+            async move {
+                let result = uncached_get(&client, &key, options).await;
+                (key, result)
+            }
+        })
+        .buffer_unordered(MAX_CONCURRENT_GETS)
 }
 
-/// Refreshes the already set etcd key, bumping its TTL without triggering watcher updates.
+/// Gets the contents of a directory created with `kv::create_in_order`, in creation order.
+///
+/// The directory is retrieved recursively, with its child nodes sorted by key name. Since key
+/// names generated by `kv::create_in_order` increase monotonically, this is the order in which
+/// they were created.
 ///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
-/// * key: The name of the key-value pair to set.
-/// * ttl: If given, the node will expire after this many seconds.
+/// * key: The name of the directory to retrieve.
 ///
 /// # Errors
 ///
-/// Fails if the node does not exist.
-pub async fn refresh<K>(client: &Client, key: K, ttl: u64) -> EtcdKeyValueResult
+/// Fails if the directory doesn't exist.
+pub async fn get_in_order<K>(client: &Client, key: K) -> EtcdKeyValueResult
 where
     K: AsRef<str>,
 {
-    raw_set(
+    get(
         client,
         key,
-        SetOptions {
-            ttl: Some(ttl),
-            refresh: true,
-            prev_exist: Some(true),
+        GetOptions {
+            recursive: true,
+            sort: true,
             ..Default::default()
         },
     )
     .await
 }
 
-/// Sets the key to an empty directory.
+/// Gets the value of a key-value pair, creating it with `value` if it does not exist, such as to
+/// register something idempotently.
 ///
-/// An existing key-value pair will be replaced, but an existing directory will not.
+/// Returns the value of the key and whether this call created it. The key is created with
+/// `kv::create`, which only succeeds if it does not exist yet, so when several clients race, one
+/// of them creates the key and the others get the value it created. If the key already exists,
+/// it is read with `kv::get`; if it is deleted in between, creating it is tried again, up to 10
+/// times in total.
 ///
 /// # Parameters
 ///
-/// * client: A `Client` to use to make the API call.
-/// * key: The name of the directory to set.
-/// * ttl: If given, the node will expire after this many seconds.
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the key-value pair.
+/// * value: The value to create the key with.
+/// * ttl: If given, a created node will expire after this many seconds. The TTL of an existing
+///   node is left unchanged.
 ///
 /// # Errors
 ///
-/// Fails if the node is an existing directory.
-pub async fn set_dir<K>(client: &Client, key: K, ttl: Option<u64>) -> EtcdKeyValueResult
+/// Fails with `Error::NotAFile` if the key is a directory. If the key was deleted after every
+/// failed attempt to create it, fails with the error of the last attempt, `Error::KeyNotFound`.
+pub async fn get_or_create<K, V>(
+    client: &Client,
+    key: K,
+    value: V,
+    ttl: Option<u64>,
+) -> Result<(String, bool), Vec<Error>>
 where
     K: AsRef<str>,
+    V: AsRef<str>,
 {
-    raw_set(
-        client,
-        key,
-        SetOptions {
+    let key = key.as_ref();
+    let value = value.as_ref();
+    let mut attempts = 1;
+
+    loop {
+        match create(client, key, value, ttl).await {
+            Ok(_) => return Ok((value.to_string(), true)),
+            Err(ref errors)
+                if errors
+                    .iter()
+                    .any(|e| matches!(*e, Error::KeyAlreadyExists(_))) => {}
+            Err(errors) => return Err(errors),
+        }
+
+        match uncached_get(client, key, GetOptions::default()).await {
+            Ok(response) => {
+                let node = response.data.node;
+                return match node.value {
+                    Some(existing) if !node.is_dir() => Ok((existing, false)),
+                    _ => Err(vec![not_a_file(key, response.cluster_info)]),
+                };
+            }
+            // The key was deleted since it could not be created.
+            Err(ref errors)
+                if attempts < MAX_GET_OR_CREATE_ATTEMPTS
+                    && errors.iter().any(|e| matches!(*e, Error::KeyNotFound(_))) => {}
+            Err(errors) => return Err(errors),
+        }
+
+        attempts += 1;
+    }
+}
+
+/// Gets the bytes stored in a key-value pair by `kv::set_bytes`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to retrieve.
+///
+/// # Errors
+///
+/// Fails if the key doesn't exist, or with `Error::NotBinary` if the value was not written by
+/// `kv::set_bytes`, such as because it is plain text or the node is a directory.
+pub async fn get_bytes<K>(client: &Client, key: K) -> Result<Vec<u8>, Vec<Error>>
+where
+    K: AsRef<str>,
+{
+    let response = uncached_get(client, key.as_ref(), GetOptions::default()).await?;
+    let value = response.data.node.value.unwrap_or_default();
+    let bytes = match value.strip_prefix(BYTES_PREFIX) {
+        Some(encoded) => base64::decode(encoded).ok(),
+        None => None,
+    };
+
+    bytes.ok_or_else(|| vec![Error::NotBinary(value)])
+}
+
+/// Gets the value of a key-value pair and deserializes it from JSON, such as a value written by
+/// `kv::set_typed`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to retrieve.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// Fails if the key doesn't exist, or with `Error::Serialization` if the value cannot be
+/// deserialized into `T`, such as because the node is a directory. The error's `body` is the raw
+/// value.
+pub async fn get_typed<T, K>(
+    client: &Client,
+    key: K,
+    options: GetOptions,
+) -> Result<Response<T>, Vec<Error>>
+where
+    T: DeserializeOwned,
+    K: AsRef<str>,
+{
+    let response = uncached_get(client, key.as_ref(), options).await?;
+    let value = response.data.node.value.unwrap_or_default();
+    let data = parse_json_body(value.as_bytes()).map_err(|e| vec![e])?;
+
+    Ok(Response {
+        cached: response.cached,
+        cluster_info: response.cluster_info,
+        data,
+        dry_run: response.dry_run,
+        endpoint: response.endpoint,
+        retries: response.retries,
+    })
+}
+
+/// Imports nodes exported by `kv::export`, such as to restore a backup or seed a new cluster.
+///
+/// The nodes are written one at a time, in the order given, which for an export places every
+/// directory before its contents. A node that fails to import does not stop the import, so the
+/// result summarizes what happened to each node.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * entries: The nodes to import.
+/// * options: Options to customize the behavior of the operation.
+pub async fn import<I>(client: &Client, entries: I, options: ImportOptions) -> ImportSummary
+where
+    I: IntoIterator<Item = ExportEntry>,
+{
+    let mut summary = ImportSummary::default();
+
+    for entry in entries {
+        let ttl = if options.preserve_ttls {
+            entry.ttl
+        } else {
+            None
+        };
+
+        let result = match (entry.dir, options.overwrite) {
+            (true, true) => set_dir(client, &entry.key, ttl).await,
+            (true, false) => create_dir(client, &entry.key, ttl).await,
+            (false, overwrite) => {
+                let value = entry.value.as_deref().unwrap_or_default();
+                if overwrite {
+                    set(client, &entry.key, value, ttl).await
+                } else {
+                    create(client, &entry.key, value, ttl).await
+                }
+            }
+        };
+
+        match result {
+            Ok(_) => summary.created.push(entry.key),
+            // The node already exists and is not replaced, either because overwriting is
+            // disabled or because it is a directory.
+            Err(ref errors)
+                if errors.iter().any(|error| match *error {
+                    Error::KeyAlreadyExists(_) => true,
+                    Error::NotAFile(_) => entry.dir,
+                    _ => false,
+                }) =>
+            {
+                summary.skipped.push(entry.key)
+            }
+            Err(errors) => summary.failed.push((entry.key, errors)),
+        }
+    }
+
+    summary
+}
+
+/// Atomically adds `delta` to the integer stored in a key, returning the new value.
+///
+/// The current value is read and parsed as an `i64`, and the sum is written with a
+/// compare-and-swap on the modified index that was read. If another client changes the key in
+/// between, the compare-and-swap fails, and the read and write are tried again, up to 10 times in
+/// total. If the key does not exist, it is created with `delta` as its value. Like Rust's atomic
+/// integers, the value wraps around on overflow. The key's TTL, if any, is removed.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the counter's key.
+/// * delta: The amount to add, which may be negative.
+///
+/// # Errors
+///
+/// Fails with `Error::NotAnInteger` if the current value is not an integer, such as because the
+/// node is a directory. If the key was changed by other clients on every attempt, fails with the
+/// error of the last attempt, such as `Error::CompareFailed`.
+pub async fn increment<K>(client: &Client, key: K, delta: i64) -> Result<i64, Vec<Error>>
+where
+    K: AsRef<str>,
+{
+    let key = key.as_ref();
+    let mut attempts = 1;
+
+    loop {
+        let result = match uncached_get(client, key, GetOptions::default()).await {
+            Ok(response) => {
+                let node = response.data.node;
+                let value = node.value.unwrap_or_default();
+                let current = value
+                    .parse::<i64>()
+                    .map_err(|_| vec![Error::NotAnInteger(value.clone())])?;
+                let new = current.wrapping_add(delta);
+                let options = CompareAndSwapOptions {
+                    prev_value: None,
+                    prev_index: node.modified_index,
+                    ..Default::default()
+                };
+
+                compare_and_swap(client, key, new.to_string(), None, options)
+                    .await
+                    .map(|_| new)
+            }
+            Err(ref errors) if errors.iter().any(|e| matches!(*e, Error::KeyNotFound(_))) => {
+                create(client, key, delta.to_string(), None)
+                    .await
+                    .map(|_| delta)
+            }
+            Err(errors) => return Err(errors),
+        };
+
+        match result {
+            Ok(value) => return Ok(value),
+            // Another client changed, created, or deleted the key since it was read.
+            Err(ref errors)
+                if attempts < MAX_INCREMENT_ATTEMPTS
+                    && errors.iter().any(|e| {
+                        matches!(
+                            *e,
+                            Error::CompareFailed(_)
+                                | Error::KeyAlreadyExists(_)
+                                | Error::KeyNotFound(_)
+                        )
+                    }) => {}
+            Err(errors) => return Err(errors),
+        }
+
+        attempts += 1;
+    }
+}
+
+/// Keeps a key with a TTL alive by refreshing it periodically, yielding the result of each refresh.
+///
+/// This is useful for keys that should only exist while the process that set them is running,
+/// such as service registrations. The key must already be set; each refresh bumps its TTL with
+/// `kv::refresh`. Refreshing stops when the stream is dropped.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the key-value pair to keep alive.
+/// * ttl: The node will expire after this many seconds if it stops being refreshed.
+/// * interval: How long to wait before each refresh. Defaults to a third of `ttl`, so that a
+///   failed refresh can be retried before the key expires.
+///
+/// # Errors
+///
+/// Failed refreshes are yielded as errors, and refreshing continues afterwards. This includes
+/// `Error::KeyNotFound` if the key has expired or been deleted, which the caller may want to react
+/// to by setting the key again.
+///
+/// # Panics
+///
+/// Panics if `ttl` or `interval` is zero.
+pub fn keep_alive<K>(
+    client: &Client,
+    key: K,
+    ttl: u64,
+    interval: Option<Duration>,
+) -> impl Stream<Item = Result<(), Vec<Error>>>
+where
+    K: AsRef<str>,
+{
+    if ttl == 0 {
+        panic!("invariant: keep-alive TTL must be at least one second")
+    }
+
+    let interval = interval.unwrap_or_else(|| Duration::from_secs(ttl) / 3);
+    if interval == Duration::from_secs(0) {
+        panic!("invariant: keep-alive interval must be greater than zero")
+    }
+
+    let state = (client.clone(), key.as_ref().to_string());
+
+    stream::unfold(state, move |(client, key)| async move {
+        sleep(interval).await;
+        let result = refresh(&client, &key, ttl).await.map(|_| ());
+        Some((result, (client, key)))
+    })
+}
+
+/// Lists the keys of the nodes beneath a directory, without their values.
+///
+/// etcd has no way to get keys without their values, so this gets the whole tree with a recursive
+/// sorted `kv::get` and discards everything but the keys. The keys are in the order of a recursive
+/// sorted get: each directory's contents follow it, sorted by key. `dir` itself is not listed,
+/// unless it is a key-value pair, in which case only its key is listed. Hidden nodes are not
+/// listed. See `kv::get`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * dir: The name of the directory whose keys to list.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// Fails if the node doesn't exist.
+pub async fn list_keys<K>(
+    client: &Client,
+    dir: K,
+    options: ListKeysOptions,
+) -> Result<Vec<String>, Vec<Error>>
+where
+    K: AsRef<str>,
+{
+    let get_options = GetOptions {
+        recursive: true,
+        sort: true,
+        ..Default::default()
+    };
+    let response = uncached_get(client, dir.as_ref(), get_options).await?;
+    let root = response.data.node;
+
+    if !root.is_dir() {
+        return Ok(root.key.into_iter().collect());
+    }
+
+    let mut keys = Vec::new();
+    let mut pending: Vec<&Node> = root.children().iter().rev().collect();
+    while let Some(node) = pending.pop() {
+        if let Some(ref key) = node.key {
+            if !node.is_dir() || options.include_dirs {
+                keys.push(key.clone());
+            }
+        }
+        pending.extend(node.children().iter().rev());
+    }
+
+    Ok(keys)
+}
+
+/// Sets the value of a key-value pair to arbitrary bytes, which are stored as text.
+///
+/// Since etcd's v2 API only stores UTF-8 values, the bytes are stored encoded in base64, prefixed
+/// with `base64:` to mark them as such, so the value is larger than `value`. Read them back with
+/// `kv::get_bytes`. Other readers see the encoded text, and it is up to the caller to only use
+/// `kv::get_bytes` for keys written with `kv::set_bytes`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to set.
+/// * value: The new value for the key-value pair.
+/// * ttl: If given, the node will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the node is a directory, or with `Error::ValueTooLarge` if the encoded value is larger
+/// than the client allows.
+pub async fn set_bytes<K>(
+    client: &Client,
+    key: K,
+    value: &[u8],
+    ttl: Option<u64>,
+) -> EtcdKeyValueResult
+where
+    K: AsRef<str>,
+{
+    let value = format!("{}{}", BYTES_PREFIX, base64::encode(value));
+
+    set(client, key, value, ttl).await
+}
+
+/// Sets the value of a key-value pair to `value` serialized as JSON. Read it back with
+/// `kv::get_typed`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to set.
+/// * value: The new value for the key-value pair.
+/// * ttl: If given, the node will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the node is a directory, with `Error::Serialization` if `value` cannot be serialized,
+/// or with `Error::ValueTooLarge` if the serialized value is larger than the client allows.
+pub async fn set_typed<T, K>(
+    client: &Client,
+    key: K,
+    value: &T,
+    ttl: Option<u64>,
+) -> EtcdKeyValueResult
+where
+    T: SerializeValue + ?Sized,
+    K: AsRef<str>,
+{
+    let value = serde_json::to_string(value).map_err(|e| vec![e.into()])?;
+
+    set(client, key, value, ttl).await
+}
+
+/// Sets the value of a key-value pair.
+///
+/// Any previous value and TTL will be replaced.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to set.
+/// * value: The new value for the key-value pair.
+/// * ttl: If given, the node will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the node is a directory, or with `Error::ValueTooLarge` if the value is larger than the
+/// client allows.
+pub async fn set<K, V>(client: &Client, key: K, value: V, ttl: Option<u64>) -> EtcdKeyValueResult
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let value = value.as_ref();
+    raw_set(
+        client,
+        key,
+        SetOptions {
+            ttl,
+            value: Some(value),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Refreshes the already set etcd key, bumping its TTL without triggering watcher updates.
+///
+/// The key's value is left untouched; no value is sent with the request. A key without a TTL is
+/// given one, so it will expire unless it is refreshed again. This requires etcd 2.3 or later.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the key-value pair to refresh.
+/// * ttl: The node will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the node does not exist, or with the error returned by etcd if the node cannot be
+/// refreshed.
+pub async fn refresh<K>(client: &Client, key: K, ttl: u64) -> EtcdKeyValueResult
+where
+    K: AsRef<str>,
+{
+    raw_set(
+        client,
+        key,
+        SetOptions {
+            ttl: Some(ttl),
+            refresh: true,
+            prev_exist: Some(true),
+            ..Default::default()
+        },
+    )
+    .await
+}
+
+/// Moves a key-value pair to a new key, keeping its value and remaining TTL.
+///
+/// etcd has no operation that renames a key, so this is not a single atomic operation: `from` is
+/// read, `to` is created with its value only if it does not exist, and then `from` is deleted
+/// with a compare-and-delete on the modified index that was read. If `from` was changed or
+/// deleted by another client in the meantime, the delete fails, and `to` is deleted again, also
+/// on the modified index it was created with, so that no change to `from` is lost. If the delete
+/// fails in any other way, it may still have been applied, such as when the response was lost
+/// and the request failed over to another member, so both keys are left in place.
+///
+/// Other clients can observe the intermediate state, in which both keys exist, and watchers see
+/// a create of `to` followed by a delete of `from` rather than a single event. If the process
+/// stops between the two writes, both keys are left in place.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * from: The name of the key-value pair to move.
+/// * to: The new name of the key-value pair.
+///
+/// # Errors
+///
+/// Fails with `Error::KeyNotFound` if `from` does not exist, with `Error::NotAFile` if it is a
+/// directory, or with `Error::KeyAlreadyExists` if `to` already exists. Nothing is changed in
+/// these cases. Fails with `Error::CompareFailed` or `Error::KeyNotFound` if `from` was changed
+/// or deleted after it was read. In that case `to` is deleted again, unless that fails too, such
+/// as because another client changed `to` in the meantime; the errors of the failed cleanup
+/// follow the first error and `to` is left in place. Fails with the errors of the delete, leaving
+/// both keys in place, if the first member that received it did not answer with either of those
+/// errors.
+pub async fn rename<F, T>(client: &Client, from: F, to: T) -> Result<(), Vec<Error>>
+where
+    F: AsRef<str>,
+    T: AsRef<str>,
+{
+    let from = from.as_ref();
+    let to = to.as_ref();
+    let options = GetOptions {
+        strong_consistency: true,
+        ..Default::default()
+    };
+    let response = uncached_get(client, from, options).await?;
+    let node = response.data.node;
+
+    let value = match node.value {
+        Some(ref value) if !node.is_dir() => value,
+        _ => return Err(vec![not_a_file(from, response.cluster_info)]),
+    };
+    // A node that is about to expire keeps at least one second to live.
+    let ttl = node.ttl.map(|ttl| ttl.max(1) as u64);
+
+    let created = create(client, to, value, ttl).await?;
+
+    let options = CompareAndDeleteOptions {
+        prev_value: None,
+        prev_index: node.modified_index,
+        ..Default::default()
+    };
+    let mut errors = match compare_and_delete(client, from, options).await {
+        Ok(_) => return Ok(()),
+        Err(errors) => errors,
+    };
+
+    // Only the first member that received the delete can tell whether it was rejected. A later
+    // member that fails it may only be seeing the result of an earlier attempt whose response was
+    // lost, and deleting `to` then would lose the value.
+    let rejected = matches!(
+        errors
+            .iter()
+            .find(|error| !matches!(**error, Error::Connection { .. })),
+        Some(Error::CompareFailed(_)) | Some(Error::KeyNotFound(_))
+    );
+    if !rejected {
+        return Err(errors);
+    }
+
+    let options = CompareAndDeleteOptions {
+        prev_value: None,
+        prev_index: created.data.node.modified_index,
+        ..Default::default()
+    };
+    if let Err(cleanup_errors) = compare_and_delete(client, to, options).await {
+        errors.extend(cleanup_errors);
+    }
+
+    Err(errors)
+}
+
+/// Sets the key to an empty directory.
+///
+/// An existing key-value pair will be replaced, but an existing directory will not.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * key: The name of the directory to set.
+/// * ttl: If given, the node will expire after this many seconds.
+///
+/// # Errors
+///
+/// Fails if the node is an existing directory.
+pub async fn set_dir<K>(client: &Client, key: K, ttl: Option<u64>) -> EtcdKeyValueResult
+where
+    K: AsRef<str>,
+{
+    raw_set(
+        client,
+        key,
+        SetOptions {
             dir: Some(true),
             ttl,
             ..Default::default()
@@ -478,9 +1644,12 @@ where
 /// * value: The new value for the key-value pair.
 /// * ttl: If given, the node will expire after this many seconds.
 ///
+/// This sends `prevExist=true`, so etcd only writes the key if it already exists.
+///
 /// # Errors
 ///
-/// Fails if the key does not exist.
+/// Fails with `Error::KeyNotFound` if the key does not exist, or with `Error::ValueTooLarge` if the
+/// value is larger than the client allows.
 pub async fn update<K, V>(client: &Client, key: K, value: V, ttl: Option<u64>) -> EtcdKeyValueResult
 where
     K: AsRef<str>,
@@ -531,13 +1700,101 @@ where
     .await
 }
 
+/// Traverses all nodes beneath a directory, getting one directory level at a time.
+///
+/// Unlike a recursive `kv::get`, which returns the whole tree in a single response, this gets each
+/// directory separately and only as the stream is polled, which bounds memory use for large trees.
+/// Every key and directory beneath `dir` is yielded, without its child nodes. Directories are
+/// yielded before their contents, but since several directories may be fetched concurrently, nodes
+/// from different directories may be interleaved.
+///
+/// Since each directory is read at a different time, the traversal is not a consistent snapshot
+/// of the tree. Hidden nodes are not yielded, since etcd omits them from directory listings. See
+/// `kv::get`.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * dir: The name of the directory to traverse. The directory itself is not yielded.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// If getting a directory fails, such as because it was deleted during the traversal, the
+/// errors are yielded and the traversal continues with the remaining directories.
+///
+/// # Panics
+///
+/// Panics if `options.concurrency` is zero.
+pub fn walk<K>(
+    client: &Client,
+    dir: K,
+    options: WalkOptions,
+) -> impl Stream<Item = Result<Node, Vec<Error>>>
+where
+    K: AsRef<str>,
+{
+    if options.concurrency == 0 {
+        panic!("invariant: walk concurrency must be at least one")
+    }
+
+    let mut pending = VecDeque::new();
+    pending.push_back(dir.as_ref().to_string());
+    let state = (
+        client.clone(),
+        pending,
+        FuturesUnordered::new(),
+        VecDeque::new(),
+    );
+
+    stream::unfold(
+        state,
+        move |(client, mut pending, mut in_flight, mut ready)| async move {
+            loop {
+                if let Some(node) = ready.pop_front() {
+                    return Some((Ok(node), (client, pending, in_flight, ready)));
+                }
+
+                while in_flight.len() < options.concurrency {
+                    match pending.pop_front() {
+                        Some(dir) => in_flight.push(walk_dir(client.clone(), dir)),
+                        None => break,
+                    }
+                }
+
+                match in_flight.next().await? {
+                    Ok(nodes) => {
+                        for mut node in nodes {
+                            if node.is_dir() {
+                                if let Some(ref key) = node.key {
+                                    pending.push_back(key.clone());
+                                }
+                            }
+                            node.nodes = None;
+                            ready.push_back(node);
+                        }
+                    }
+                    Err(errors) => return Some((Err(errors), (client, pending, in_flight, ready))),
+                }
+            }
+        },
+    )
+}
+
+/// Gets the child nodes of a single directory for `kv::walk`.
+async fn walk_dir(client: Client, dir: String) -> Result<Vec<Node>, Vec<Error>> {
+    let response = uncached_get(&client, &dir, GetOptions::default()).await?;
+    Ok(response.data.node.nodes.unwrap_or_default())
+}
+
 /// Watches a node for changes and returns the new value as soon as a change takes place.
 ///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
 /// * key: The name of the node to watch.
-/// * options: Options to customize the behavior of the operation.
+/// * options: Options to customize the behavior of the operation. Unless `options.recursive` is
+///   set, only changes to `key` itself are returned, not changes to its descendants.
 ///
 /// # Errors
 ///
@@ -548,6 +1805,13 @@ where
 ///
 /// Fails if a timeout is specified and the duration lapses without a response from the etcd
 /// cluster.
+///
+/// # Cancellation
+///
+/// Dropping the returned future cancels the watch: the in-flight request is aborted and its
+/// connection is closed right away rather than at the end of the long poll. This makes the future
+/// safe to use with `tokio::select!` or `tokio::time::timeout`, for example to stop watching on
+/// shutdown.
 pub async fn watch<K>(
     client: &Client,
     key: K,
@@ -556,16 +1820,7 @@ pub async fn watch<K>(
 where
     K: AsRef<str>,
 {
-    let fut = raw_get(
-        client,
-        key,
-        InternalGetOptions {
-            recursive: options.recursive,
-            wait_index: options.index,
-            wait: true,
-            ..Default::default()
-        },
-    );
+    let fut = raw_watch(client, key.as_ref(), options);
 
     if let Some(duration) = options.timeout {
         match timeout(duration, fut).await {
@@ -577,23 +1832,236 @@ where
     }
 }
 
+/// Long-polls for the next change to a node, without a timeout, for `kv::watch`.
+async fn raw_watch(client: &Client, key: &str, options: WatchOptions) -> EtcdKeyValueResult {
+    loop {
+        let result = raw_get(
+            client,
+            key,
+            InternalGetOptions {
+                no_value: options.without_values,
+                recursive: options.recursive,
+                wait_index: options.index,
+                wait: true,
+                ..Default::default()
+            },
+        )
+        .await;
+        let result = result.map(|mut response| {
+            if options.without_values {
+                response.data.node.value = None;
+                if let Some(ref mut prev_node) = response.data.prev_node {
+                    prev_node.value = None;
+                }
+            }
+            response
+        });
+
+        // The request itself has an arbitrary large timeout, since `reqwest` requires one. If it
+        // lapses on every endpoint, nothing has changed yet, so poll again.
+        match result {
+            Err(ref errors) if errors.iter().all(|e| matches!(*e, Error::Timeout)) => {}
+            result => return result,
+        }
+    }
+}
+
+/// Watches a node for changes continuously, yielding each change as it takes place.
+///
+/// After each change, the stream waits for the next change at the following modified index, so
+/// no changes are missed between events. If etcd reports that the wait index has been cleared
+/// from its event history, the stream resynchronizes from the cluster's current index instead of
+/// failing. Changes that occurred within the cleared window are not yielded. If `options.timeout`
+/// lapses before the next change, the watch is re-issued from the same index, so an idle node
+/// does not end the stream.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the node to watch.
+/// * options: Options to customize the behavior of the operation. `options.index` is only used
+///   for the first request.
+///
+/// # Errors
+///
+/// The stream yields the error and ends if a request fails for any reason other than a cleared
+/// wait index or a lapsed timeout.
+///
+/// # Cancellation
+///
+/// Dropping the stream cancels the current watch, as described for `kv::watch`.
+pub fn watch_stream<K>(
+    client: &Client,
+    key: K,
+    options: WatchOptions,
+) -> impl Stream<Item = EtcdKeyValueResult<WatchError>>
+where
+    K: AsRef<str>,
+{
+    let state = Some((client.clone(), key.as_ref().to_string(), options));
+
+    stream::unfold(state, |state| async move {
+        let (client, key, mut options) = state?;
+
+        loop {
+            match watch(&client, &key, options).await {
+                Ok(response) => {
+                    options.index = response.data.node.modified_index.map(|index| index + 1);
+                    return Some((Ok(response), Some((client, key, options))));
+                }
+                Err(WatchError::Timeout) => {}
+                Err(error) => match cleared_index(&error) {
+                    Some(index) => options.index = Some(index + 1),
+                    None => return Some((Err(error), None)),
+                },
+            }
+        }
+    })
+}
+
+/// Waits until a node is deleted or expires.
+///
+/// The node is read first, so if it does not exist, this returns right away. Otherwise, the node
+/// is watched from the index it was read at, so a deletion between the read and the watch is not
+/// missed. Changes to the node that do not remove it, such as updates, are skipped. Deleting a
+/// parent directory of the node also counts as deleting the node.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * key: The name of the node to wait for.
+/// * timeout: If given, the operation will time out if the node still exists after the
+///   duration.
+///
+/// # Errors
+///
+/// Fails with `WatchError::Timeout` if a timeout is specified and the duration lapses before the
+/// node is gone, or with `WatchError::Other` if any of the API calls fail.
+pub async fn wait_delete<K>(
+    client: &Client,
+    key: K,
+    timeout: Option<Duration>,
+) -> Result<(), WatchError>
+where
+    K: AsRef<str>,
+{
+    let fut = raw_wait_delete(client, key.as_ref());
+
+    if let Some(duration) = timeout {
+        match tokio::time::timeout(duration, fut).await {
+            Ok(result) => result,
+            Err(_elapsed) => Err(WatchError::Timeout),
+        }
+    } else {
+        fut.await
+    }
+}
+
+/// Waits until a node is deleted or expires, without a timeout, for `kv::wait_delete`.
+async fn raw_wait_delete(client: &Client, key: &str) -> Result<(), WatchError> {
+    let mut index = None;
+
+    loop {
+        let start = match index {
+            Some(index) => index,
+            None => match uncached_get(client, key, GetOptions::default()).await {
+                Ok(response) => response.cluster_info.etcd_index.unwrap_or_default() + 1,
+                Err(ref errors) if errors.iter().any(|e| matches!(*e, Error::KeyNotFound(_))) => {
+                    return Ok(())
+                }
+                Err(errors) => return Err(WatchError::Other(errors)),
+            },
+        };
+
+        let options = WatchOptions {
+            index: Some(start),
+            ..Default::default()
+        };
+        match watch(client, key, options).await {
+            Ok(response) => match response.data.action {
+                Action::CompareAndDelete | Action::Delete | Action::Expire => return Ok(()),
+                _ => index = response.data.node.modified_index.map(|index| index + 1),
+            },
+            // Read the node again, since its deletion may have been cleared from the history.
+            Err(ref error) if cleared_index(error).is_some() => index = None,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Synthesizes the error etcd returns when a key-value operation targets a directory.
+fn not_a_file(key: &str, cluster_info: ClusterInfo) -> Error {
+    api_error(key, NOT_A_FILE, "Not a file", cluster_info)
+}
+
+/// Synthesizes the error etcd would return with the given code and message for an operation on
+/// the given key, for checks this crate makes before sending a request.
+fn api_error(key: &str, error_code: u64, message: &str, cluster_info: ClusterInfo) -> Error {
+    let error = ApiError {
+        cause: Some(key.to_string()),
+        error_code,
+        index: cluster_info.etcd_index.unwrap_or_default(),
+        message: message.to_string(),
+        cluster_info: Some(Box::new(cluster_info)),
+    };
+
+    error.into()
+}
+
+/// Returns the cluster's current index if the watch failed because its index was cleared.
+pub(crate) fn cleared_index(error: &WatchError) -> Option<u64> {
+    match *error {
+        WatchError::Other(ref errors) => errors.iter().find_map(|error| match *error {
+            Error::Api(ref error) if error.error_code == EVENT_INDEX_CLEARED => Some(error.index),
+            _ => None,
+        }),
+        WatchError::Timeout => None,
+    }
+}
+
 /// Handles all delete operations.
-async fn raw_delete<K>(client: &Client, key: K, options: DeleteOptions<'_>) -> EtcdKeyValueResult
+async fn raw_delete<K>(
+    client: &Client,
+    key: K,
+    options: InternalDeleteOptions<'_>,
+) -> EtcdKeyValueResult
 where
     K: AsRef<str>,
 {
     let key = key.as_ref();
+    let action = if options.conditions.is_some() {
+        Action::CompareAndDelete
+    } else {
+        Action::Delete
+    };
+    let dir = options.dir;
+    let request_timeout = options.timeout;
     let query_params = options.into_query_params().map_err(|e| vec![e])?;
 
-    client
+    if client.is_dry_run() {
+        let node = Node {
+            dir,
+            ..dry_run_node(key)
+        };
+        return dry_run_response(client, key, Some(&query_params), action, node);
+    }
+
+    let result = client
         .first_ok(move |client, endpoint| {
-            let url = build_url(endpoint, key, Some(&query_params));
+            let url = build_url(&endpoint, key, Some(&query_params));
             async move {
-                let response = client.http_client().delete(url).send().await?;
+                let mut request = client.http_client().delete(url);
+                if let Some(request_timeout) = request_timeout {
+                    request = request.timeout(request_timeout);
+                }
+                let response = client.send(request).await?;
                 parse_etcd_response(response, |s| s == StatusCode::OK).await
             }
         })
-        .await
+        .await;
+    invalidate_cache(client, key);
+
+    result
 }
 
 /// Handles all get operations.
@@ -602,22 +2070,25 @@ where
     K: AsRef<str>,
 {
     let wait = options.wait;
+    let request_timeout = options.timeout;
     let query_params = options.into_query_params();
     let key = key.as_ref();
 
     client
-        .first_ok(move |client, endpoint| {
-            let url = build_url(endpoint, key, Some(&query_params));
+        .first_ok_idempotent(move |client, endpoint| {
+            let url = build_url(&endpoint, key, Some(&query_params));
             async move {
                 let request = client.http_client().get(url);
-                let request = if wait {
+                let request = if let Some(request_timeout) = request_timeout {
+                    request.timeout(request_timeout)
+                } else if wait {
                     // Since `reqwest` doesn't let us specify a timeout, we'll set an arbitrary
                     // large amount of requests.
                     request.timeout(Duration::from_secs(60 * 60 * 24))
                 } else {
                     request
                 };
-                let response = request.send().await?;
+                let response = client.send(request).await?;
                 parse_etcd_response(response, |s| s == StatusCode::OK).await
             }
         })
@@ -631,31 +2102,118 @@ where
 {
     let key = key.as_ref();
     let create_in_order = options.create_in_order;
+    let action = if create_in_order {
+        Action::Create
+    } else if options.conditions.is_some() {
+        Action::CompareAndSwap
+    } else if options.refresh {
+        Action::Update
+    } else {
+        match options.prev_exist {
+            Some(true) => Action::Update,
+            Some(false) => Action::Create,
+            None => Action::Set,
+        }
+    };
+    let node = Node {
+        dir: options.dir,
+        ttl: options.ttl.map(|ttl| ttl as i64),
+        value: options.value.map(str::to_string),
+        ..dry_run_node(key)
+    };
+    if let Some(value) = options.value {
+        let limit = client.max_value_size();
+        if value.len() > limit {
+            return Err(vec![Error::ValueTooLarge {
+                size: value.len(),
+                limit,
+            }]);
+        }
+    }
+    let request_timeout = options.timeout;
     let request_body = options.into_request_body().map_err(|e| vec![e])?;
 
-    client
+    if client.is_dry_run() {
+        return dry_run_response(client, key, None, action, node);
+    }
+
+    let result = client
         .first_ok(move |client, endpoint| {
             let request_body = request_body.clone();
 
             async move {
-                let url = build_url(endpoint, key, None);
+                let url = build_url(&endpoint, key, None);
                 let request = if create_in_order {
                     client.http_client().post(url)
                 } else {
                     client.http_client().put(url)
                 };
-                let request = request.header(
+                let mut request = request.header(
                     http::header::CONTENT_TYPE,
                     "application/x-www-form-urlencoded",
                 );
-                let response = request.body(request_body).send().await?;
+                if let Some(request_timeout) = request_timeout {
+                    request = request.timeout(request_timeout);
+                }
+                let response = client.send(request.body(request_body)).await?;
                 parse_etcd_response(response, |s| {
                     s == StatusCode::OK || s == StatusCode::CREATED
                 })
                 .await
             }
         })
-        .await
+        .await;
+    // Even a failed write may have been applied, such as when its response timed out.
+    invalidate_cache(client, key);
+
+    result
+}
+
+/// Removes the cached gets that a write to `key` may have changed.
+fn invalidate_cache(client: &Client, key: &str) {
+    if let Some(cache) = client.read_cache() {
+        cache.invalidate(key);
+    }
+}
+
+/// Returns a node with only the given key, to describe the target of a dry-run write.
+fn dry_run_node(key: &str) -> Node {
+    Node {
+        created_index: None,
+        dir: None,
+        expiration: None,
+        key: Some(key.to_string()),
+        modified_index: None,
+        nodes: None,
+        ttl: None,
+        value: None,
+    }
+}
+
+/// Synthesizes the response to a write by a client in dry-run mode, after checking that the
+/// request URL is valid.
+fn dry_run_response(
+    client: &Client,
+    key: &str,
+    query_params: Option<&str>,
+    action: Action,
+    node: Node,
+) -> EtcdKeyValueResult {
+    let endpoint = client.first_endpoint();
+    Url::parse(&build_url(&endpoint, key, query_params)).map_err(|error| vec![error.into()])?;
+
+    Ok(Response {
+        cluster_info: ClusterInfo::default(),
+        data: KeyValueInfo {
+            action,
+            node,
+            prev_node: None,
+        },
+        cached: false,
+        dry_run: true,
+        endpoint,
+        retries: 0,
+    })
 }
 
 /// Constructs the full URL for an API call.
@@ -666,3 +2224,10 @@ fn build_url(endpoint: &Uri, path: &str, query_params: Option<&str>) -> String {
         format!("{}v2/keys{}", endpoint, path)
     }
 }
+
+/// Parses an RFC 3339 timestamp, such as `2013-12-04T12:01:21.874888581-08:00`.
+fn parse_rfc3339(timestamp: &str) -> Option<SystemTime> {
+    OffsetDateTime::parse(timestamp, &Rfc3339)
+        .ok()
+        .map(SystemTime::from)
+}
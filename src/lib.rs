@@ -1,7 +1,7 @@
 //! Crate `etcd` provides a client for [etcd](https://github.com/coreos/etcd), a distributed
 //! key-value store from [CoreOS](https://coreos.com/).
 //!
-//! The client uses etcd's v2 API. Support for the v3 API is planned, and will be added via
+//! The client uses etcd's v2 API. Support for the v3 API is being added in the `v3` module, via
 //! separate types for backwards compatibility and to support both APIs simultaneously.
 //!
 //! The client uses asynchronous I/O, backed by the `futures` and `tokio` crates, and requires
@@ -23,6 +23,9 @@
 //! API, the primary key-value store API, the cluster membership API, and statistics API,
 //! respectively.
 //!
+//! The `recipes` module builds coordination primitives, such as distributed locks, on top of the
+//! key-value API.
+//!
 //! # Examples
 //!
 //! Basic usage:
@@ -56,20 +59,45 @@
 //!
 //! # Cargo features
 //!
-//! Crate `etcd` has one Cargo feature, `tls`, which adds HTTPS support via the `Client::https`
-//! constructor. This feature is enabled by default.
+//! Crate `etcd` has the following Cargo features:
+//!
+//! * `tls`: Adds HTTPS support via the `Client::https` constructor, and HTTP/2 negotiation for
+//!   clients built with `HttpVersion::Negotiate`. This feature is enabled by default.
+//! * `blocking`: Adds the `blocking` module, with a client whose methods block the current thread
+//!   until the API call completes, for code that does not use futures.
+//! * `compression`: Asks etcd to compress response bodies with gzip or deflate, and decompresses
+//!   them transparently. This reduces the amount of data transferred by large requests, such as
+//!   recursive gets of big directories. Decompressed bodies are limited to the size set with
+//!   `ClientBuilder::with_max_decompressed_size`.
+//! * `tracing`: Records a `tracing` span for each HTTP request, with the endpoint, path, and
+//!   status of the request, and the etcd error code if it failed. Spans follow OpenTelemetry's
+//!   naming conventions, so they can be exported with `tracing-opentelemetry`.
 #![deny(missing_debug_implementations, missing_docs, warnings)]
 
-pub use crate::client::{Client, ClientBuilder, ClusterInfo, Health, Response};
+#[cfg(feature = "tls")]
+pub use crate::client::TlsVersion;
+pub use crate::client::{
+    CacheConfig, CircuitConfig, CircuitState, Client, ClientBuilder, ClusterInfo, Health,
+    HttpVersion, RequestEvent, Response, ResponseEvent, RetryPolicy, Selection,
+};
 pub use crate::error::{ApiError, Error};
-pub use crate::version::VersionInfo;
+pub use crate::version::{Version, VersionInfo};
 
 pub mod auth;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod kv;
 pub mod members;
+pub mod recipes;
 pub mod stats;
+pub mod v3;
 
 mod client;
+#[cfg(feature = "compression")]
+mod compression;
+mod discovery;
 mod error;
 mod options;
+#[cfg(feature = "tracing")]
+mod telemetry;
 mod version;
@@ -2,14 +2,19 @@
 //!
 //! These API endpoints are used to manage cluster membership.
 
+use std::time::Duration;
+
 use crate::{
-    client::{parse_empty_response, parse_etcd_response},
+    client::{parse_etcd_response, parse_json_body, read_body, response_endpoint, ClusterInfo},
     Client, Error, Response,
 };
 
-use http::{StatusCode, Uri};
+use futures_util::stream::{self, Stream};
+use http::{header::CONTENT_TYPE, StatusCode, Uri};
+use serde::de::DeserializeOwned;
 use serde_derive::{Deserialize, Serialize};
 use serde_json;
+use tokio::time::sleep;
 
 /// An etcd server that is a member of a cluster.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -17,12 +22,17 @@ pub struct Member {
     /// An internal identifier for the cluster member.
     pub id: String,
     /// A human-readable name for the cluster member.
+    ///
+    /// This is empty for a member that has been added but has not started yet.
+    #[serde(default)]
     pub name: String,
     /// URLs exposing this cluster member's peer API.
     #[serde(rename = "peerURLs")]
     pub peer_urls: Vec<String>,
     /// URLs exposing this cluster member's client API.
-    #[serde(rename = "clientURLs")]
+    ///
+    /// This is empty for a member that has been added but has not started yet.
+    #[serde(default, rename = "clientURLs")]
     pub client_urls: Vec<String>,
 }
 
@@ -34,6 +44,13 @@ struct PeerUrls {
     peer_urls: Vec<String>,
 }
 
+/// The body of an error response from the members API.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+struct MembersError {
+    /// A description of the error.
+    message: String,
+}
+
 /// A small wrapper around `Member` to match the response of `GET /v2/members`.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 struct ListResponse {
@@ -41,15 +58,39 @@ struct ListResponse {
     members: Vec<Member>,
 }
 
+/// Options for customizing the behavior of `members::watch`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct WatchOptions {
+    /// How long to wait between requests for the member list.
+    pub interval: Duration,
+}
+
+impl Default for WatchOptions {
+    /// Polls the member list every 5 seconds.
+    fn default() -> Self {
+        WatchOptions {
+            interval: Duration::from_secs(5),
+        }
+    }
+}
+
 type EtcdMembersResult<T = ()> = Result<Response<T>, Vec<Error>>;
 
 /// Adds a new member to the cluster.
 ///
+/// On success, returns the new member with the ID etcd assigned to it, which the joining member
+/// needs in order to start.
+///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
 /// * peer_urls: URLs exposing this cluster member's peer API.
-pub async fn add(client: &Client, peer_urls: Vec<String>) -> EtcdMembersResult {
+///
+/// # Errors
+///
+/// Fails with `Error::InvalidPeerUrls` if any of the peer URLs is invalid and with
+/// `Error::PeerUrlsExist` if another member already uses one of the peer URLs.
+pub async fn add(client: &Client, peer_urls: Vec<String>) -> EtcdMembersResult<Member> {
     let peer_urls = PeerUrls { peer_urls };
     let body = serde_json::to_string(&peer_urls).map_err(|e| vec![e.into()])?;
 
@@ -57,9 +98,14 @@ pub async fn add(client: &Client, peer_urls: Vec<String>) -> EtcdMembersResult {
         .first_ok(|client, endpoint| {
             let body = body.clone();
             async move {
-                let url = build_url(endpoint, "");
-                let response = client.http_client().get(url).body(body).send().await?;
-                parse_empty_response(response).await
+                let url = build_url(&endpoint, "");
+                let request = client
+                    .http_client()
+                    .post(url)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body);
+                let response = client.send(request).await?;
+                parse_members_response(response).await
             }
         })
         .await
@@ -78,10 +124,10 @@ where
     let id = id.as_ref();
     client
         .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, &format!("/{}", id));
+            let url = build_url(&endpoint, &format!("/{}", id));
             async move {
-                let response = client.http_client().delete(url).send().await?;
-                parse_empty_response(response).await
+                let response = client.send(client.http_client().delete(url)).await?;
+                parse_members_response(response).await
             }
         })
         .await
@@ -89,19 +135,28 @@ where
 
 /// Lists the members of the cluster.
 ///
+/// The list is served from the local state of whichever member receives the request. Unlike
+/// `kv::get` with `GetOptions::strong_consistency`, etcd offers no way to make this read
+/// linearizable, so a member that was just added or removed may not be reflected by a member that
+/// lags behind the leader.
+///
 /// # Parameters
 ///
 /// * client: A `Client` to use to make the API call.
 pub async fn list(client: &Client) -> EtcdMembersResult<Vec<Member>> {
     client
-        .first_ok(|client, endpoint| async move {
-            let url = build_url(endpoint, "");
-            let response = client.http_client().get(url).send().await?;
+        .first_ok_idempotent(|client, endpoint| async move {
+            let url = build_url(&endpoint, "");
+            let response = client.send(client.http_client().get(url)).await?;
             let response: Response<ListResponse> =
                 parse_etcd_response(response, |s| s == StatusCode::OK).await?;
             Ok(Response {
                 cluster_info: response.cluster_info,
                 data: response.data.members,
+                endpoint: response.endpoint,
+                cached: response.cached,
+                dry_run: response.dry_run,
+                retries: response.retries,
             })
         })
         .await
@@ -114,22 +169,109 @@ pub async fn list(client: &Client) -> EtcdMembersResult<Vec<Member>> {
 /// * client: A `Client` to use to make the API call.
 /// * id: The unique identifier of the member to update.
 /// * peer_urls: URLs exposing this cluster member's peer API.
-pub async fn update(client: &Client, id: String, peer_urls: Vec<String>) -> EtcdMembersResult {
+///
+/// # Errors
+///
+/// Fails with `Error::InvalidPeerUrls` if any of the peer URLs is invalid, with
+/// `Error::MemberNotFound` if there is no member with the given ID, and with
+/// `Error::PeerUrlsExist` if another member already uses one of the peer URLs.
+pub async fn update<K>(client: &Client, id: K, peer_urls: Vec<String>) -> EtcdMembersResult
+where
+    K: AsRef<str>,
+{
+    let id = id.as_ref();
     let peer_urls = PeerUrls { peer_urls };
     let body = serde_json::to_string(&peer_urls).map_err(|e| vec![e.into()])?;
 
     client
         .first_ok(|client, endpoint| {
-            let url = build_url(endpoint, &format!("/{}", id));
+            let url = build_url(&endpoint, &format!("/{}", id));
             let body = body.clone();
             async move {
-                let response = client.http_client().put(url).body(body).send().await?;
-                parse_empty_response(response).await
+                let request = client
+                    .http_client()
+                    .put(url)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(body);
+                let response = client.send(request).await?;
+                parse_members_response(response).await
             }
         })
         .await
 }
 
+/// Watches the members of the cluster, yielding the member list whenever it changes.
+///
+/// etcd's members API does not support watches, so the member list is polled on the interval
+/// given in `options`. The first member list is always yielded. After that, a member list is only
+/// yielded if its members differ from the last one yielded, regardless of their order.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * options: Options to customize the behavior of the operation.
+///
+/// # Errors
+///
+/// Failed requests are yielded as errors, and polling continues afterwards.
+pub fn watch(
+    client: &Client,
+    options: WatchOptions,
+) -> impl Stream<Item = EtcdMembersResult<Vec<Member>>> {
+    let state = (client.clone(), None::<Vec<Member>>, false);
+
+    stream::unfold(state, move |(client, mut last, mut wait)| async move {
+        loop {
+            if wait {
+                sleep(options.interval).await;
+            }
+            wait = true;
+
+            match list(&client).await {
+                Ok(response) => {
+                    let mut members = response.data.clone();
+                    members.sort_by(|a, b| a.id.cmp(&b.id));
+
+                    if last.as_ref() != Some(&members) {
+                        last = Some(members);
+                        return Some((Ok(response), (client, last, wait)));
+                    }
+                }
+                Err(errors) => return Some((Err(errors), (client, last, wait))),
+            }
+        }
+    })
+}
+
+/// Parses the response of a members API call that changes a member.
+///
+/// An empty response body is parsed as `()`.
+async fn parse_members_response<T>(response: reqwest::Response) -> Result<Response<T>, Error>
+where
+    T: DeserializeOwned,
+{
+    let status_code = response.status();
+    let cluster_info = ClusterInfo::from(response.headers());
+    let endpoint = response_endpoint(&response)?;
+    let body = read_body(response).await?;
+
+    let message = || parse_json_body::<MembersError>(&body).map(|error| error.message);
+    match status_code {
+        StatusCode::CREATED | StatusCode::NO_CONTENT | StatusCode::OK => Ok(Response {
+            data: parse_json_body(if body.is_empty() { b"null" } else { &body })?,
+            cluster_info,
+            endpoint,
+            cached: false,
+            dry_run: false,
+            retries: 0,
+        }),
+        StatusCode::BAD_REQUEST => Err(Error::InvalidPeerUrls(message()?)),
+        StatusCode::NOT_FOUND => Err(Error::MemberNotFound(message()?)),
+        StatusCode::CONFLICT => Err(Error::PeerUrlsExist(message()?)),
+        _ => Err(Error::UnexpectedStatus(status_code)),
+    }
+}
+
 /// Constructs the full URL for an API call.
 fn build_url(endpoint: &Uri, path: &str) -> String {
     format!("{}v2/members{}", endpoint, path)
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use url::form_urlencoded::Serializer;
 
 use bytes::Bytes;
@@ -29,17 +31,23 @@ pub struct DeleteOptions<'a> {
     pub dir: Option<bool>,
     /// Whether or not keys within a directory should be deleted recursively.
     pub recursive: Option<bool>,
+    /// A timeout for the request, overriding the client's request timeout.
+    pub timeout: Option<Duration>,
 }
 
 /// Controls the various different ways a get operation can be performed.
 #[derive(Debug, Default)]
 pub struct GetOptions {
+    /// Whether or not to ask etcd to leave values out of the response.
+    pub no_value: bool,
     /// Whether or not to use read linearization to avoid stale data.
     pub strong_consistency: bool,
     /// Whether or not keys within a directory should be included in the response.
     pub recursive: bool,
     /// Whether or not directory contents will be sorted within the response.
     pub sort: Option<bool>,
+    /// A timeout for the request, overriding the client's request timeout.
+    pub timeout: Option<Duration>,
     /// Whether or not to wait for a change.
     pub wait: bool,
     /// The etcd index to use as a lower bound when watching a key.
@@ -57,6 +65,8 @@ pub struct SetOptions<'a> {
     pub dir: Option<bool>,
     /// Whether or not the key being operated on must already exist.
     pub prev_exist: Option<bool>,
+    /// A timeout for the request, overriding the client's request timeout.
+    pub timeout: Option<Duration>,
     /// Time to live in seconds.
     pub ttl: Option<u64>,
     /// New value for the key.
@@ -71,6 +81,10 @@ impl GetOptions {
 
         serializer.append_pair("recursive", bool_to_str(self.recursive));
 
+        if self.strong_consistency {
+            serializer.append_pair("quorum", bool_to_str(true));
+        }
+
         if let Some(sort) = self.sort {
             serializer.append_pair("sorted", bool_to_str(sort));
         }
@@ -83,6 +97,10 @@ impl GetOptions {
             serializer.append_pair("waitIndex", &wait_index.to_string());
         }
 
+        if self.no_value {
+            serializer.append_pair("noValueOnSuccess", bool_to_str(true));
+        }
+
         serializer.finish()
     }
 }
@@ -0,0 +1,226 @@
+//! Single-leader election.
+//!
+//! Each candidate creates an in-order node in the election's directory, holding its nomination,
+//! such as its address. The candidate whose node is first in order is the leader. When the leader
+//! resigns or stops refreshing its node, the next candidate in order becomes the leader.
+
+use futures_util::stream::{self, Stream};
+
+use crate::{
+    kv::{self, GetOptions, WatchOptions},
+    Client, Error,
+};
+
+use super::{
+    delete_in_background, delete_node, is_node_gone, wait_until_first, watch_errors, KeepAlive,
+};
+
+/// The current leader of an election.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Leader {
+    /// The name of the leader's node.
+    pub key: String,
+    /// The nomination the leader campaigned with.
+    pub nomination: String,
+}
+
+/// The leadership of an election won with `election::campaign`.
+///
+/// Leadership is held until `Leadership::resign` is called or the `Leadership` is dropped.
+#[derive(Debug)]
+pub struct Leadership {
+    client: Client,
+    keep_alive: KeepAlive,
+    key: String,
+    nomination: String,
+    resigned: bool,
+}
+
+impl Leadership {
+    /// Returns the name of the node that holds the leadership.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns the nomination this process campaigned with.
+    pub fn nomination(&self) -> &str {
+        &self.nomination
+    }
+
+    /// Returns whether this process is still the leader.
+    ///
+    /// This becomes `false` if the leadership node was deleted, or if it could not be refreshed
+    /// for a whole TTL, such as during a network partition. Another candidate may then have become
+    /// the leader, so the process should stop acting as the leader.
+    pub fn is_leader(&self) -> bool {
+        self.keep_alive.is_alive()
+    }
+
+    /// Steps down as the leader by deleting the leadership node, letting the next candidate take
+    /// over.
+    pub async fn resign(mut self) -> Result<(), Vec<Error>> {
+        self.resigned = true;
+        delete_node(&self.client, &self.key, self.nomination.clone()).await
+    }
+}
+
+impl Drop for Leadership {
+    fn drop(&mut self) {
+        if !self.resigned {
+            delete_in_background(&self.client, &self.key, &self.nomination);
+        }
+    }
+}
+
+/// Campaigns to become the leader of the election in the directory `path`, waiting until this
+/// process is elected.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * path: The name of the directory to use for the election. All candidates in the same
+///   election must use the same directory.
+/// * nomination: A value identifying this candidate to observers, such as its address.
+/// * ttl: The number of seconds after which leadership is lost if the leader stops refreshing
+///   it, such as when it crashes. This must be at least one second.
+///
+/// # Cancellation
+///
+/// If the returned future is dropped before this process is elected, its candidacy is withdrawn
+/// in the background.
+///
+/// # Errors
+///
+/// Fails if any of the API calls fail, or with `Error::KeyNotFound` if this process's node was
+/// deleted while waiting, such as because it could not be refreshed in time.
+///
+/// # Panics
+///
+/// Panics if `ttl` is zero, or if not called within a Tokio runtime.
+pub async fn campaign<P, N>(
+    client: &Client,
+    path: P,
+    nomination: N,
+    ttl: u64,
+) -> Result<Leadership, Vec<Error>>
+where
+    P: AsRef<str>,
+    N: Into<String>,
+{
+    if ttl == 0 {
+        panic!("invariant: election TTL must be at least one second")
+    }
+
+    let path = path.as_ref();
+    let nomination = nomination.into();
+    let response = kv::create_in_order(client, path, &nomination, Some(ttl)).await?;
+    let key = response
+        .data
+        .node
+        .key
+        .expect("invariant: created node has no key");
+
+    // From here on, dropping the leadership deletes the node, including when this future is
+    // dropped.
+    let leadership = Leadership {
+        client: client.clone(),
+        keep_alive: KeepAlive::start(client, key.clone(), ttl),
+        key,
+        nomination,
+        resigned: false,
+    };
+
+    wait_until_first(client, path, &leadership.key).await?;
+    Ok(leadership)
+}
+
+/// Observes the election in the directory `path`, yielding the current leader whenever it
+/// changes.
+///
+/// The current leader is always yielded first. `None` is yielded when there is no leader, such as
+/// before any candidate has campaigned.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * path: The name of the directory used for the election.
+///
+/// # Errors
+///
+/// The stream yields the error and ends if a request fails.
+pub fn observe<P>(
+    client: &Client,
+    path: P,
+) -> impl Stream<Item = Result<Option<Leader>, Vec<Error>>>
+where
+    P: AsRef<str>,
+{
+    let state = Some((client.clone(), path.as_ref().to_string(), None, true));
+
+    stream::unfold(state, |state| async move {
+        let (client, path, mut last, mut first) = state?;
+
+        loop {
+            let (leader, index) = match current_leader(&client, &path).await {
+                Ok(current) => current,
+                Err(errors) => return Some((Err(errors), None)),
+            };
+
+            if first || leader != last {
+                first = false;
+                last = leader.clone();
+                return Some((Ok(leader), Some((client, path, last, first))));
+            }
+
+            let options = WatchOptions {
+                index: Some(index + 1),
+                recursive: true,
+                // Only the change itself matters, since the leader is read again afterwards.
+                without_values: true,
+                timeout: None,
+            };
+            match kv::watch(&client, &path, options).await {
+                Ok(_) => {}
+                Err(ref error) if kv::cleared_index(error).is_some() => {}
+                Err(error) => return Some((Err(watch_errors(error)), None)),
+            }
+        }
+    })
+}
+
+/// Returns the current leader of the election in `path`, and the etcd index it was read at.
+async fn current_leader(client: &Client, path: &str) -> Result<(Option<Leader>, u64), Vec<Error>> {
+    let options = GetOptions {
+        sort: true,
+        ..Default::default()
+    };
+
+    match kv::uncached_get(client, path, options).await {
+        Ok(response) => {
+            let index = response.cluster_info.etcd_index.unwrap_or_default();
+            let leader = response
+                .data
+                .node
+                .nodes
+                .unwrap_or_default()
+                .into_iter()
+                .next()
+                .map(|node| Leader {
+                    key: node.key.unwrap_or_default(),
+                    nomination: node.value.unwrap_or_default(),
+                });
+
+            Ok((leader, index))
+        }
+        // No candidate has campaigned yet.
+        Err(ref errors) if errors.iter().any(is_node_gone) => {
+            let index = errors
+                .iter()
+                .find_map(Error::api_error)
+                .map(|error| error.index)
+                .unwrap_or_default();
+            Ok((None, index))
+        }
+        Err(errors) => Err(errors),
+    }
+}
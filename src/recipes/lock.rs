@@ -0,0 +1,107 @@
+//! A distributed lock.
+//!
+//! Each process trying to acquire the lock creates an in-order node in the lock's directory. The
+//! process whose node is first in order holds the lock, and each other process waits for the node
+//! just ahead of its own to be deleted, so waiters acquire the lock in the order they asked for it.
+
+use crate::{kv, Client, Error};
+
+use super::{delete_in_background, delete_node, unique_value, wait_until_first, KeepAlive};
+
+/// A held distributed lock.
+///
+/// The lock is held until `Lock::release` is called or the `Lock` is dropped. Its node is refreshed
+/// in the background, so if the process crashes, the lock is released once the TTL lapses.
+#[derive(Debug)]
+pub struct Lock {
+    client: Client,
+    keep_alive: KeepAlive,
+    key: String,
+    released: bool,
+    value: String,
+}
+
+impl Lock {
+    /// Acquires the lock for the directory `key`, waiting until it is available.
+    ///
+    /// # Parameters
+    ///
+    /// * client: A `Client` to use to make the API calls.
+    /// * key: The name of the directory to use for the lock. All processes using the same lock
+    ///   must use the same directory.
+    /// * ttl: The number of seconds after which the lock is released if the holder stops
+    ///   refreshing it, such as when it crashes. This must be at least one second.
+    ///
+    /// # Cancellation
+    ///
+    /// If the returned future is dropped before the lock is acquired, this process's node is
+    /// deleted in the background, so it does not hold up other waiters.
+    ///
+    /// # Errors
+    ///
+    /// Fails if any of the API calls fail, or with `Error::KeyNotFound` if this process's node
+    /// was deleted while waiting, such as because it could not be refreshed in time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ttl` is zero, or if not called within a Tokio runtime.
+    pub async fn acquire<K>(client: &Client, key: K, ttl: u64) -> Result<Lock, Vec<Error>>
+    where
+        K: AsRef<str>,
+    {
+        if ttl == 0 {
+            panic!("invariant: lock TTL must be at least one second")
+        }
+
+        let key = key.as_ref();
+        let value = unique_value();
+        let response = kv::create_in_order(client, key, &value, Some(ttl)).await?;
+        let node_key = response
+            .data
+            .node
+            .key
+            .expect("invariant: created node has no key");
+
+        // From here on, dropping the lock deletes the node, including when this future is dropped.
+        let lock = Lock {
+            client: client.clone(),
+            keep_alive: KeepAlive::start(client, node_key.clone(), ttl),
+            key: node_key,
+            released: false,
+            value,
+        };
+
+        wait_until_first(client, key, &lock.key).await?;
+        Ok(lock)
+    }
+
+    /// Returns the name of the node that holds the lock for this process.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns whether the lock is still held.
+    ///
+    /// This becomes `false` if the lock's node was deleted, or if it could not be refreshed for a
+    /// whole TTL, such as during a network partition. Another process may then acquire the lock.
+    pub fn is_held(&self) -> bool {
+        self.keep_alive.is_alive()
+    }
+
+    /// Releases the lock by deleting the node that holds it.
+    ///
+    /// Only the node created by this process is deleted, so if the lock has already been lost,
+    /// this does not affect whichever process holds it now.
+    pub async fn release(mut self) -> Result<(), Vec<Error>> {
+        self.released = true;
+        delete_node(&self.client, &self.key, self.value.clone()).await
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if !self.released {
+            delete_in_background(&self.client, &self.key, &self.value);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+//! Higher-level coordination primitives built on etcd's key-value API.
+//!
+//! These recipes follow the patterns recommended for etcd's v2 API. Each participant creates its
+//! own node with a TTL, which is refreshed in the background for as long as the participant is
+//! alive. If the process holding a node crashes, the refreshes stop and etcd deletes the node once
+//! its TTL lapses, so other participants are never blocked forever.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use futures_util::StreamExt;
+use rand::{thread_rng, Rng};
+use tokio::task::JoinHandle;
+
+use crate::{
+    kv::{self, GetOptions, WatchError},
+    Client, Error,
+};
+
+pub mod election;
+pub mod lock;
+
+/// Keeps a node alive by refreshing its TTL in a background task until dropped.
+#[derive(Debug)]
+pub(crate) struct KeepAlive {
+    /// Whether the node is still known to exist.
+    alive: Arc<AtomicBool>,
+    /// The background task refreshing the node.
+    task: JoinHandle<()>,
+}
+
+impl KeepAlive {
+    /// Starts refreshing the node at `key` every third of its TTL.
+    ///
+    /// The node is considered lost if it no longer exists, or if it could not be refreshed for a
+    /// whole TTL, after which etcd will have expired it.
+    pub(crate) fn start(client: &Client, key: String, ttl: u64) -> Self {
+        let alive = Arc::new(AtomicBool::new(true));
+        let task_alive = alive.clone();
+        let client = client.clone();
+        let ttl_duration = Duration::from_secs(ttl);
+
+        let task = tokio::spawn(async move {
+            let refreshes = kv::keep_alive(&client, &key, ttl, None);
+            futures_util::pin_mut!(refreshes);
+            let mut last_refresh = Instant::now();
+
+            while let Some(result) = refreshes.next().await {
+                match result {
+                    Ok(()) => last_refresh = Instant::now(),
+                    Err(ref errors) if errors.iter().any(is_node_gone) => break,
+                    Err(_) if last_refresh.elapsed() >= ttl_duration => break,
+                    Err(_) => {}
+                }
+            }
+
+            task_alive.store(false, Ordering::SeqCst);
+        });
+
+        KeepAlive { alive, task }
+    }
+
+    /// Returns whether the node is still known to exist.
+    pub(crate) fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Returns whether an error shows that a node no longer exists.
+fn is_node_gone(error: &Error) -> bool {
+    matches!(*error, Error::KeyNotFound(_))
+}
+
+/// Returns the errors of a failed watch or wait, with a timeout as `Error::Timeout`.
+fn watch_errors(error: WatchError) -> Vec<Error> {
+    match error {
+        WatchError::Other(errors) => errors,
+        WatchError::Timeout => vec![Error::Timeout],
+    }
+}
+
+/// Returns a random value that uniquely identifies a node created by this process.
+fn unique_value() -> String {
+    format!("{:032x}", thread_rng().gen::<u128>())
+}
+
+/// Deletes the node at `key` if it still has the given value, in the background if possible.
+///
+/// This is used when a recipe's handle is dropped, which cannot wait for the deletion.
+fn delete_in_background(client: &Client, key: &str, value: &str) {
+    let handle = match tokio::runtime::Handle::try_current() {
+        Ok(handle) => handle,
+        // Without a runtime, the node is left to expire.
+        Err(_) => return,
+    };
+
+    let client = client.clone();
+    let key = key.to_string();
+    let value = value.to_string();
+
+    handle.spawn(async move {
+        let _ = delete_node(&client, &key, value).await;
+    });
+}
+
+/// Deletes the node at `key` only if it still has the given value.
+async fn delete_node(client: &Client, key: &str, value: String) -> Result<(), Vec<Error>> {
+    let options = kv::CompareAndDeleteOptions {
+        prev_value: Some(value),
+        prev_index: None,
+        ..Default::default()
+    };
+
+    match kv::compare_and_delete(client, key, options).await {
+        Ok(_) => Ok(()),
+        // The node has already expired or been replaced, so there is nothing to delete.
+        Err(ref errors) if errors.iter().any(is_node_gone) => Ok(()),
+        Err(errors) => Err(errors),
+    }
+}
+
+/// Waits until the node at `key` is the first in order in the directory `dir`.
+///
+/// Fails with `Error::KeyNotFound` if the node no longer exists.
+async fn wait_until_first(client: &Client, dir: &str, key: &str) -> Result<(), Vec<Error>> {
+    loop {
+        let options = GetOptions {
+            sort: true,
+            ..Default::default()
+        };
+        let response = kv::uncached_get(client, dir, options).await?;
+        let nodes = response.data.node.nodes.unwrap_or_default();
+
+        let position = match nodes
+            .iter()
+            .position(|node| node.key.as_deref() == Some(key))
+        {
+            Some(position) => position,
+            // Confirm the node is missing, which fails with `Error::KeyNotFound`.
+            None => {
+                kv::uncached_get(client, key, GetOptions::default()).await?;
+                continue;
+            }
+        };
+
+        let previous = match position.checked_sub(1) {
+            Some(previous) => &nodes[previous],
+            None => return Ok(()),
+        };
+        let previous_key = previous
+            .key
+            .as_deref()
+            .expect("invariant: listed node has no key");
+
+        // Wait for the node ahead to be deleted, and then check again, since the nodes further
+        // ahead may still exist.
+        kv::wait_delete(client, previous_key, None)
+            .await
+            .map_err(watch_errors)?;
+    }
+}
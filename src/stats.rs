@@ -1,18 +1,29 @@
 //! etcd's statistics API.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
+use futures_util::stream::{self, Stream};
+use serde::{de::Error as _, Deserialize, Deserializer};
 use serde_derive::{Deserialize, Serialize};
+use tokio::time::sleep;
 
 use crate::client::{Client, Response};
 use crate::error::Error;
 
 /// Statistics about an etcd cluster leader.
+///
+/// Fields that etcd adds in later versions are ignored, and statistics missing from the response
+/// default to zero, so that parsing does not fail across etcd versions. etcd does not include the
+/// leader's uptime in these statistics; it is reported by each member in `SelfStats::leader_info`.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct LeaderStats {
     /// A unique identifier of a leader member.
     pub leader: String,
     /// Statistics for each peer in the cluster keyed by each peer's unique identifier.
+    ///
+    /// A follower that has been removed from the cluster no longer appears in this map. This is
+    /// empty in a cluster with a single member.
+    #[serde(default)]
     pub followers: HashMap<String, FollowerStats>,
 }
 
@@ -20,13 +31,16 @@ pub struct LeaderStats {
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct FollowerStats {
     /// Counts of Raft RPC request successes and failures to this follower.
+    #[serde(default)]
     pub counts: CountStats,
     /// Latency statistics for this follower.
+    #[serde(default)]
     pub latency: LatencyStats,
 }
 
 /// Statistics about the number of successful and failed Raft RPC requests to an etcd node.
-#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(default)]
 pub struct CountStats {
     /// The number of times an RPC request to the node failed.
     pub fail: u64,
@@ -35,7 +49,8 @@ pub struct CountStats {
 }
 
 /// Statistics about the network latency to an etcd node.
-#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
 pub struct LatencyStats {
     /// The average observed latency to the node, in seconds.
     pub average: f64,
@@ -64,19 +79,27 @@ pub struct SelfStats {
     #[serde(rename = "recvAppendRequestCnt")]
     pub received_append_request_count: u64,
     /// The bandwidth rate of received requests.
-    #[serde(rename = "recvBandwidthRate")]
+    #[serde(
+        rename = "recvBandwidthRate",
+        default,
+        deserialize_with = "deserialize_rate"
+    )]
     pub received_bandwidth_rate: Option<f64>,
-    #[serde(rename = "recvPkgRate")]
+    #[serde(rename = "recvPkgRate", default, deserialize_with = "deserialize_rate")]
     /// The package rate of received requests.
     pub received_package_rate: Option<f64>,
     /// The number of sent requests.
     #[serde(rename = "sendAppendRequestCnt")]
     pub sent_append_request_count: u64,
     /// The bandwidth rate of sent requests.
-    #[serde(rename = "sendBandwidthRate")]
+    #[serde(
+        rename = "sendBandwidthRate",
+        default,
+        deserialize_with = "deserialize_rate"
+    )]
     pub sent_bandwidth_rate: Option<f64>,
     /// The package rate of sent requests.
-    #[serde(rename = "sendPkgRate")]
+    #[serde(rename = "sendPkgRate", default, deserialize_with = "deserialize_rate")]
     pub sent_package_rate: Option<f64>,
     /// The time the member started.
     #[serde(rename = "startTime")]
@@ -85,6 +108,30 @@ pub struct SelfStats {
     pub state: String,
 }
 
+/// A rate in `SelfStats`, which some etcd builds send as a string rather than a number.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Rate {
+    Number(f64),
+    Text(String),
+}
+
+/// Deserializes a rate in `SelfStats` from either a number or a string containing a number.
+fn deserialize_rate<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<Rate>::deserialize(deserializer)? {
+        Some(Rate::Number(rate)) => Ok(Some(rate)),
+        Some(Rate::Text(ref rate)) if rate.is_empty() => Ok(None),
+        Some(Rate::Text(rate)) => rate
+            .parse()
+            .map(Some)
+            .map_err(|_| D::Error::custom(format!("invalid rate: {}", rate))),
+        None => Ok(None),
+    }
+}
+
 /// A small amount of information about the leader of the cluster.
 #[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub struct LeaderInfo {
@@ -150,6 +197,87 @@ pub struct StoreStats {
     pub watchers: u64,
 }
 
+impl StoreStats {
+    /// Returns the fraction of get operations that succeeded, from 0 to 1, or `None` if there
+    /// were no get operations.
+    pub fn get_success_ratio(&self) -> Option<f64> {
+        success_ratio(self.get_success, self.get_fail)
+    }
+
+    /// Returns the fraction of set operations that succeeded, from 0 to 1, or `None` if there
+    /// were no set operations.
+    pub fn set_success_ratio(&self) -> Option<f64> {
+        success_ratio(self.set_success, self.set_fail)
+    }
+
+    /// Returns the total number of successful and failed operations of every kind.
+    ///
+    /// Expirations are not included, since they are not requested by clients.
+    pub fn total_operations(&self) -> u64 {
+        [
+            self.compare_and_delete_fail,
+            self.compare_and_delete_success,
+            self.compare_and_swap_fail,
+            self.compare_and_swap_success,
+            self.create_fail,
+            self.create_success,
+            self.delete_fail,
+            self.delete_success,
+            self.get_fail,
+            self.get_success,
+            self.set_fail,
+            self.set_success,
+            self.update_fail,
+            self.update_success,
+        ]
+        .iter()
+        .fold(0, |total, count| total.saturating_add(*count))
+    }
+
+    /// Returns the number of operations handled since an earlier sample of the same member's
+    /// statistics, such as one yielded by `stats::store_stats_stream`.
+    ///
+    /// Each operation count is the difference between the two samples. Counts that went down,
+    /// such as because the member restarted, are zero. `watchers` is not a count, so it is the
+    /// current number of watchers.
+    pub fn delta(&self, earlier: &StoreStats) -> StoreStats {
+        StoreStats {
+            compare_and_delete_fail: self
+                .compare_and_delete_fail
+                .saturating_sub(earlier.compare_and_delete_fail),
+            compare_and_delete_success: self
+                .compare_and_delete_success
+                .saturating_sub(earlier.compare_and_delete_success),
+            compare_and_swap_fail: self
+                .compare_and_swap_fail
+                .saturating_sub(earlier.compare_and_swap_fail),
+            compare_and_swap_success: self
+                .compare_and_swap_success
+                .saturating_sub(earlier.compare_and_swap_success),
+            create_fail: self.create_fail.saturating_sub(earlier.create_fail),
+            create_success: self.create_success.saturating_sub(earlier.create_success),
+            delete_fail: self.delete_fail.saturating_sub(earlier.delete_fail),
+            delete_success: self.delete_success.saturating_sub(earlier.delete_success),
+            expire_count: self.expire_count.saturating_sub(earlier.expire_count),
+            get_fail: self.get_fail.saturating_sub(earlier.get_fail),
+            get_success: self.get_success.saturating_sub(earlier.get_success),
+            set_fail: self.set_fail.saturating_sub(earlier.set_fail),
+            set_success: self.set_success.saturating_sub(earlier.set_success),
+            update_fail: self.update_fail.saturating_sub(earlier.update_fail),
+            update_success: self.update_success.saturating_sub(earlier.update_success),
+            watchers: self.watchers,
+        }
+    }
+}
+
+/// Returns the fraction of operations that succeeded, or `None` if there were none.
+fn success_ratio(success: u64, fail: u64) -> Option<f64> {
+    match success.saturating_add(fail) {
+        0 => None,
+        total => Some(success as f64 / total as f64),
+    }
+}
+
 /// Returns statistics about the leader member of a cluster.
 ///
 /// Fails if JSON decoding fails, which suggests a bug in our schema.
@@ -173,3 +301,90 @@ pub async fn self_stats(client: &Client) -> VecResultResponse<SelfStats> {
 pub async fn store_stats(client: &Client) -> VecResultResponse<StoreStats> {
     client.request_on_each_endpoint("v2/stats/store").await
 }
+
+/// Samples the statistics about operations handled by each etcd member the client was
+/// initialized with, periodically.
+///
+/// The first sample is taken right away, and each following one `interval` after the previous one
+/// completed. Each sample is the result of `stats::store_stats`, with one result for each
+/// endpoint, in the order the endpoints were given. Use `StoreStats::delta` to compute the number
+/// of operations between two samples of a member. Sampling stops when the stream is dropped.
+///
+/// # Panics
+///
+/// Panics if `interval` is zero.
+pub fn store_stats_stream(
+    client: &Client,
+    interval: Duration,
+) -> impl Stream<Item = VecResultResponse<StoreStats>> {
+    if interval == Duration::from_secs(0) {
+        panic!("invariant: store stats interval must be greater than zero")
+    }
+
+    let state = (client.clone(), true);
+
+    stream::unfold(state, move |(client, first)| async move {
+        if !first {
+            sleep(interval).await;
+        }
+        let sample = store_stats(&client).await;
+        Some((sample, (client, false)))
+    })
+}
+
+/// Polls the leader of the cluster periodically, yielding the unique identifier of the leader each
+/// time it changes.
+///
+/// The current leader is yielded first, and after that, a leader is only yielded when it differs
+/// from the previous one. The leader is checked every `interval` with `stats::leader_stats`.
+/// Polling stops when the stream is dropped.
+///
+/// # Errors
+///
+/// If a poll fails, such as because no member can be reached, the error is yielded and polling
+/// continues. Only the first error of consecutive failed polls is yielded, so that an outage is
+/// reported once rather than on every poll.
+///
+/// # Panics
+///
+/// Panics if `interval` is zero.
+pub fn watch_leader(
+    client: &Client,
+    interval: Duration,
+) -> impl Stream<Item = Result<String, Error>> {
+    if interval == Duration::from_secs(0) {
+        panic!("invariant: leader poll interval must be greater than zero")
+    }
+
+    // The client, the last leader yielded, whether the last poll failed, and whether this is the
+    // first poll.
+    let state = (client.clone(), None::<String>, false, true);
+
+    stream::unfold(
+        state,
+        move |(client, mut last, mut failing, mut first)| async move {
+            loop {
+                if !first {
+                    sleep(interval).await;
+                }
+                first = false;
+
+                match leader_stats(&client).await {
+                    Ok(response) => {
+                        failing = false;
+                        let leader = response.data.leader;
+                        if last.as_ref() != Some(&leader) {
+                            last = Some(leader.clone());
+                            return Some((Ok(leader), (client, last, failing, first)));
+                        }
+                    }
+                    Err(error) if !failing => {
+                        failing = true;
+                        return Some((Err(error), (client, last, failing, first)));
+                    }
+                    Err(_) => {}
+                }
+            }
+        },
+    )
+}
@@ -0,0 +1,92 @@
+//! Tracing spans for requests, enabled by the `tracing` feature.
+
+use tracing::{field::Empty, Span};
+
+use crate::client::RequestEvent;
+use crate::error::ApiError;
+
+/// The span of the request that produced a response, stored in the response's extensions so the
+/// etcd error code can be recorded once the body is parsed.
+#[derive(Clone, Debug)]
+struct RequestSpan(Span);
+
+/// Creates the span for a request.
+///
+/// The span is named `etcd.request`. Its `otel.name` field, which OpenTelemetry bridges such as
+/// `tracing-opentelemetry` use as the span's name, is made of the API module and HTTP method, such
+/// as `etcd kv GET`.
+pub(crate) fn request_span(event: &RequestEvent) -> Span {
+    tracing::info_span!(
+        "etcd.request",
+        otel.name = %format!("etcd {} {}", module(&event.path), event.method),
+        otel.kind = "client",
+        otel.status_code = Empty,
+        http.method = %event.method,
+        http.status_code = Empty,
+        server.address = %event.endpoint,
+        url.path = %event.path,
+        etcd.error_code = Empty,
+    )
+}
+
+/// Records the outcome of a request on its span, and attaches the span to the response.
+pub(crate) fn record_response(
+    span: Span,
+    mut result: Result<reqwest::Response, reqwest::Error>,
+) -> Result<reqwest::Response, reqwest::Error> {
+    match result {
+        Ok(ref mut response) => {
+            let status = response.status();
+            span.record("http.status_code", status.as_u16());
+            if status.is_client_error() || status.is_server_error() {
+                span.record("otel.status_code", "ERROR");
+            }
+            response.extensions_mut().insert(RequestSpan(span));
+        }
+        Err(_) => {
+            span.record("otel.status_code", "ERROR");
+        }
+    }
+
+    result
+}
+
+/// Returns the span of the request that produced a response.
+pub(crate) fn response_span(response: &reqwest::Response) -> Option<Span> {
+    response
+        .extensions()
+        .get::<RequestSpan>()
+        .map(|span| span.0.clone())
+}
+
+/// Records an error returned by etcd on the span of the request that caused it.
+pub(crate) fn record_api_error(span: Option<Span>, error: &ApiError) {
+    if let Some(span) = span {
+        span.record("otel.status_code", "ERROR");
+        span.record("etcd.error_code", error.error_code);
+    }
+}
+
+/// Returns the name of the API module a request path belongs to.
+fn module(path: &str) -> &'static str {
+    let path = path
+        .strip_prefix("/v2")
+        .or_else(|| path.strip_prefix("/v3"))
+        .unwrap_or(path);
+
+    if path.starts_with("/keys") || path.starts_with("/kv") {
+        "kv"
+    } else if path.starts_with("/members") {
+        "members"
+    } else if path.starts_with("/auth") {
+        "auth"
+    } else if path.starts_with("/stats") {
+        "stats"
+    } else if path.starts_with("/health") {
+        "health"
+    } else if path.starts_with("/version") {
+        "version"
+    } else {
+        "request"
+    }
+}
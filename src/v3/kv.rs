@@ -0,0 +1,175 @@
+//! etcd's v3 key-value API.
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::client::{Client, Response};
+use crate::error::Error;
+
+use super::{
+    deserialize_int, dry_run_response, is_zero, prefix_end, serialize_bytes, KeyValue,
+    ResponseHeader,
+};
+
+/// A request for the keys in a range, for `v3::kv::range`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize)]
+pub struct RangeRequest {
+    /// The first key in the range.
+    #[serde(serialize_with = "serialize_bytes")]
+    pub key: Vec<u8>,
+    /// The end of the range, which is not included in it.
+    ///
+    /// Only `key` is read if this is empty. All keys greater than or equal to `key` are read if
+    /// this is a single zero byte. `RangeRequest::prefix` sets it to read the keys with a prefix.
+    #[serde(
+        serialize_with = "serialize_bytes",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub range_end: Vec<u8>,
+    /// The maximum number of keys to return, or zero for no limit.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub limit: i64,
+    /// The revision of the key-value store to read at, or zero for the latest revision.
+    ///
+    /// Reading a revision that has been compacted fails.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub revision: i64,
+}
+
+impl RangeRequest {
+    /// Creates a request for a single key.
+    pub fn new<K>(key: K) -> Self
+    where
+        K: Into<Vec<u8>>,
+    {
+        RangeRequest {
+            key: key.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a request for all keys that start with `prefix`.
+    ///
+    /// An empty prefix requests every key.
+    pub fn prefix<P>(prefix: P) -> Self
+    where
+        P: Into<Vec<u8>>,
+    {
+        let key = prefix.into();
+        let range_end = prefix_end(&key);
+
+        RangeRequest {
+            key,
+            range_end,
+            ..Default::default()
+        }
+    }
+}
+
+/// The keys in a range, returned by `v3::kv::range`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct RangeResponse {
+    /// Information about the state of the cluster.
+    #[serde(default)]
+    pub header: ResponseHeader,
+    /// The keys in the range, in ascending order, up to the request's limit.
+    #[serde(default)]
+    pub kvs: Vec<KeyValue>,
+    /// Whether there are more keys in the range than were returned, because of the request's
+    /// limit.
+    #[serde(default)]
+    pub more: bool,
+    /// The number of keys in the range, including those not returned because of the request's
+    /// limit.
+    #[serde(default, deserialize_with = "deserialize_int")]
+    pub count: i64,
+}
+
+/// A request to set the value of a key, for `v3::kv::put`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize)]
+pub struct PutRequest {
+    /// The key to set.
+    #[serde(serialize_with = "serialize_bytes")]
+    pub key: Vec<u8>,
+    /// The new value of the key.
+    #[serde(serialize_with = "serialize_bytes")]
+    pub value: Vec<u8>,
+    /// The ID of the lease to attach to the key, or zero for no lease.
+    ///
+    /// The key is deleted when the lease expires.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub lease: i64,
+    /// Whether to return the key-value pair from before the put in `PutResponse::prev_kv`.
+    #[serde(skip_serializing_if = "is_false")]
+    pub prev_kv: bool,
+}
+
+impl PutRequest {
+    /// Creates a request to set `key` to `value`, without a lease.
+    pub fn new<K, V>(key: K, value: V) -> Self
+    where
+        K: Into<Vec<u8>>,
+        V: Into<Vec<u8>>,
+    {
+        PutRequest {
+            key: key.into(),
+            value: value.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The result of setting the value of a key, returned by `v3::kv::put`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct PutResponse {
+    /// Information about the state of the cluster. Its `revision` is the revision created by the
+    /// put.
+    #[serde(default)]
+    pub header: ResponseHeader,
+    /// The key-value pair from before the put, if the request's `prev_kv` was set and the key
+    /// existed.
+    #[serde(default)]
+    pub prev_kv: Option<KeyValue>,
+}
+
+/// Reads the keys in a range.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * request: The range to read, and at which revision.
+///
+/// # Errors
+///
+/// Fails with `Error::V3` if etcd rejects the request, such as when reading a compacted revision.
+pub async fn range(
+    client: &Client,
+    request: RangeRequest,
+) -> Result<Response<RangeResponse>, Vec<Error>> {
+    super::request(client, "kv/range", &request, true).await
+}
+
+/// Sets the value of a key, creating it if it does not exist.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * request: The key and value to set, and the lease to attach to the key.
+///
+/// # Errors
+///
+/// Fails with `Error::V3` if etcd rejects the request, such as when the lease does not exist.
+pub async fn put(
+    client: &Client,
+    request: PutRequest,
+) -> Result<Response<PutResponse>, Vec<Error>> {
+    if client.is_dry_run() {
+        return Ok(dry_run_response(client, PutResponse::default()));
+    }
+
+    super::request(client, "kv/put", &request, false).await
+}
+
+/// Returns whether an optional flag is unset.
+fn is_false(value: &bool) -> bool {
+    !*value
+}
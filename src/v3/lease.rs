@@ -0,0 +1,148 @@
+//! etcd's v3 lease API.
+//!
+//! A lease expires unless it is kept alive within its TTL. Keys attached to a lease, by setting
+//! `PutRequest::lease`, are deleted when it expires, so they only exist for as long as the process
+//! that keeps the lease alive is running.
+
+use std::time::Duration;
+
+use futures_util::stream::{self, Stream};
+use serde_derive::{Deserialize, Serialize};
+use tokio::time::sleep;
+
+use crate::client::{Client, Response};
+use crate::error::Error;
+
+use super::{deserialize_int, stream_result, ResponseHeader, Status, StreamResult};
+
+/// The gRPC status code returned when a lease does not exist.
+const NOT_FOUND: i32 = 5;
+
+/// A newly granted lease, returned by `v3::lease::grant`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct LeaseGrantResponse {
+    /// Information about the state of the cluster.
+    #[serde(default)]
+    pub header: ResponseHeader,
+    /// The ID of the lease.
+    #[serde(rename = "ID", default, deserialize_with = "deserialize_int")]
+    pub id: i64,
+    /// The number of seconds after which the lease expires unless it is kept alive.
+    ///
+    /// This may be greater than the requested TTL, since etcd enforces a minimum TTL.
+    #[serde(rename = "TTL", default, deserialize_with = "deserialize_int")]
+    pub ttl: i64,
+}
+
+/// The result of keeping a lease alive, yielded by `v3::lease::keep_alive`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct LeaseKeepAliveResponse {
+    /// Information about the state of the cluster.
+    #[serde(default)]
+    pub header: ResponseHeader,
+    /// The ID of the lease.
+    #[serde(rename = "ID", default, deserialize_with = "deserialize_int")]
+    pub id: i64,
+    /// The number of seconds after which the lease now expires unless it is kept alive again.
+    #[serde(rename = "TTL", default, deserialize_with = "deserialize_int")]
+    pub ttl: i64,
+}
+
+/// The body of a lease grant request.
+#[derive(Serialize)]
+struct LeaseGrantRequest {
+    #[serde(rename = "TTL")]
+    ttl: i64,
+}
+
+/// The body of a lease keep-alive request.
+#[derive(Serialize)]
+struct LeaseKeepAliveRequest {
+    #[serde(rename = "ID")]
+    id: i64,
+}
+
+/// Grants a new lease.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * ttl: The number of seconds after which the lease expires unless it is kept alive.
+///
+/// # Errors
+///
+/// Fails with `Error::V3` if etcd rejects the request, such as when `ttl` is too large.
+pub async fn grant(client: &Client, ttl: i64) -> Result<Response<LeaseGrantResponse>, Vec<Error>> {
+    super::request(client, "lease/grant", &LeaseGrantRequest { ttl }, false).await
+}
+
+/// Keeps a lease alive by refreshing it periodically, yielding the result of each refresh.
+///
+/// Each refresh resets the lease's TTL, which is given in the yielded `LeaseKeepAliveResponse`.
+/// Refreshing stops when the stream is dropped.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API calls.
+/// * id: The ID of the lease, returned by `v3::lease::grant`.
+/// * interval: How long to wait before each refresh. Defaults to refreshing right away, and then
+///   after a third of the TTL granted by the previous refresh, so that a failed refresh can be
+///   retried before the lease expires.
+///
+/// # Errors
+///
+/// Failed refreshes are yielded as errors, and refreshing continues afterwards. This includes
+/// `Error::V3` with gRPC status code 5 (not found) if the lease has expired or been revoked, which
+/// the caller may want to react to by granting a new lease.
+///
+/// # Panics
+///
+/// Panics if `interval` is zero.
+pub fn keep_alive(
+    client: &Client,
+    id: i64,
+    interval: Option<Duration>,
+) -> impl Stream<Item = Result<Response<LeaseKeepAliveResponse>, Vec<Error>>> {
+    if interval == Some(Duration::from_secs(0)) {
+        panic!("invariant: keep-alive interval must be greater than zero")
+    }
+
+    // The first refresh is made right away. Until one has succeeded, failed refreshes are retried
+    // every second.
+    let state = (client.clone(), Duration::from_secs(0));
+
+    stream::unfold(state, move |(client, wait)| async move {
+        sleep(interval.unwrap_or(wait)).await;
+
+        let result = refresh(&client, id).await;
+        let wait = match result {
+            Ok(ref response) => Duration::from_secs(response.data.ttl as u64) / 3,
+            Err(_) if wait == Duration::from_secs(0) => Duration::from_secs(1),
+            Err(_) => wait,
+        };
+
+        Some((result, (client, wait)))
+    })
+}
+
+/// Refreshes a lease once.
+async fn refresh(client: &Client, id: i64) -> Result<Response<LeaseKeepAliveResponse>, Vec<Error>> {
+    let response: Response<StreamResult<LeaseKeepAliveResponse>> = super::request(
+        client,
+        "lease/keepalive",
+        &LeaseKeepAliveRequest { id },
+        true,
+    )
+    .await?;
+    let response = stream_result(response).map_err(|error| vec![error])?;
+
+    // etcd reports a lease that does not exist with a TTL of zero, rather than an error.
+    if response.data.ttl <= 0 {
+        return Err(vec![Error::V3(Status {
+            code: NOT_FOUND,
+            message: "etcdserver: requested lease not found".to_string(),
+        })]);
+    }
+
+    Ok(response)
+}
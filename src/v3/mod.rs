@@ -0,0 +1,303 @@
+//! etcd's v3 API, through its JSON gateway.
+//!
+//! The v3 API is separate from the v2 API: its keys are arbitrary bytes in a flat key space, and
+//! changes are tracked by a cluster-wide revision instead of an index. Data written with one API
+//! is not visible to the other, so the types in this module are kept apart from those of the `kv`
+//! module, and both APIs can be used with the same `Client`.
+//!
+//! Requests are sent to the gRPC gateway that etcd serves on its client URLs, under the `/v3`
+//! prefix, which requires etcd 3.4 or later. The gateway encodes keys and values in base64, which
+//! this module handles transparently.
+
+use std::error::Error as StdError;
+use std::fmt::{Display, Error as FmtError, Formatter};
+
+use bytes::Bytes;
+use http::{header::CONTENT_TYPE, StatusCode, Uri};
+use serde::{
+    de::DeserializeOwned, de::Error as _, Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::client::{parse_json_body, read_body, response_endpoint, Client, ClusterInfo, Response};
+use crate::error::Error;
+
+pub mod kv;
+pub mod lease;
+pub mod watch;
+
+/// Information about the state of the cluster, included in every v3 response.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct ResponseHeader {
+    /// The ID of the cluster that sent the response.
+    #[serde(default, deserialize_with = "deserialize_int")]
+    pub cluster_id: u64,
+    /// The ID of the member that sent the response.
+    #[serde(default, deserialize_with = "deserialize_int")]
+    pub member_id: u64,
+    /// The revision of the key-value store when the request was handled.
+    #[serde(default, deserialize_with = "deserialize_int")]
+    pub revision: i64,
+    /// The Raft term when the request was handled.
+    #[serde(default, deserialize_with = "deserialize_int")]
+    pub raft_term: u64,
+}
+
+/// A key and its value in the v3 key-value store.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct KeyValue {
+    /// The key.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_bytes",
+        serialize_with = "serialize_bytes"
+    )]
+    pub key: Vec<u8>,
+    /// The revision at which the key was last created.
+    #[serde(default, deserialize_with = "deserialize_int")]
+    pub create_revision: i64,
+    /// The revision at which the key was last modified.
+    #[serde(default, deserialize_with = "deserialize_int")]
+    pub mod_revision: i64,
+    /// The number of times the key has been modified since it was last created. Deleting the key
+    /// resets it to zero.
+    #[serde(default, deserialize_with = "deserialize_int")]
+    pub version: i64,
+    /// The value.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_bytes",
+        serialize_with = "serialize_bytes"
+    )]
+    pub value: Vec<u8>,
+    /// The ID of the lease attached to the key, or zero if there is none.
+    #[serde(default, deserialize_with = "deserialize_int")]
+    pub lease: i64,
+}
+
+/// An error returned by the v3 API, with its gRPC status code.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Status {
+    /// The gRPC status code, such as 11 (out of range) when reading a compacted revision.
+    pub code: i32,
+    /// A human-friendly description of the error.
+    #[serde(default)]
+    pub message: String,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl StdError for Status {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// A message from a streaming gateway endpoint, which holds either a result or an error.
+#[derive(Deserialize)]
+enum StreamResult<T> {
+    #[serde(rename = "result")]
+    Result(T),
+    #[serde(rename = "error")]
+    Error(StreamError),
+}
+
+/// An error from a streaming gateway endpoint.
+#[derive(Deserialize)]
+struct StreamError {
+    grpc_code: i32,
+    #[serde(default)]
+    message: String,
+}
+
+impl From<StreamError> for Error {
+    fn from(error: StreamError) -> Error {
+        Error::V3(Status {
+            code: error.grpc_code,
+            message: error.message,
+        })
+    }
+}
+
+/// Unwraps the result of a response from a streaming gateway endpoint.
+fn stream_result<T>(response: Response<StreamResult<T>>) -> Result<Response<T>, Error> {
+    let data = match response.data {
+        StreamResult::Result(data) => data,
+        StreamResult::Error(error) => return Err(error.into()),
+    };
+
+    Ok(Response {
+        cluster_info: response.cluster_info,
+        data,
+        cached: response.cached,
+        dry_run: response.dry_run,
+        endpoint: response.endpoint,
+        retries: response.retries,
+    })
+}
+
+/// Sends a v3 request to the gateway endpoint `path`, such as `kv/range`, on each endpoint in turn,
+/// returning the first successful response.
+///
+/// Only requests that do not modify data should be `idempotent`, which allows them to be retried
+/// after any transient error.
+pub(crate) async fn request<B, T>(
+    client: &Client,
+    path: &str,
+    body: &B,
+    idempotent: bool,
+) -> Result<Response<T>, Vec<Error>>
+where
+    B: Serialize,
+    T: DeserializeOwned,
+{
+    let body = Bytes::from(serde_json::to_vec(body).map_err(|e| vec![e.into()])?);
+
+    if idempotent {
+        client
+            .first_ok_idempotent(|client, endpoint| {
+                request_on_endpoint(client, endpoint, path, body.clone())
+            })
+            .await
+    } else {
+        client
+            .first_ok(|client, endpoint| request_on_endpoint(client, endpoint, path, body.clone()))
+            .await
+    }
+}
+
+/// Sends a v3 request to a single endpoint.
+async fn request_on_endpoint<T>(
+    client: &Client,
+    endpoint: Uri,
+    path: &str,
+    body: Bytes,
+) -> Result<Response<T>, Error>
+where
+    T: DeserializeOwned,
+{
+    let request = build_request(client, &endpoint, path, body);
+    let response = check_response(client.send(request).await?).await?;
+    let cluster_info = ClusterInfo::from(response.headers());
+    let endpoint = response_endpoint(&response)?;
+    let body = read_body(response).await?;
+
+    Ok(Response {
+        data: parse_json_body(&body)?,
+        cluster_info,
+        endpoint,
+        cached: false,
+        dry_run: false,
+        retries: 0,
+    })
+}
+
+/// Builds a v3 request to a single endpoint.
+fn build_request(
+    client: &Client,
+    endpoint: &Uri,
+    path: &str,
+    body: Bytes,
+) -> reqwest::RequestBuilder {
+    client
+        .http_client()
+        .post(build_url(endpoint, path))
+        .header(CONTENT_TYPE, "application/json")
+        .body(body)
+}
+
+/// Returns a successful response, or parses the error of an unsuccessful one.
+async fn check_response(response: reqwest::Response) -> Result<reqwest::Response, Error> {
+    let status_code = response.status();
+    if status_code == StatusCode::OK {
+        return Ok(response);
+    }
+
+    // Errors that do not come from etcd itself, such as a 404 from a member without the gateway,
+    // have no status in their body.
+    let body = read_body(response).await?;
+    match serde_json::from_slice(&body) {
+        Ok(status) => Err(Error::V3(status)),
+        Err(_) => Err(Error::UnexpectedStatus(status_code)),
+    }
+}
+
+/// Synthesizes the response to a write by a client in dry-run mode.
+fn dry_run_response<T>(client: &Client, data: T) -> Response<T> {
+    Response {
+        cluster_info: ClusterInfo::default(),
+        data,
+        cached: false,
+        dry_run: true,
+        endpoint: client.first_endpoint(),
+        retries: 0,
+    }
+}
+
+/// Returns the end of the range of keys that start with `prefix`.
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    match prefix.iter().rposition(|&byte| byte < 0xff) {
+        Some(position) => {
+            let mut end = prefix[..=position].to_vec();
+            end[position] += 1;
+            end
+        }
+        // No key is greater than every key with this prefix, so read to the end of the key space.
+        None => vec![0],
+    }
+}
+
+/// Returns whether an optional integer field is unset.
+fn is_zero(value: &i64) -> bool {
+    *value == 0
+}
+
+/// Constructs the full URL for a v3 API call.
+fn build_url(endpoint: &Uri, path: &str) -> String {
+    format!("{}v3/{}", endpoint, path)
+}
+
+/// Serializes bytes as base64, as the gateway expects for keys and values.
+fn serialize_bytes<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&base64::encode(bytes))
+}
+
+/// Deserializes base64-encoded bytes.
+fn deserialize_bytes<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let encoded = String::deserialize(deserializer)?;
+    base64::decode(&encoded).map_err(D::Error::custom)
+}
+
+/// An integer as encoded by the gateway.
+///
+/// 64-bit integers are encoded as strings, since they may not fit in a JSON number, but numbers
+/// are accepted as well.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Int<T> {
+    Number(T),
+    Text(String),
+}
+
+/// Deserializes an integer encoded as a string or a number.
+fn deserialize_int<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + std::str::FromStr,
+    T::Err: Display,
+{
+    match Int::deserialize(deserializer)? {
+        Int::Number(number) => Ok(number),
+        Int::Text(text) => text.parse().map_err(D::Error::custom),
+    }
+}
@@ -0,0 +1,276 @@
+//! etcd's v3 watch API.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures_util::stream::{self, Stream};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::client::{parse_json_body, response_endpoint, Client, ClusterInfo, Response};
+use crate::error::Error;
+
+use super::{
+    build_request, check_response, deserialize_int, is_zero, prefix_end, serialize_bytes, KeyValue,
+    ResponseHeader, Status, StreamResult,
+};
+
+/// The gRPC status code of a watch canceled by etcd.
+const CANCELLED: i32 = 1;
+
+/// A request to watch the keys in a range, for `v3::watch::watch`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq, Serialize)]
+pub struct WatchRequest {
+    /// The first key in the range.
+    #[serde(serialize_with = "serialize_bytes")]
+    pub key: Vec<u8>,
+    /// The end of the range, which is not included in it.
+    ///
+    /// Only `key` is watched if this is empty. All keys greater than or equal to `key` are watched
+    /// if this is a single zero byte. `WatchRequest::prefix` sets it to watch the keys with a
+    /// prefix.
+    #[serde(
+        serialize_with = "serialize_bytes",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub range_end: Vec<u8>,
+    /// The revision to watch from, including it, or zero to watch changes after the current
+    /// revision.
+    ///
+    /// Watching from a revision that has been compacted fails with `Error::Compacted`.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub start_revision: i64,
+}
+
+impl WatchRequest {
+    /// Creates a request to watch a single key.
+    pub fn new<K>(key: K) -> Self
+    where
+        K: Into<Vec<u8>>,
+    {
+        WatchRequest {
+            key: key.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a request to watch all keys that start with `prefix`.
+    ///
+    /// An empty prefix watches every key.
+    pub fn prefix<P>(prefix: P) -> Self
+    where
+        P: Into<Vec<u8>>,
+    {
+        let key = prefix.into();
+        let range_end = prefix_end(&key);
+
+        WatchRequest {
+            key,
+            range_end,
+            ..Default::default()
+        }
+    }
+}
+
+/// Changes to watched keys, yielded by `v3::watch::watch`.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct WatchResponse {
+    /// Information about the state of the cluster.
+    #[serde(default)]
+    pub header: ResponseHeader,
+    /// The changes, in the order they were made.
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
+
+/// A change to a key.
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Event {
+    /// The kind of change.
+    #[serde(rename = "type", default)]
+    pub kind: EventType,
+    /// The key after the change.
+    ///
+    /// For a deletion, only its `key` and `mod_revision`, the revision of the deletion, are set.
+    #[serde(default)]
+    pub kv: KeyValue,
+}
+
+/// The kind of change made to a key.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum EventType {
+    /// The key was created or its value was set.
+    ///
+    /// The gateway omits the type of puts, since it is the default.
+    #[default]
+    #[serde(rename = "PUT")]
+    Put,
+    /// The key was deleted, or it was attached to a lease that expired.
+    #[serde(rename = "DELETE")]
+    Delete,
+}
+
+/// The body of a watch request, which creates a single watcher.
+#[derive(Serialize)]
+struct CreateRequest<'a> {
+    create_request: &'a WatchRequest,
+}
+
+/// A message sent by etcd on a watch stream.
+#[derive(Deserialize)]
+struct WatchMessage {
+    #[serde(default)]
+    header: ResponseHeader,
+    #[serde(default)]
+    canceled: bool,
+    #[serde(default)]
+    cancel_reason: String,
+    #[serde(default, deserialize_with = "deserialize_int")]
+    compact_revision: i64,
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+/// The state of a watch stream.
+enum State {
+    /// The watch has not been created yet.
+    Connecting(Client, Bytes),
+    /// The watch is streaming changes, with the part of the next message received so far.
+    Streaming(reqwest::Response, Vec<u8>),
+    /// The watch has failed, with the error to yield before it ends.
+    Failed(Error),
+    /// The watch has ended.
+    Done,
+}
+
+/// Watches the keys in a range, yielding their changes as they happen.
+///
+/// The watch is made with a single long-lived request to the first endpoint that accepts it, over
+/// which etcd sends the changes. Unlike the v2 `kv::watch`, which only returns the next change,
+/// every change is yielded, and none are missed between them. Watching stops when the stream is
+/// dropped.
+///
+/// # Parameters
+///
+/// * client: A `Client` to use to make the API call.
+/// * request: The range to watch, and the revision to watch it from.
+///
+/// # Errors
+///
+/// The stream yields the error and ends if the watch cannot be created, or if etcd cancels it. If
+/// `start_revision` has been compacted, the error is `Error::Compacted`, with the oldest revision
+/// that can still be watched; the caller can read the keys again with `v3::kv::range` and watch
+/// from the revision of that read. The stream also ends if the connection is closed.
+pub fn watch(
+    client: &Client,
+    request: WatchRequest,
+) -> impl Stream<Item = Result<WatchResponse, Error>> {
+    let body = serde_json::to_vec(&CreateRequest {
+        create_request: &request,
+    })
+    .map(Bytes::from);
+
+    let state = match body {
+        Ok(body) => State::Connecting(client.clone(), body),
+        Err(error) => State::Failed(error.into()),
+    };
+
+    stream::unfold(state, |mut state| async move {
+        loop {
+            state = match state {
+                State::Connecting(client, body) => match connect(&client, body).await {
+                    Ok(response) => State::Streaming(response, Vec::new()),
+                    Err(error) => return Some((Err(error), State::Done)),
+                },
+                State::Streaming(mut response, mut buffer) => {
+                    if let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+                        let line: Vec<u8> = buffer.drain(..=end).collect();
+                        match parse_message(&line) {
+                            Ok(Some(data)) => {
+                                return Some((Ok(data), State::Streaming(response, buffer)))
+                            }
+                            Ok(None) => State::Streaming(response, buffer),
+                            Err(error) => return Some((Err(error), State::Done)),
+                        }
+                    } else {
+                        match response.chunk().await {
+                            Ok(Some(chunk)) => {
+                                buffer.extend_from_slice(&chunk);
+                                State::Streaming(response, buffer)
+                            }
+                            Ok(None) => return None,
+                            Err(error) => return Some((Err(error.into()), State::Done)),
+                        }
+                    }
+                }
+                State::Failed(error) => return Some((Err(error), State::Done)),
+                State::Done => return None,
+            }
+        }
+    })
+}
+
+/// Creates the watch on the first endpoint that accepts it.
+async fn connect(client: &Client, body: Bytes) -> Result<reqwest::Response, Error> {
+    let result = client
+        .first_ok_idempotent(|client, endpoint| {
+            let body = body.clone();
+            async move {
+                // Since `reqwest` doesn't let us disable the timeout, we'll set an arbitrary large
+                // amount, like `kv::watch`.
+                let request = build_request(client, &endpoint, "watch", body)
+                    .timeout(Duration::from_secs(60 * 60 * 24));
+                let response = check_response(client.send(request).await?).await?;
+
+                Ok(Response {
+                    cluster_info: ClusterInfo::from(response.headers()),
+                    endpoint: response_endpoint(&response)?,
+                    data: response,
+                    cached: false,
+                    dry_run: false,
+                    retries: 0,
+                })
+            }
+        })
+        .await;
+
+    match result {
+        Ok(response) => Ok(response.data),
+        Err(errors) => Err(errors
+            .into_iter()
+            .next()
+            .expect("invariant: errors array should never be empty.")),
+    }
+}
+
+/// Parses a line of the watch stream, returning the changes it holds, if any.
+fn parse_message(line: &[u8]) -> Result<Option<WatchResponse>, Error> {
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+
+    let message = match parse_json_body(line)? {
+        StreamResult::Result(message) => message,
+        StreamResult::Error(error) => return Err(error.into()),
+    };
+    let WatchMessage {
+        header,
+        canceled,
+        cancel_reason,
+        compact_revision,
+        events,
+    } = message;
+
+    if compact_revision != 0 {
+        Err(Error::Compacted(compact_revision))
+    } else if canceled {
+        Err(Error::V3(Status {
+            code: CANCELLED,
+            message: cancel_reason,
+        }))
+    } else if events.is_empty() {
+        // The confirmation that the watch was created, or a progress notification.
+        Ok(None)
+    } else {
+        Ok(Some(WatchResponse { header, events }))
+    }
+}
@@ -1,7 +1,12 @@
 //! Types for the version endpoint.
 
+use std::fmt::{Display, Error as FmtError, Formatter};
+use std::str::FromStr;
+
 use serde_derive::{Deserialize, Serialize};
 
+use crate::Error;
+
 /// Information about the versions of etcd running in a cluster.
 ///
 /// This value is returned by `Client::versions`.
@@ -14,3 +19,74 @@ pub struct VersionInfo {
     #[serde(rename = "etcdserver")]
     pub server_version: String,
 }
+
+impl VersionInfo {
+    /// Parses `cluster_version`.
+    ///
+    /// etcd only tracks the major and minor version of the cluster, so its patch version is
+    /// always zero.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::InvalidVersion` if the version cannot be parsed, such as when it is
+    /// `not_decided` because the members have not yet agreed on a cluster version.
+    pub fn parse_cluster_version(&self) -> Result<Version, Error> {
+        self.cluster_version.parse()
+    }
+
+    /// Parses `server_version`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::InvalidVersion` if the version cannot be parsed.
+    pub fn parse_server_version(&self) -> Result<Version, Error> {
+        self.server_version.parse()
+    }
+}
+
+/// A version number of etcd, such as `2.3.8`.
+///
+/// Versions are ordered by their major, minor, and patch versions. When parsed, any pre-release
+/// or build metadata suffix, such as the `-rc.1` in `3.0.0-rc.1`, is ignored.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Version {
+    /// The major version.
+    pub major: u64,
+    /// The minor version.
+    pub minor: u64,
+    /// The patch version.
+    pub patch: u64,
+}
+
+impl Version {
+    /// Creates a version from its parts.
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Version {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for Version {
+    type Err = Error;
+
+    fn from_str(version: &str) -> Result<Self, Self::Err> {
+        let release = version.split(['-', '+']).next().unwrap_or_default();
+        let mut parts = release.split('.').map(|part| part.parse::<u64>().ok());
+
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Some(major)), Some(Some(minor)), Some(Some(patch)), None) => {
+                Ok(Version::new(major, minor, patch))
+            }
+            _ => Err(Error::InvalidVersion(version.to_string())),
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use crate::test::TestClient;
+use crate::test::{MockServer, TestClient};
 use etcd::{
     auth::{self, AuthChange, NewUser, Role, RoleUpdate, UserUpdate},
-    ClientBuilder,
+    Client, ClientBuilder, Error,
 };
 
 mod test;
@@ -11,12 +11,13 @@ fn auth() {
     let test_client = TestClient::no_destructor();
     let authed_client = ClientBuilder::new(&["http://etcd:2379"])
         .with_basic_auth("root", "secret")
-        .build();
+        .build()
+        .unwrap();
 
     // Check that auth is disabled first.
     {
-        let response = test_client.run(|c| auth::status(c)).unwrap();
-        assert_eq!(response.data, false);
+        let response = test_client.run(auth::status).unwrap();
+        assert!(!response.data);
     }
 
     // Create a new user.
@@ -31,7 +32,7 @@ fn auth() {
 
     // Enable auth:
     {
-        let response = test_client.run(|c| auth::enable(c)).unwrap();
+        let response = test_client.run(auth::enable).unwrap();
         assert_eq!(response.data, AuthChange::Changed);
     }
 
@@ -50,8 +51,16 @@ fn auth() {
         rkt_role.grant_kv_read_permission("/rkt/*");
         rkt_role.grant_kv_write_permission("/rkt/*");
         test_client
-            .run(|_| auth::create_role(&authed_client, rkt_role))
+            .run(|_| auth::create_role(&authed_client, rkt_role.clone()))
             .unwrap();
+
+        let errors = test_client
+            .run(|_| auth::create_role(&authed_client, rkt_role))
+            .unwrap_err();
+        match errors[0] {
+            Error::RoleAlreadyExists(_) => {}
+            ref error => panic!("expected Error::RoleAlreadyExists, got {:?}", error),
+        }
     }
 
     // Create a new user:
@@ -79,10 +88,34 @@ fn auth() {
             .unwrap();
     }
 
+    // Rotate our user's password:
+    {
+        let response = test_client
+            .run(|_| auth::change_password(&authed_client, "rkt", "secret3"))
+            .unwrap();
+        assert!(response.data.role_names().contains(&"rkt".to_owned()));
+
+        let old_client = ClientBuilder::new(&["http://etcd:2379"])
+            .with_basic_auth("rkt", "secret2")
+            .build()
+            .unwrap();
+        assert!(test_client
+            .run(|_| auth::get_user(&old_client, "rkt"))
+            .is_err());
+
+        let new_client = ClientBuilder::new(&["http://etcd:2379"])
+            .with_basic_auth("rkt", "secret3")
+            .build()
+            .unwrap();
+        test_client
+            .run(|_| auth::get_user(&new_client, "rkt"))
+            .unwrap();
+    }
+
     // Read the role back:
     {
         let response = test_client
-            .run(|_| auth::get_role(&&authed_client, "rkt"))
+            .run(|_| auth::get_role(&authed_client, "rkt"))
             .unwrap();
         let role = response.data;
         assert!(role.kv_read_permissions().contains(&"/rkt/*".to_owned()));
@@ -116,7 +149,197 @@ fn auth() {
 
     // Check that auth is disabled, using unauthorized client:
     {
-        let response = test_client.run(|c| auth::status(c)).unwrap();
-        assert_eq!(response.data, false);
+        let response = test_client.run(auth::status).unwrap();
+        assert!(!response.data);
+    }
+}
+
+#[test]
+fn enable_without_root_user() {
+    let server = MockServer::new(vec![(
+        409,
+        r#"{"message":"No root user available, please create one"}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let errors = TestClient::no_destructor()
+        .run(|_| auth::enable(&client))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::RootUserRequired(ref message) => {
+            assert_eq!(message, "No root user available, please create one")
+        }
+        ref error => panic!("expected Error::RootUserRequired, got {:?}", error),
+    }
+}
+
+#[test]
+fn enable_when_already_enabled() {
+    let server = MockServer::new(vec![(409, r#"{"message":"already enabled"}"#)]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let response = TestClient::no_destructor()
+        .run(|_| auth::enable(&client))
+        .unwrap();
+
+    assert_eq!(response.data, AuthChange::Unchanged);
+}
+
+#[test]
+fn disable_without_root_credentials() {
+    let server = MockServer::new(vec![(401, r#"{"message":"Insufficient credentials"}"#)]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let errors = TestClient::no_destructor()
+        .run(|_| auth::disable(&client))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::RootUserRequired(ref message) => assert_eq!(message, "Insufficient credentials"),
+        ref error => panic!("expected Error::RootUserRequired, got {:?}", error),
     }
 }
+
+#[test]
+fn create_role_with_prefix_permissions() {
+    let server = MockServer::new(vec![(
+        201,
+        r#"{"role":"app","permissions":{"kv":{"read":["/app/*"],"write":["/app/config"]}}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+    let mut role = Role::new("app");
+    role.grant_kv_read_permission("/app/*");
+    role.grant_kv_write_permission("/app/config");
+
+    let response = TestClient::no_destructor()
+        .run(|_| auth::create_role(&client, role))
+        .unwrap();
+
+    assert_eq!(response.data.kv_read_permissions(), ["/app/*"]);
+    assert_eq!(response.data.kv_write_permissions(), ["/app/config"]);
+    assert!(server.requests()[0].starts_with("PUT /v2/auth/roles/app "));
+    assert_eq!(
+        server.request_bodies()[0],
+        r#"{"role":"app","permissions":{"kv":{"read":["/app/*"],"write":["/app/config"]}}}"#
+    );
+}
+
+#[test]
+fn create_existing_role() {
+    let server = MockServer::new(vec![(409, r#"{"message":"Role app already exists."}"#)]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let errors = TestClient::no_destructor()
+        .run(|_| auth::create_role(&client, Role::new("app")))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::RoleAlreadyExists(ref message) => assert_eq!(message, "Role app already exists."),
+        ref error => panic!("expected Error::RoleAlreadyExists, got {:?}", error),
+    }
+}
+
+#[test]
+fn update_role_sends_only_changes() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"role":"app","permissions":{"kv":{"read":["/app/*","/shared/*"],"write":[]}}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+    let mut update = RoleUpdate::new("app");
+    update.grant_kv_read_permission("/shared/*");
+    update.revoke_kv_write_permission("/app/*");
+
+    let response = TestClient::no_destructor()
+        .run(|_| auth::update_role(&client, update))
+        .unwrap();
+
+    assert_eq!(response.data.kv_read_permissions(), ["/app/*", "/shared/*"]);
+    assert!(response.data.kv_write_permissions().is_empty());
+    assert_eq!(
+        server.request_bodies()[0],
+        r#"{"role":"app","grant":{"kv":{"read":["/shared/*"]}},"revoke":{"kv":{"write":["/app/*"]}}}"#
+    );
+}
+
+#[test]
+fn grant_and_revoke_user_role() {
+    let server = MockServer::new(vec![
+        (200, r#"{"user":"svc","roles":["app","reader"]}"#),
+        (200, r#"{"user":"svc","roles":["app"]}"#),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor().run(|_| async {
+        let response = auth::grant_user_role(&client, "svc", "reader")
+            .await
+            .unwrap();
+        assert_eq!(response.data.role_names(), ["app", "reader"]);
+
+        let response = auth::revoke_user_role(&client, "svc", "reader")
+            .await
+            .unwrap();
+        assert_eq!(response.data.role_names(), ["app"]);
+    });
+
+    let bodies = server.request_bodies();
+    assert_eq!(bodies[0], r#"{"user":"svc","grant":["reader"]}"#);
+    assert_eq!(bodies[1], r#"{"user":"svc","revoke":["reader"]}"#);
+}
+
+#[test]
+fn grant_missing_user_role() {
+    let server = MockServer::new(vec![(404, r#"{"message":"Role reader does not exist."}"#)]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let errors = TestClient::no_destructor()
+        .run(|_| auth::grant_user_role(&client, "svc", "reader"))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::RoleNotFound(ref message) => assert_eq!(message, "Role reader does not exist."),
+        ref error => panic!("expected Error::RoleNotFound, got {:?}", error),
+    }
+}
+
+#[test]
+fn get_user_with_role_details() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"user":"svc","roles":[{"role":"app","permissions":{"kv":{"read":["/app/*"],"write":[]}}}]}"#,
+        ),
+        (404, r#"{"message":"User nobody does not exist."}"#),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor().run(|_| async {
+        let response = auth::get_user(&client, "svc").await.unwrap();
+        assert_eq!(response.data.name(), "svc");
+        assert_eq!(response.data.role_names(), ["app"]);
+
+        let errors = auth::get_user(&client, "nobody").await.unwrap_err();
+        match errors[0] {
+            Error::UserNotFound(_) => {}
+            ref error => panic!("expected Error::UserNotFound, got {:?}", error),
+        }
+    });
+}
+
+#[test]
+fn change_password() {
+    let server = MockServer::new(vec![(200, r#"{"user":"svc","roles":["app"]}"#)]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let response = TestClient::no_destructor()
+        .run(|_| auth::change_password(&client, "svc", "hunter2"))
+        .unwrap();
+
+    assert_eq!(response.data.role_names(), ["app"]);
+    assert!(server.requests()[0].starts_with("PUT /v2/auth/users/svc "));
+    assert_eq!(
+        server.request_bodies()[0],
+        r#"{"user":"svc","password":"hunter2"}"#
+    );
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "blocking")]
+
+use etcd::blocking::Client;
+use etcd::kv::{GetOptions, WatchOptions};
+use etcd::Error;
+
+use crate::test::MockServer;
+
+mod test;
+
+#[test]
+fn get_and_set() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"set","node":{"key":"/foo","value":"bar","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let response = client.set("/foo", "bar", None).unwrap();
+    assert_eq!(response.data.node.modified_index, Some(5));
+
+    let response = client.get("/foo", GetOptions::default()).unwrap();
+    assert_eq!(response.data.node.value.unwrap(), "bar");
+
+    let requests = server.requests();
+    assert!(requests[0].starts_with("PUT /v2/keys/foo "));
+    assert!(requests[1].starts_with("GET /v2/keys/foo"));
+}
+
+#[test]
+fn errors() {
+    let server = MockServer::new(vec![(
+        404,
+        r#"{"errorCode":100,"message":"Key not found","cause":"/foo","index":5}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let errors = client.get("/foo", GetOptions::default()).unwrap_err();
+
+    match errors[0] {
+        Error::KeyNotFound(ref error) => assert_eq!(error.index, 5),
+        ref error => panic!("expected Error::KeyNotFound, got {:?}", error),
+    }
+}
+
+#[test]
+fn watch() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"action":"set","node":{"key":"/foo","value":"baz","modifiedIndex":6,"createdIndex":6}}"#,
+    )]);
+    let client = Client::from(etcd::Client::new(&[&server.endpoint()]));
+
+    let options = WatchOptions {
+        index: Some(6),
+        ..Default::default()
+    };
+    let response = client.watch("/foo", options).unwrap();
+
+    let requests = server.requests();
+    assert!(requests[0].contains("wait=true"));
+    assert!(requests[0].contains("waitIndex=6"));
+    assert_eq!(response.data.node.value.unwrap(), "baz");
+}
+
+#[test]
+fn set_and_get_bytes() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"set","node":{"key":"/foo","value":"base64:AP8=","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"base64:AP8=","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    client.set_bytes("/foo", &[0, 255], None).unwrap();
+
+    assert_eq!(client.get_bytes("/foo").unwrap(), vec![0, 255]);
+    assert!(server.request_bodies()[0].contains("base64%3AAP8%3D"));
+}
+
+#[test]
+fn drop_in_async_context() {
+    let client = Client::new(&["http://127.0.0.1:2379"]);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+
+    // Dropping the last clone shuts down the client's runtime, which must not panic here.
+    runtime.block_on(async move { drop(client) });
+}
@@ -1,14 +1,84 @@
-use crate::test::TestClient;
+use std::io::{Read, Write};
+use std::net::{TcpListener, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use http::Uri;
+
+use etcd::kv::{self, GetOptions};
+use etcd::{
+    CacheConfig, CircuitConfig, CircuitState, Client, ClientBuilder, ClusterInfo, Error, Health,
+    HttpVersion, RequestEvent, ResponseEvent, RetryPolicy, Selection, TlsVersion, Version,
+};
+
+use crate::test::{MockServer, TestClient};
 
 mod test;
 
+const GET_RESPONSE: &str =
+    r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":5,"createdIndex":5}}"#;
+const RAFT_INTERNAL_ERROR: &str =
+    r#"{"errorCode":300,"message":"Raft Internal Error","cause":"","index":5}"#;
+
+fn retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(10),
+        multiplier: 2.0,
+        max_delay: Duration::from_millis(20),
+    }
+}
+
 #[test]
 fn health() {
     let client = TestClient::no_destructor();
     let responses = client.run(|c| c.health());
 
     for response in responses {
-        assert_eq!(response.unwrap().data.health, "true");
+        assert!(response.unwrap().data.health);
+    }
+}
+
+#[test]
+fn health_status_forms() {
+    for &(body, expected) in &[
+        (r#"{"health":"true"}"#, true),
+        (r#"{"health":"false"}"#, false),
+        (r#"{"health":true}"#, true),
+        (r#"{"health":false,"reason":"RAFT NO LEADER"}"#, false),
+    ] {
+        let health: Health = serde_json::from_str(body).unwrap();
+        assert_eq!(health.health, expected, "{}", body);
+    }
+
+    assert!(serde_json::from_str::<Health>(r#"{"health":"maybe"}"#).is_err());
+}
+
+#[test]
+fn health_endpoint() {
+    let server = MockServer::new(vec![(200, r#"{"health":"true"}"#)]);
+    let endpoint = server.endpoint();
+    let client = Client::new(&["http://127.0.0.1:1", &endpoint]);
+    let runtime = TestClient::no_destructor();
+
+    let healthy: Uri = endpoint.parse().unwrap();
+    let response = runtime.run(|_| client.health_endpoint(&healthy)).unwrap();
+    assert!(response.data.health);
+    assert_eq!(server.requests().len(), 1);
+
+    let unreachable: Uri = "http://127.0.0.1:1".parse().unwrap();
+    match runtime.run(|_| client.health_endpoint(&unreachable)) {
+        Err(Error::Connection { .. }) => {}
+        ref result => panic!("expected Error::Connection, got {:?}", result),
+    }
+
+    let unknown: Uri = "http://127.0.0.1:2".parse().unwrap();
+    match runtime.run(|_| client.health_endpoint(&unknown)) {
+        Err(Error::UnknownEndpoint(ref endpoint)) => assert_eq!(*endpoint, unknown),
+        ref result => panic!("expected Error::UnknownEndpoint, got {:?}", result),
     }
 }
 
@@ -23,3 +93,1827 @@ fn versions() {
         assert_eq!(response.data.server_version, "2.3.8");
     }
 }
+
+#[test]
+fn versions_all() {
+    let old = MockServer::new(vec![(
+        200,
+        r#"{"etcdserver":"2.3.7","etcdcluster":"2.3.0"}"#,
+    )]);
+    let new = MockServer::new(vec![(
+        200,
+        r#"{"etcdserver":"2.3.8","etcdcluster":"2.3.0"}"#,
+    )]);
+    let down = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        format!("http://{}", listener.local_addr().unwrap())
+    };
+    let client = Client::new(&[&old.endpoint(), &down, &new.endpoint()]);
+
+    let mut results: Vec<(String, Result<String, Error>)> = TestClient::no_destructor().run(|_| {
+        client
+            .versions_all()
+            .map(|(endpoint, result)| {
+                let version = result.map(|version| version.server_version);
+                (
+                    endpoint.to_string().trim_end_matches('/').to_string(),
+                    version,
+                )
+            })
+            .collect()
+    });
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+
+    assert_eq!(results.len(), 3);
+    for (endpoint, result) in results {
+        if endpoint == old.endpoint() {
+            assert_eq!(result.unwrap(), "2.3.7");
+        } else if endpoint == new.endpoint() {
+            assert_eq!(result.unwrap(), "2.3.8");
+        } else {
+            assert_eq!(endpoint, down);
+            assert!(result.is_err());
+        }
+    }
+}
+
+#[test]
+fn require_min_version() {
+    let server = MockServer::new(vec![
+        (200, r#"{"etcdserver":"2.3.8","etcdcluster":"2.3.0"}"#),
+        (200, r#"{"etcdserver":"2.3.8","etcdcluster":"2.3.0"}"#),
+        (200, r#"{"etcdserver":"2.4.1","etcdcluster":"2.3.0"}"#),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor().run(|_| async {
+        client
+            .require_min_version(Version::new(2, 3, 8))
+            .await
+            .unwrap();
+
+        match client.require_min_version(Version::new(2, 3, 9)).await {
+            Err(Error::VersionTooOld { found, required }) => {
+                assert_eq!(found, Version::new(2, 3, 8));
+                assert_eq!(required, Version::new(2, 3, 9));
+            }
+            result => panic!("expected Error::VersionTooOld, got {:?}", result),
+        }
+
+        match client.require_min_version(Version::new(2, 4, 0)).await {
+            Err(Error::VersionTooOld { found, .. }) => assert_eq!(found, Version::new(2, 3, 0)),
+            result => panic!("expected Error::VersionTooOld, got {:?}", result),
+        }
+    });
+}
+
+#[test]
+fn require_min_version_cluster_not_decided() {
+    let server = MockServer::new(vec![
+        (200, r#"{"etcdserver":"2.3.8","etcdcluster":"not_decided"}"#),
+        (200, r#"{"etcdserver":"2.3.7","etcdcluster":"not_decided"}"#),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor().run(|_| async {
+        client
+            .require_min_version(Version::new(2, 3, 8))
+            .await
+            .unwrap();
+
+        match client.require_min_version(Version::new(2, 3, 8)).await {
+            Err(Error::VersionTooOld { found, .. }) => assert_eq!(found, Version::new(2, 3, 7)),
+            result => panic!("expected Error::VersionTooOld, got {:?}", result),
+        }
+    });
+}
+
+#[test]
+fn parse_version() {
+    assert_eq!("2.3.8".parse::<Version>().unwrap(), Version::new(2, 3, 8));
+    assert_eq!(
+        "3.0.0-rc.1".parse::<Version>().unwrap(),
+        Version::new(3, 0, 0)
+    );
+    assert_eq!(
+        "2.3.0+git".parse::<Version>().unwrap(),
+        Version::new(2, 3, 0)
+    );
+    assert!(Version::new(2, 10, 0) > Version::new(2, 9, 9));
+
+    for version in &["", "2.3", "2.3.x", "2.3.8.1"] {
+        match version.parse::<Version>() {
+            Err(Error::InvalidVersion(_)) => {}
+            result => panic!("expected Error::InvalidVersion, got {:?}", result),
+        }
+    }
+}
+
+#[test]
+fn request_raw() {
+    let body = r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":7,"createdIndex":7}}"#;
+    let server = MockServer::with_headers(vec![
+        (200, "X-Etcd-Index: 7\r\n".to_string(), body),
+        (
+            404,
+            String::new(),
+            r#"{"errorCode":100,"message":"Key not found","cause":"/bar","index":7}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor().run(|_| async {
+        let response = client
+            .request_raw(format!("{}/v2/keys/foo", server.endpoint()))
+            .await
+            .unwrap();
+        assert_eq!(&response.data[..], body.as_bytes());
+        assert_eq!(response.cluster_info.etcd_index, Some(7));
+
+        match client
+            .request_raw(format!("{}/v2/keys/bar", server.endpoint()))
+            .await
+        {
+            Err(Error::KeyNotFound(_)) => {}
+            result => panic!("expected Error::KeyNotFound, got {:?}", result),
+        }
+    });
+}
+
+#[test]
+fn retry_policy_retries_reads() {
+    let server = MockServer::new(vec![(500, RAFT_INTERNAL_ERROR), (200, GET_RESPONSE)]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_retry_policy(retry_policy())
+        .build()
+        .unwrap();
+
+    let response = TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    assert_eq!(response.retries, 1);
+    assert_eq!(response.data.node.value.unwrap(), "bar");
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn retry_policy_caps_delay() {
+    let server = MockServer::new(vec![
+        (500, RAFT_INTERNAL_ERROR),
+        (500, RAFT_INTERNAL_ERROR),
+        (200, GET_RESPONSE),
+    ]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_retry_policy(RetryPolicy {
+            multiplier: f64::MAX,
+            ..retry_policy()
+        })
+        .build()
+        .unwrap();
+
+    let response = TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    assert_eq!(response.retries, 2);
+}
+
+#[test]
+#[should_panic(expected = "retry policy multiplier must be finite and non-negative")]
+fn retry_policy_multiplier_must_be_finite() {
+    ClientBuilder::new(&["http://etcd:2379"]).with_retry_policy(RetryPolicy {
+        multiplier: f64::NAN,
+        ..retry_policy()
+    });
+}
+
+#[test]
+fn retry_policy_does_not_retry_sent_writes() {
+    let server = MockServer::new(vec![(500, RAFT_INTERNAL_ERROR), (200, GET_RESPONSE)]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_retry_policy(retry_policy())
+        .build()
+        .unwrap();
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::set(&client, "/foo", "bar", None))
+        .unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn retry_policy_retries_connection_errors() {
+    let client = ClientBuilder::new(&["http://127.0.0.1:1"])
+        .with_retry_policy(retry_policy())
+        .build()
+        .unwrap();
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::set(&client, "/foo", "bar", None))
+        .unwrap_err();
+
+    assert_eq!(errors.len(), 3);
+    for error in errors {
+        match error {
+            Error::Connection {
+                ref endpoint,
+                ref source,
+            } => {
+                assert_eq!(endpoint, "http://127.0.0.1:1");
+                assert!(source.is_connect());
+            }
+            _ => panic!("expected Error::Connection"),
+        }
+    }
+}
+
+#[test]
+fn connection_errors_name_each_endpoint() {
+    let client = Client::new(&["http://127.0.0.1:1", "http://127.0.0.1:2"]);
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap_err();
+
+    let mut endpoints: Vec<String> = errors
+        .iter()
+        .map(|error| match *error {
+            Error::Connection { ref endpoint, .. } => endpoint.to_string(),
+            ref error => panic!("expected Error::Connection, got {:?}", error),
+        })
+        .collect();
+    endpoints.sort();
+    assert_eq!(endpoints, ["http://127.0.0.1:1/", "http://127.0.0.1:2/"]);
+}
+
+#[test]
+fn request_timeout() {
+    // Connections are accepted by the OS, but no response is ever sent.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let client = ClientBuilder::new(&[&endpoint])
+        .with_request_timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::set(&client, "/foo", "bar", None))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::Timeout => {}
+        _ => panic!("expected Error::Timeout"),
+    }
+}
+
+#[test]
+fn request_timeout_per_get() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let client = Client::new(&[&endpoint]);
+
+    let errors = TestClient::no_destructor()
+        .run(|_| {
+            kv::get(
+                &client,
+                "/foo",
+                GetOptions {
+                    timeout: Some(Duration::from_millis(100)),
+                    ..Default::default()
+                },
+            )
+        })
+        .unwrap_err();
+
+    match errors[0] {
+        Error::Timeout => {}
+        _ => panic!("expected Error::Timeout"),
+    }
+}
+
+#[test]
+fn request_timeout_per_write() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let client = Client::new(&[&endpoint]);
+    let timeout = Some(Duration::from_millis(100));
+
+    TestClient::no_destructor().run(|_| async {
+        let options = kv::CompareAndSwapOptions {
+            prev_index: Some(5),
+            timeout,
+            ..Default::default()
+        };
+        let errors = kv::compare_and_swap(&client, "/foo", "bar", None, options)
+            .await
+            .unwrap_err();
+        assert!(matches!(errors[0], Error::Timeout), "{:?}", errors);
+
+        let options = kv::DeleteOptions {
+            timeout,
+            ..Default::default()
+        };
+        let errors = kv::delete_with_options(&client, "/foo", options)
+            .await
+            .unwrap_err();
+        assert!(matches!(errors[0], Error::Timeout), "{:?}", errors);
+    });
+}
+
+#[test]
+fn default_user_agent() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    let request = server.requests()[0].to_lowercase();
+    assert!(request.contains(&format!(
+        "user-agent: rust-etcd/{}\r\n",
+        env!("CARGO_PKG_VERSION")
+    )));
+}
+
+#[test]
+fn user_agent() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_user_agent("my-service/1.0")
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    let request = server.requests()[0].to_lowercase();
+    assert!(request.contains("user-agent: my-service/1.0\r\n"));
+}
+
+#[test]
+fn default_headers() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let mut headers = HeaderMap::new();
+    headers.insert("X-Request-Source", HeaderValue::from_static("tests"));
+    headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer ignored"));
+    headers.append("X-Tag", HeaderValue::from_static("a"));
+    headers.append("X-Tag", HeaderValue::from_static("b"));
+    let mut replacements = HeaderMap::new();
+    replacements.insert("X-Request-Source", HeaderValue::from_static("replaced"));
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_default_headers(headers)
+        .with_default_headers(replacements)
+        .with_basic_auth("root", "secret")
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    let request = server.requests()[0].to_lowercase();
+    assert!(request.contains("x-request-source: replaced\r\n"));
+    assert!(!request.contains("x-request-source: tests\r\n"));
+    assert!(request.contains("x-tag: a, b\r\n"));
+    assert!(request.contains("authorization: basic cm9vddpzzwnyzxq=\r\n"));
+}
+
+#[test]
+fn set_credentials() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE), (200, GET_RESPONSE)]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_basic_auth("root", "secret")
+        .build()
+        .unwrap();
+    let runtime = TestClient::no_destructor();
+
+    runtime
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+    client.clone().set_credentials("root", "rotated");
+    runtime
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    let requests = server.requests();
+    assert!(requests[0]
+        .to_lowercase()
+        .contains("authorization: basic cm9vddpzzwnyzxq=\r\n"));
+    assert!(requests[1]
+        .to_lowercase()
+        .contains("authorization: basic cm9vddpyb3rhdgvk\r\n"));
+}
+
+#[test]
+fn proxy() {
+    let proxy = MockServer::new(vec![(200, GET_RESPONSE), (200, GET_RESPONSE)]);
+    let credentials = proxy.endpoint().replace("http://", "http://user:secret@");
+
+    for (proxy_uri, proxy_auth) in [(proxy.endpoint(), true), (credentials, false)] {
+        let client = ClientBuilder::new(&["http://etcd.invalid:2379"])
+            .with_proxy(proxy_uri.parse().unwrap());
+        let client = if proxy_auth {
+            client.with_proxy_basic_auth("user", "secret")
+        } else {
+            client
+        }
+        .build()
+        .unwrap();
+
+        TestClient::no_destructor()
+            .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+            .unwrap();
+    }
+
+    for request in proxy.requests() {
+        let request = request.to_lowercase();
+        assert!(request.starts_with("get http://etcd.invalid:2379/v2/keys/foo?"));
+        assert!(request.contains("proxy-authorization: basic dxnlcjpzzwnyzxq=\r\n"));
+    }
+    assert_eq!(proxy.requests().len(), 2);
+}
+
+#[test]
+fn invalid_proxy() {
+    let result = ClientBuilder::new(&["http://etcd.example.com:2379"])
+        .with_proxy("ftp://proxy.example.com".parse().unwrap())
+        .build();
+
+    match result {
+        Err(Error::InvalidProxy(_)) => {}
+        Err(error) => panic!("expected Error::InvalidProxy, got {:?}", error),
+        Ok(_) => panic!("expected Error::InvalidProxy"),
+    }
+}
+
+#[test]
+fn pinned_cluster_id() {
+    let server = MockServer::with_headers(vec![
+        (
+            200,
+            "X-Etcd-Cluster-Id: cdf818194e3a8c32\r\n".to_string(),
+            GET_RESPONSE,
+        ),
+        (
+            200,
+            "X-Etcd-Cluster-Id: cdf818194e3a8c32\r\n".to_string(),
+            GET_RESPONSE,
+        ),
+        (
+            200,
+            "X-Etcd-Cluster-Id: 7e27652122e8b2ae\r\n".to_string(),
+            GET_RESPONSE,
+        ),
+    ]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_pinned_cluster_id(true)
+        .build()
+        .unwrap();
+
+    for _ in 0..2 {
+        let response = TestClient::no_destructor()
+            .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+            .unwrap();
+        assert_eq!(
+            response.cluster_info.cluster_id.as_deref(),
+            Some("cdf818194e3a8c32")
+        );
+    }
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap_err();
+    match errors[0] {
+        Error::ClusterIdMismatch {
+            ref expected,
+            ref actual,
+        } => {
+            assert_eq!(expected, "cdf818194e3a8c32");
+            assert_eq!(actual, "7e27652122e8b2ae");
+        }
+        ref error => panic!("expected Error::ClusterIdMismatch, got {:?}", error),
+    }
+}
+
+#[test]
+fn cluster_info_is_newer_than() {
+    let cluster_info = |etcd_index| ClusterInfo {
+        cluster_id: None,
+        etcd_index,
+        raft_index: None,
+        raft_term: None,
+    };
+
+    assert!(cluster_info(Some(2)).is_newer_than(&cluster_info(Some(1))));
+    assert!(!cluster_info(Some(1)).is_newer_than(&cluster_info(Some(2))));
+    assert!(!cluster_info(Some(1)).is_newer_than(&cluster_info(Some(1))));
+    assert!(!cluster_info(None).is_newer_than(&cluster_info(Some(1))));
+    assert!(!cluster_info(Some(1)).is_newer_than(&cluster_info(None)));
+}
+
+#[test]
+fn cluster_info_is_stale_relative_to() {
+    let cluster_info = |etcd_index| ClusterInfo {
+        cluster_id: None,
+        etcd_index,
+        raft_index: None,
+        raft_term: None,
+    };
+
+    assert!(cluster_info(Some(1)).is_stale_relative_to(&cluster_info(Some(2))));
+    assert!(!cluster_info(Some(2)).is_stale_relative_to(&cluster_info(Some(1))));
+    assert!(!cluster_info(Some(1)).is_stale_relative_to(&cluster_info(Some(1))));
+    assert!(!cluster_info(None).is_stale_relative_to(&cluster_info(Some(1))));
+    assert!(!cluster_info(Some(1)).is_stale_relative_to(&cluster_info(None)));
+}
+
+#[test]
+fn connection_pool_limits() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_max_idle_connections_per_host(1)
+        .with_pool_idle_timeout(Duration::from_secs(1))
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+}
+
+#[test]
+#[should_panic(expected = "max idle connections per host must be non-zero")]
+fn connection_pool_limits_must_be_non_zero() {
+    ClientBuilder::new(&["http://etcd:2379"]).with_max_idle_connections_per_host(0);
+}
+
+#[test]
+#[should_panic(expected = "pool idle timeout must be non-zero")]
+fn pool_idle_timeout_must_be_non_zero() {
+    ClientBuilder::new(&["http://etcd:2379"]).with_pool_idle_timeout(Duration::from_secs(0));
+}
+
+#[test]
+fn health_aware_endpoint_selection() {
+    // A body that is both a valid health check and a valid get response.
+    const HEALTHY_RESPONSE: &str = r#"{"health":"true","action":"get","node":{"createdIndex":1,"key":"/foo","modifiedIndex":1,"value":"bar"}}"#;
+
+    let healthy = MockServer::new(vec![(200, HEALTHY_RESPONSE); 10]);
+    let unhealthy = MockServer::new(vec![(200, r#"{"health":"false"}"#); 10]);
+    let client = ClientBuilder::new(&[&healthy.endpoint(), &unhealthy.endpoint()])
+        .with_endpoint_selection(Selection::HealthAware {
+            interval: Duration::from_secs(3600),
+        })
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor().run(|_| async {
+        // Starts the health checks. Until they complete, every endpoint may be tried.
+        kv::get(&client, "/foo", GetOptions::default())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let unhealthy_requests = unhealthy.requests().len();
+        for _ in 0..5 {
+            kv::get(&client, "/foo", GetOptions::default())
+                .await
+                .unwrap();
+        }
+        assert_eq!(unhealthy.requests().len(), unhealthy_requests);
+    });
+}
+
+#[test]
+#[should_panic(expected = "health check interval must be non-zero")]
+fn health_check_interval_must_be_non_zero() {
+    ClientBuilder::new(&["http://etcd:2379"]).with_endpoint_selection(Selection::HealthAware {
+        interval: Duration::from_secs(0),
+    });
+}
+
+#[test]
+fn round_robin_endpoint_selection() {
+    let servers: Vec<MockServer> = (0..3)
+        .map(|_| MockServer::new(vec![(200, GET_RESPONSE); 10]))
+        .collect();
+    let endpoints: Vec<String> = servers.iter().map(MockServer::endpoint).collect();
+    let endpoints: Vec<&str> = endpoints.iter().map(String::as_str).collect();
+    let client = ClientBuilder::new(&endpoints)
+        .with_endpoint_selection(Selection::RoundRobin)
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor().run(|_| async {
+        for _ in 0..30 {
+            kv::get(&client, "/foo", GetOptions::default())
+                .await
+                .unwrap();
+        }
+    });
+
+    for server in &servers {
+        assert_eq!(server.requests().len(), 10);
+    }
+}
+
+#[test]
+fn follows_redirects() {
+    const SET_RESPONSE: &str = r#"{"action":"set","node":{"createdIndex":1,"key":"/foo","modifiedIndex":1,"value":"bar"}}"#;
+
+    let leader = MockServer::new(vec![(200, SET_RESPONSE)]);
+    let follower = MockServer::with_headers(vec![(
+        307,
+        format!("Location: {}/v2/keys/foo\r\n", leader.endpoint()),
+        "",
+    )]);
+    let client = Client::new(&[&follower.endpoint()]);
+
+    let response = TestClient::no_destructor()
+        .run(|_| kv::set(&client, "/foo", "bar", None))
+        .unwrap();
+
+    assert_eq!(
+        response.endpoint.to_string(),
+        format!("{}/", leader.endpoint())
+    );
+    assert!(leader.requests()[0].starts_with("PUT /v2/keys/foo HTTP/1.1"));
+    assert!(leader.requests()[0].contains("content-type: application/x-www-form-urlencoded"));
+}
+
+#[test]
+fn redirects_keep_credentials_for_endpoints() {
+    const SET_RESPONSE: &str = r#"{"action":"set","node":{"createdIndex":1,"key":"/foo","modifiedIndex":1,"value":"bar"}}"#;
+
+    let leader = MockServer::new(vec![(200, SET_RESPONSE)]);
+    let follower = MockServer::with_headers(vec![(
+        307,
+        format!("Location: {}/v2/keys/foo\r\n", leader.endpoint()),
+        "",
+    )]);
+    let client = ClientBuilder::new(&[&follower.endpoint(), &leader.endpoint()])
+        .with_basic_auth("root", "secret")
+        .build()
+        .unwrap();
+
+    let response = TestClient::no_destructor()
+        .run(|_| kv::set(&client, "/foo", "bar", None))
+        .unwrap();
+
+    assert_eq!(
+        response.endpoint.to_string(),
+        format!("{}/", leader.endpoint())
+    );
+    assert_eq!(leader.request_bodies(), ["value=bar"]);
+    let request = leader.requests()[0].to_lowercase();
+    assert!(request.starts_with("put /v2/keys/foo http/1.1"));
+    assert!(request.contains("authorization: basic cm9vddpzzwnyzxq=\r\n"));
+}
+
+#[test]
+fn redirects_drop_credentials_for_other_hosts() {
+    const SET_RESPONSE: &str = r#"{"action":"set","node":{"createdIndex":1,"key":"/foo","modifiedIndex":1,"value":"bar"}}"#;
+
+    let other = MockServer::new(vec![(200, SET_RESPONSE)]);
+    let follower = MockServer::with_headers(vec![(
+        307,
+        format!("Location: {}/v2/keys/foo\r\n", other.endpoint()),
+        "",
+    )]);
+    let client = ClientBuilder::new(&[&follower.endpoint()])
+        .with_basic_auth("root", "secret")
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor()
+        .run(|_| kv::set(&client, "/foo", "bar", None))
+        .unwrap();
+
+    assert!(!other.requests()[0].to_lowercase().contains("authorization"));
+}
+
+#[test]
+fn too_many_redirects() {
+    // A chain of servers that each redirect to the previous one, ending at an unused address.
+    let mut location = "http://127.0.0.1:1/v2/keys/foo".to_string();
+    let servers: Vec<MockServer> = (0..6)
+        .map(|_| {
+            let server =
+                MockServer::with_headers(vec![(307, format!("Location: {}\r\n", location), "")]);
+            location = format!("{}/v2/keys/foo", server.endpoint());
+            server
+        })
+        .collect();
+    let client = Client::new(&[&servers[5].endpoint()]);
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::set(&client, "/foo", "bar", None))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::TooManyRedirects => {}
+        ref error => panic!("expected Error::TooManyRedirects, got {:?}", error),
+    }
+}
+
+#[test]
+fn too_many_redirects_across_hosts() {
+    // Redirects that alternate between the client's endpoints and other hosts count against the
+    // same limit.
+    let target = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let mut location = format!("{}/v2/keys/foo", target.endpoint());
+    let mut servers: Vec<MockServer> = (0..6)
+        .map(|_| {
+            let server =
+                MockServer::with_headers(vec![(307, format!("Location: {}\r\n", location), "")]);
+            location = format!("{}/v2/keys/foo", server.endpoint());
+            server
+        })
+        .collect();
+    servers.reverse();
+    let endpoints: Vec<String> = servers
+        .iter()
+        .step_by(2)
+        .map(MockServer::endpoint)
+        .collect();
+    let endpoints: Vec<&str> = endpoints.iter().map(String::as_str).collect();
+    // Round robin starts with the first endpoint, which heads the chain.
+    let client = ClientBuilder::new(&endpoints)
+        .with_endpoint_selection(Selection::RoundRobin)
+        .build()
+        .unwrap();
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::TooManyRedirects => {}
+        ref error => panic!("expected Error::TooManyRedirects, got {:?}", error),
+    }
+    assert!(target.requests().is_empty());
+}
+
+#[test]
+fn redirects_can_be_disabled() {
+    let server = MockServer::with_headers(vec![(
+        307,
+        "Location: http://127.0.0.1:1/v2/keys/foo\r\n".to_string(),
+        "",
+    )]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_follow_redirects(false)
+        .build()
+        .unwrap();
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::set(&client, "/foo", "bar", None))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::UnexpectedStatus(status) => assert_eq!(status.as_u16(), 307),
+        ref error => panic!("expected Error::UnexpectedStatus, got {:?}", error),
+    }
+}
+
+/// Starts a DNS server that answers SRV queries for `_etcd-client._tcp.example.com` with the given
+/// (priority, weight, port, target) records, and every other query with NXDOMAIN.
+fn srv_server(records: Vec<(u16, u16, u16, &'static str)>) -> std::net::SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+
+    thread::spawn(move || loop {
+        let mut query = [0; 512];
+        let (len, peer) = socket.recv_from(&mut query).unwrap();
+        socket
+            .send_to(&srv_response(&query[..len], &records, false), peer)
+            .unwrap();
+    });
+
+    address
+}
+
+/// Starts a DNS server like `srv_server`, except that it answers over UDP with only the first
+/// record and the truncation flag set, and with every record over TCP.
+fn truncating_srv_server(records: Vec<(u16, u16, u16, &'static str)>) -> std::net::SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let address = socket.local_addr().unwrap();
+    let listener = TcpListener::bind(address).unwrap();
+    let tcp_records = records.clone();
+
+    thread::spawn(move || loop {
+        let mut query = [0; 512];
+        let (len, peer) = socket.recv_from(&mut query).unwrap();
+        socket
+            .send_to(&srv_response(&query[..len], &records[..1], true), peer)
+            .unwrap();
+    });
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut len = [0; 2];
+            stream.read_exact(&mut len).unwrap();
+            let mut query = vec![0; usize::from(u16::from_be_bytes(len))];
+            stream.read_exact(&mut query).unwrap();
+
+            let response = srv_response(&query, &tcp_records, false);
+            stream
+                .write_all(&(response.len() as u16).to_be_bytes())
+                .unwrap();
+            stream.write_all(&response).unwrap();
+        }
+    });
+
+    address
+}
+
+/// Builds the response of the servers started by `srv_server` to a query.
+fn srv_response(
+    query: &[u8],
+    records: &[(u16, u16, u16, &'static str)],
+    truncated: bool,
+) -> Vec<u8> {
+    let question_end = 12 + query[12..].iter().position(|&b| b == 0).unwrap() + 5;
+    let found = query[12..question_end]
+        .windows(13)
+        .any(|name| name == b"\x0c_etcd-client");
+    let answers = if found { records } else { &[] };
+
+    let mut response = query[..2].to_vec();
+    // A response with recursion desired, and the truncation flag if requested.
+    response.push(if truncated { 0x83 } else { 0x81 });
+    // Recursion available, and NXDOMAIN if the name was not found.
+    response.push(if found { 0x80 } else { 0x83 });
+    response.extend_from_slice(&[0, 1, 0, answers.len() as u8, 0, 0, 0, 0]);
+    response.extend_from_slice(&query[12..question_end]);
+    for &(priority, weight, port, target) in answers {
+        let mut target_name = Vec::new();
+        for label in target.split('.').filter(|label| !label.is_empty()) {
+            target_name.push(label.len() as u8);
+            target_name.extend_from_slice(label.as_bytes());
+        }
+        target_name.push(0);
+
+        // The owner name is a pointer to the question.
+        response.extend_from_slice(&[0xc0, 12, 0, 33, 0, 1, 0, 0, 0, 60, 0]);
+        response.push(6 + target_name.len() as u8);
+        response.extend_from_slice(&priority.to_be_bytes());
+        response.extend_from_slice(&weight.to_be_bytes());
+        response.extend_from_slice(&port.to_be_bytes());
+        response.extend_from_slice(&target_name);
+    }
+
+    response
+}
+
+#[test]
+fn discover_srv() {
+    let primary = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let secondary = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let port = |server: &MockServer| {
+        server
+            .endpoint()
+            .rsplit(':')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+    let nameserver = srv_server(vec![
+        (20, 1, port(&secondary), "127.0.0.1"),
+        (10, 1, port(&primary), "127.0.0.1"),
+    ]);
+
+    TestClient::no_destructor().run(|_| async {
+        let client = Client::discover_srv_from("example.com", nameserver)
+            .await
+            .unwrap()
+            .with_endpoint_selection(Selection::RoundRobin)
+            .build()
+            .unwrap();
+
+        kv::get(&client, "/foo", GetOptions::default())
+            .await
+            .unwrap();
+    });
+
+    assert_eq!(primary.requests().len(), 1);
+    assert_eq!(secondary.requests().len(), 0);
+}
+
+#[test]
+fn discover_srv_truncated() {
+    let servers: Vec<_> = (0..3)
+        .map(|_| MockServer::new(vec![(200, GET_RESPONSE)]))
+        .collect();
+    let records = servers
+        .iter()
+        .map(|server| {
+            let port = server
+                .endpoint()
+                .rsplit(':')
+                .next()
+                .unwrap()
+                .parse()
+                .unwrap();
+            (10, 1, port, "127.0.0.1")
+        })
+        .collect();
+    let nameserver = truncating_srv_server(records);
+
+    TestClient::no_destructor().run(|_| async {
+        let client = Client::discover_srv_from("example.com", nameserver)
+            .await
+            .unwrap()
+            .with_endpoint_selection(Selection::RoundRobin)
+            .build()
+            .unwrap();
+
+        for _ in 0..3 {
+            kv::get(&client, "/foo", GetOptions::default())
+                .await
+                .unwrap();
+        }
+    });
+
+    // Every record was discovered, not only the one in the truncated answer.
+    for server in &servers {
+        assert_eq!(server.requests().len(), 1);
+    }
+}
+
+#[test]
+fn discover_srv_without_records() {
+    let nameserver = srv_server(vec![]);
+
+    let result =
+        TestClient::no_destructor().run(|_| Client::discover_srv_from("example.org", nameserver));
+
+    match result {
+        Err(Error::Discovery(_)) => {}
+        Err(error) => panic!("expected Error::Discovery, got {:?}", error),
+        Ok(_) => panic!("expected Error::Discovery"),
+    }
+}
+
+#[test]
+fn discover_srv_skips_unavailable_service() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let port = server
+        .endpoint()
+        .rsplit(':')
+        .next()
+        .unwrap()
+        .parse()
+        .unwrap();
+    // A target of "." means that the service is not available at all.
+    let nameserver = srv_server(vec![(10, 1, 2379, "."), (20, 1, port, "127.0.0.1")]);
+
+    TestClient::no_destructor().run(|_| async {
+        let client = Client::discover_srv_from("example.com", nameserver)
+            .await
+            .unwrap()
+            .build()
+            .unwrap();
+
+        kv::get(&client, "/foo", GetOptions::default())
+            .await
+            .unwrap();
+    });
+
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn discover_srv_invalid_target() {
+    let nameserver = srv_server(vec![(10, 1, 2379, "not a host")]);
+
+    let result =
+        TestClient::no_destructor().run(|_| Client::discover_srv_from("example.com", nameserver));
+
+    match result {
+        Err(Error::Discovery(_)) => {}
+        Err(error) => panic!("expected Error::Discovery, got {:?}", error),
+        Ok(_) => panic!("expected Error::Discovery"),
+    }
+}
+
+#[test]
+fn bearer_token() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_bearer_token("secret-token")
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    assert!(server.requests()[0]
+        .to_lowercase()
+        .contains("authorization: bearer secret-token\r\n"));
+}
+
+#[test]
+fn invalid_bearer_token() {
+    let result = ClientBuilder::new(&["http://etcd.example.com:2379"])
+        .with_bearer_token("secret\ntoken")
+        .build();
+
+    match result {
+        Err(Error::InvalidBearerToken) => {}
+        Err(error) => panic!("expected Error::InvalidBearerToken, got {:?}", error),
+        Ok(_) => panic!("expected Error::InvalidBearerToken"),
+    }
+}
+
+#[test]
+fn bearer_token_conflicts_with_basic_auth() {
+    let result = ClientBuilder::new(&["http://etcd:2379"])
+        .with_basic_auth("root", "secret")
+        .with_bearer_token("secret-token")
+        .build();
+
+    match result {
+        Err(Error::ConflictingAuth) => {}
+        Err(error) => panic!("expected Error::ConflictingAuth, got {:?}", error),
+        Ok(_) => panic!("expected Error::ConflictingAuth"),
+    }
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn compressed_responses() {
+    let gzip = include_bytes!("compression/listing.json.gz");
+    let deflate = include_bytes!("compression/listing.json.zz");
+    let server = MockServer::with_headers(vec![
+        (200, "Content-Encoding: gzip\r\n".to_string(), &gzip[..]),
+        (
+            200,
+            "Content-Encoding: deflate\r\n".to_string(),
+            &deflate[..],
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor().run(|_| async {
+        for _ in 0..2 {
+            let response = kv::get(&client, "/services", GetOptions::default())
+                .await
+                .unwrap();
+            let nodes = response.data.node.nodes.unwrap();
+
+            assert_eq!(nodes.len(), 200);
+            assert_eq!(nodes[199].key.as_deref(), Some("/services/node-00199"));
+        }
+    });
+
+    for request in server.requests() {
+        assert!(request
+            .to_lowercase()
+            .contains("accept-encoding: gzip, deflate\r\n"));
+    }
+}
+
+/// Requests `/services` from a server that sends `body` with the given `Content-Encoding`,
+/// expecting the body to be rejected.
+#[cfg(feature = "compression")]
+fn assert_decompression_fails(encoding: &str, body: Vec<u8>, max_decompressed_size: Option<usize>) {
+    let server = MockServer::with_headers(vec![(
+        200,
+        format!("Content-Encoding: {}\r\n", encoding),
+        body,
+    )]);
+    let client = ClientBuilder::new(&[&server.endpoint()]);
+    let client = match max_decompressed_size {
+        Some(size) => client.with_max_decompressed_size(size),
+        None => client,
+    }
+    .build()
+    .unwrap();
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/services", GetOptions::default()))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::Decompression(_) => {}
+        ref error => panic!("expected Error::Decompression, got {:?}", error),
+    }
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn truncated_compressed_response() {
+    let gzip = include_bytes!("compression/listing.json.gz");
+    let deflate = include_bytes!("compression/listing.json.zz");
+
+    for encoding in ["gzip", "deflate"].iter() {
+        let body = if *encoding == "gzip" {
+            &gzip[..]
+        } else {
+            &deflate[..]
+        };
+
+        // Without the trailer, and cut off in the middle of the compressed data.
+        for len in [body.len() - 4, body.len() / 2].iter() {
+            assert_decompression_fails(encoding, body[..*len].to_vec(), None);
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn corrupt_compressed_response() {
+    let gzip = include_bytes!("compression/listing.json.gz");
+    let deflate = include_bytes!("compression/listing.json.zz");
+
+    for &(encoding, body) in [("gzip", &gzip[..]), ("deflate", &deflate[..])].iter() {
+        let mut body = body.to_vec();
+        let middle = body.len() / 2;
+        for byte in &mut body[middle..middle + 16] {
+            *byte ^= 0xff;
+        }
+
+        assert_decompression_fails(encoding, body, None);
+    }
+
+    assert_decompression_fails("gzip", b"not gzip at all".to_vec(), None);
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn oversized_compressed_response() {
+    let gzip = include_bytes!("compression/listing.json.gz");
+    let deflate = include_bytes!("compression/listing.json.zz");
+
+    for &(encoding, body) in [("gzip", &gzip[..]), ("deflate", &deflate[..])].iter() {
+        assert_decompression_fails(encoding, body.to_vec(), Some(1024));
+    }
+}
+
+#[test]
+fn tls_handshake_failure() {
+    // Answers the TLS handshake with plain HTTP.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("https://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0; 1024];
+        let _ = stream.read(&mut buffer);
+        let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n");
+    });
+    let client = ClientBuilder::new(&[&endpoint])
+        .with_tls_min_version(TlsVersion::Tls1_2)
+        .build()
+        .unwrap();
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::Tls(_) => {}
+        ref error => panic!("expected Error::Tls, got {:?}", error),
+    }
+}
+
+#[test]
+fn unsupported_tls_min_version() {
+    let result = ClientBuilder::new(&["https://etcd:2379"])
+        .with_tls_min_version(TlsVersion::Tls1_3)
+        .build();
+
+    match result {
+        Err(Error::Tls(_)) => {}
+        Err(error) => panic!("expected Error::Tls, got {:?}", error),
+        Ok(_) => panic!("expected Error::Tls"),
+    }
+}
+
+#[test]
+fn invalid_pem_identity() {
+    let result = ClientBuilder::new(&["https://etcd:2379"])
+        .with_client_identity_pem(b"not a certificate", b"not a key")
+        .build();
+
+    match result {
+        Err(Error::Tls(_)) => {}
+        Err(error) => panic!("expected Error::Tls, got {:?}", error),
+        Ok(_) => panic!("expected Error::Tls"),
+    }
+}
+
+#[test]
+fn request_and_response_hooks() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let endpoint = server.endpoint();
+    let requests = Arc::new(Mutex::new(Vec::<RequestEvent>::new()));
+    let responses = Arc::new(Mutex::new(Vec::<ResponseEvent>::new()));
+    let recorded_requests = requests.clone();
+    let recorded_responses = responses.clone();
+
+    let client = ClientBuilder::new(&["http://127.0.0.1:1", &endpoint])
+        .with_endpoint_selection(Selection::RoundRobin)
+        .on_request(Arc::new(move |event| {
+            recorded_requests.lock().unwrap().push(event.clone())
+        }))
+        .on_response(Arc::new(move |event| {
+            recorded_responses.lock().unwrap().push(event.clone())
+        }))
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].endpoint, "http://127.0.0.1:1");
+    assert_eq!(requests[1].endpoint.to_string(), format!("{}/", endpoint));
+    assert_eq!(requests[1].method, http::Method::GET);
+    assert_eq!(requests[1].path, "/v2/keys/foo");
+
+    let responses = responses.lock().unwrap();
+    assert_eq!(responses.len(), 2);
+    assert_eq!(responses[0].status, None);
+    assert_eq!(responses[1].status, Some(http::StatusCode::OK));
+    assert_eq!(responses[1].path, "/v2/keys/foo");
+}
+
+#[test]
+fn trace_context_headers() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_trace_context(Arc::new(|event| {
+            let mut headers = HeaderMap::new();
+            if event.path == "/v2/keys/foo" {
+                headers.insert(
+                    "traceparent",
+                    HeaderValue::from_static(
+                        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+                    ),
+                );
+            }
+            headers
+        }))
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    let requests = server.requests();
+    assert!(requests[0]
+        .contains("traceparent: 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01\r\n"));
+}
+
+#[test]
+fn warmup() {
+    let server = MockServer::new(vec![(200, r#"{"health":"true"}"#)]);
+    let endpoint = server.endpoint();
+    let client = Client::new(&[&endpoint, "http://127.0.0.1:1"]);
+
+    let results = TestClient::no_destructor().run(|_| client.warmup());
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].0.to_string(), format!("{}/", endpoint));
+    assert!(results[0].1.is_ok());
+    assert_eq!(results[1].0, "http://127.0.0.1:1");
+    match results[1].1 {
+        Err(Error::Connection { ref source, .. }) => assert!(source.is_connect()),
+        ref result => panic!("expected a connection error, got {:?}", result),
+    }
+    assert!(server.requests()[0].starts_with("GET /health"));
+}
+
+#[test]
+fn tcp_options() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_tcp_keepalive(Duration::from_secs(30))
+        .with_tcp_nodelay(false)
+        .build()
+        .unwrap();
+
+    let res = TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    assert_eq!(res.data.node.value.unwrap(), "bar");
+}
+
+#[test]
+fn http_version() {
+    assert_eq!(HttpVersion::default(), HttpVersion::Http1Only);
+
+    let server = MockServer::new(vec![(200, r#"{"health":"true"}"#)]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_http_version(HttpVersion::Http1Only)
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor().run(|_| client.health());
+
+    assert!(server.requests()[0].starts_with("GET /health HTTP/1.1\r\n"));
+
+    // The mock server only speaks HTTP/1.1, so it sees the HTTP/2 connection preface and the
+    // request fails.
+    let server = MockServer::new(vec![(200, r#"{"health":"true"}"#)]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_http_version(HttpVersion::Http2PriorKnowledge)
+        .build()
+        .unwrap();
+
+    let results = TestClient::no_destructor().run(|_| client.health());
+
+    assert!(server.requests()[0].starts_with("PRI * HTTP/2.0\r\n"));
+    assert!(results[0].is_err());
+}
+
+#[test]
+fn clones_share_client_across_tasks() {
+    fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+    assert_shareable::<Client>();
+
+    let tasks = 16;
+    let server = MockServer::new(vec![(200, r#"{"health":"true"}"#); tasks]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let results = TestClient::no_destructor().run(|_| async {
+        let handles: Vec<_> = (0..tasks)
+            .map(|_| {
+                let client = client.clone();
+                tokio::spawn(async move { client.health().await })
+            })
+            .collect();
+
+        let mut results = Vec::new();
+        for handle in handles {
+            results.extend(handle.await.unwrap());
+        }
+        results
+    });
+
+    assert_eq!(results.len(), tasks);
+    for result in results {
+        assert!(result.unwrap().data.health);
+    }
+    assert_eq!(server.requests().len(), tasks);
+}
+
+#[test]
+fn set_endpoints() {
+    let old_server = MockServer::new(vec![(200, r#"{"health":"true"}"#); 2]);
+    let new_server = MockServer::new(vec![(200, r#"{"health":"true"}"#)]);
+    let client = Client::new(&[&old_server.endpoint()]);
+    let clone = client.clone();
+
+    let runtime = TestClient::no_destructor();
+    runtime.run(|_| client.health())[0].as_ref().unwrap();
+
+    match client.set_endpoints(&[]) {
+        Err(Error::NoEndpoints) => {}
+        result => panic!("expected Error::NoEndpoints, got {:?}", result),
+    }
+    match client.set_endpoints(&[&new_server.endpoint(), "http://bad endpoint"]) {
+        Err(Error::InvalidUri(_)) => {}
+        result => panic!("expected Error::InvalidUri, got {:?}", result),
+    }
+    runtime.run(|_| client.health())[0].as_ref().unwrap();
+
+    client.set_endpoints(&[&new_server.endpoint()]).unwrap();
+    let results = runtime.run(|_| clone.health());
+    assert_eq!(results.len(), 1);
+    assert_eq!(
+        results[0].as_ref().unwrap().endpoint.to_string(),
+        format!("{}/", new_server.endpoint())
+    );
+    assert_eq!(old_server.requests().len(), 2);
+    assert_eq!(new_server.requests().len(), 1);
+}
+
+#[test]
+fn circuit_breaker_skips_failing_endpoints() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#,
+        );
+        3
+    ]);
+    let failing = "http://127.0.0.1:1";
+    let client = ClientBuilder::new(&[failing, &server.endpoint()])
+        .with_endpoint_selection(Selection::RoundRobin)
+        .with_circuit_breaker(CircuitConfig {
+            failure_threshold: 1,
+            open_duration: Duration::from_millis(100),
+        })
+        .build()
+        .unwrap();
+
+    let runtime = TestClient::no_destructor();
+    for _ in 0..3 {
+        runtime
+            .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+            .unwrap();
+    }
+
+    let states = client.circuit_states();
+    assert_eq!(states[0].0.to_string(), format!("{}/", failing));
+    assert_eq!(states[0].1, CircuitState::Open);
+    assert_eq!(states[1].1, CircuitState::Closed);
+    assert_eq!(server.requests().len(), 3);
+
+    thread::sleep(Duration::from_millis(150));
+    assert_eq!(client.circuit_states()[0].1, CircuitState::HalfOpen);
+}
+
+#[test]
+fn circuit_breaker_closes_after_probe() {
+    let server = MockServer::new(vec![
+        (
+            500,
+            r#"{"errorCode":300,"message":"Raft Internal Error","cause":"","index":1}"#,
+        ),
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":1,"createdIndex":1}}"#,
+        ),
+    ]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_circuit_breaker(CircuitConfig {
+            failure_threshold: 1,
+            open_duration: Duration::from_millis(50),
+        })
+        .build()
+        .unwrap();
+
+    let runtime = TestClient::no_destructor();
+    runtime
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap_err();
+    assert_eq!(client.circuit_states()[0].1, CircuitState::Open);
+
+    thread::sleep(Duration::from_millis(100));
+    runtime
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+    assert_eq!(client.circuit_states()[0].1, CircuitState::Closed);
+}
+
+#[test]
+fn circuit_breaker_probes_only_when_sent() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE); 4]);
+    let failing = "http://127.0.0.1:1";
+    let client = ClientBuilder::new(&[&server.endpoint(), failing])
+        .with_endpoint_selection(Selection::RoundRobin)
+        .with_circuit_breaker(CircuitConfig {
+            failure_threshold: 1,
+            open_duration: Duration::from_millis(100),
+        })
+        .build()
+        .unwrap();
+    let runtime = TestClient::no_destructor();
+    let get = || {
+        runtime
+            .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+            .unwrap()
+    };
+
+    // The second get tries the failing endpoint first, opening its circuit.
+    get();
+    get();
+    assert_eq!(client.circuit_states()[1].1, CircuitState::Open);
+
+    // Once half-open, a get answered by the first endpoint does not use up the probe, so the next
+    // get, which tries the failing endpoint first, probes it and opens its circuit again.
+    thread::sleep(Duration::from_millis(150));
+    get();
+    assert_eq!(client.circuit_states()[1].1, CircuitState::HalfOpen);
+    get();
+    assert_eq!(client.circuit_states()[1].1, CircuitState::Open);
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[test]
+fn read_cache() {
+    let set = r#"{"action":"set","node":{"key":"/foo/bar","value":"baz","modifiedIndex":6,"createdIndex":6}}"#;
+    let server = MockServer::new(vec![
+        (200, GET_RESPONSE),
+        (200, GET_RESPONSE),
+        (200, set),
+        (200, GET_RESPONSE),
+    ]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_read_cache(CacheConfig {
+            ttl: Duration::from_secs(60),
+            max_entries: 10,
+        })
+        .build()
+        .unwrap();
+    let runtime = TestClient::no_destructor();
+
+    let response = runtime
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+    assert!(!response.cached);
+    let response = runtime
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+    assert!(response.cached);
+    assert_eq!(response.data.node.value.unwrap(), "bar");
+    assert_eq!(server.requests().len(), 1);
+
+    // Strongly consistent gets always reach etcd.
+    let strong = GetOptions {
+        strong_consistency: true,
+        ..Default::default()
+    };
+    let response = runtime.run(|_| kv::get(&client, "/foo", strong)).unwrap();
+    assert!(!response.cached);
+    assert_eq!(server.requests().len(), 2);
+
+    // A write to a key in the directory invalidates it.
+    runtime
+        .run(|_| kv::set(&client, "/foo/bar", "baz", None))
+        .unwrap();
+    let response = runtime
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+    assert!(!response.cached);
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[test]
+fn read_cache_expires() {
+    let server = MockServer::new(vec![(200, GET_RESPONSE), (200, GET_RESPONSE)]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_read_cache(CacheConfig {
+            ttl: Duration::from_millis(50),
+            max_entries: 10,
+        })
+        .build()
+        .unwrap();
+    let runtime = TestClient::no_destructor();
+
+    runtime
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    let response = runtime
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    assert!(!response.cached);
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[test]
+fn retryable_errors() {
+    let server = MockServer::new(vec![
+        (500, RAFT_INTERNAL_ERROR),
+        (
+            404,
+            r#"{"errorCode":100,"message":"Key not found","cause":"/foo","index":5}"#,
+        ),
+        (
+            412,
+            r#"{"errorCode":101,"message":"Compare failed","cause":"[baz != bar]","index":5}"#,
+        ),
+        (503, "Service Unavailable"),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+    let unreachable = Client::new(&["http://127.0.0.1:1"]);
+    let runtime = TestClient::no_destructor();
+    let get = |client| runtime.run(|_| kv::get(client, "/foo", GetOptions::default()));
+
+    let raft_internal = get(&client).unwrap_err();
+    assert!(raft_internal[0].is_retryable());
+    let key_not_found = get(&client).unwrap_err();
+    assert!(!key_not_found[0].is_retryable());
+
+    let options = kv::CompareAndSwapOptions {
+        prev_value: Some("baz".to_string()),
+        prev_index: None,
+        ..Default::default()
+    };
+    let compare_failed = runtime
+        .run(|_| kv::compare_and_swap(&client, "/foo", "qux", None, options))
+        .unwrap_err();
+    assert!(!compare_failed[0].is_retryable());
+
+    let unavailable = get(&client).unwrap_err();
+    match unavailable[0] {
+        Error::UnexpectedStatus(status) => assert_eq!(status, 503),
+        ref error => panic!("expected Error::UnexpectedStatus, got {:?}", error),
+    }
+    assert!(unavailable[0].is_retryable());
+
+    let connection = get(&unreachable).unwrap_err();
+    assert!(connection[0].is_retryable());
+}
+
+#[test]
+fn shutdown_rejects_new_requests() {
+    let server = MockServer::new(vec![]);
+    let client = Client::new(&[&server.endpoint()]);
+    let clone = client.clone();
+
+    let (get, raw) = TestClient::no_destructor().run(|_| async {
+        client.shutdown().await;
+
+        let get = kv::get(&clone, "/foo", GetOptions::default()).await;
+        let raw = clone
+            .request_raw(format!("{}/version", server.endpoint()))
+            .await;
+        (get, raw)
+    });
+
+    match get.unwrap_err()[..] {
+        [Error::ClientClosed] => {}
+        ref errors => panic!("expected Error::ClientClosed, got {:?}", errors),
+    }
+    match raw {
+        Err(Error::ClientClosed) => {}
+        ref result => panic!("expected Error::ClientClosed, got {:?}", result),
+    }
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn shutdown_waits_for_requests_in_flight() {
+    // Accepts a single request and responds to it after a delay.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0; 1024];
+        let _ = stream.read(&mut buffer);
+        thread::sleep(Duration::from_millis(200));
+        let _ = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            GET_RESPONSE.len(),
+            GET_RESPONSE,
+        );
+    });
+    let client = Client::new(&[&endpoint]);
+
+    let (get, elapsed) = TestClient::no_destructor().run(|_| async {
+        let get_client = client.clone();
+        let get =
+            tokio::spawn(async move { kv::get(&get_client, "/foo", GetOptions::default()).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let start = std::time::Instant::now();
+        client.shutdown().await;
+        let elapsed = start.elapsed();
+        (get.await.unwrap(), elapsed)
+    });
+
+    // The response was sent 200 milliseconds after the request, 150 after the shutdown began.
+    assert!(elapsed >= Duration::from_millis(100));
+    assert_eq!(get.unwrap().data.node.value.unwrap(), "bar");
+}
+
+#[test]
+fn shutdown_grace_period() {
+    // Accepts a single watch request and never responds.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0; 1024];
+        while stream.read(&mut buffer).map(|len| len > 0).unwrap_or(false) {}
+    });
+    let client = ClientBuilder::new(&[&endpoint])
+        .with_shutdown_grace_period(Duration::from_millis(100))
+        .build()
+        .unwrap();
+
+    let elapsed = TestClient::no_destructor().run(|_| async {
+        let watch_client = client.clone();
+        let watch = tokio::spawn(async move {
+            kv::watch(&watch_client, "/foo", kv::WatchOptions::default()).await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let start = std::time::Instant::now();
+        client.shutdown().await;
+        let elapsed = start.elapsed();
+
+        assert!(!watch.is_finished());
+        watch.abort();
+        elapsed
+    });
+
+    assert!(elapsed >= Duration::from_millis(100));
+    assert!(elapsed < Duration::from_secs(5));
+}
+
+/// Records the debug messages logged by the client.
+struct RecordingLogger(Mutex<Vec<String>>);
+
+impl log::Log for RecordingLogger {
+    fn enabled(&self, metadata: &log::Metadata<'_>) -> bool {
+        metadata.target().starts_with("etcd")
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        if self.enabled(record.metadata()) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: RecordingLogger = RecordingLogger(Mutex::new(Vec::new()));
+
+#[test]
+fn logs_endpoint_failover() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+
+    let server = MockServer::new(vec![(200, GET_RESPONSE)]);
+    let client = ClientBuilder::new(&["http://127.0.0.1:1", &server.endpoint()])
+        .with_endpoint_selection(Selection::RoundRobin)
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    // Other tests may log at the same time, so only look at messages about these endpoints.
+    let endpoint = format!("{}/", server.endpoint());
+    let messages: Vec<String> = LOGGER
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|message| message.contains("127.0.0.1:1/") || message.contains(&endpoint))
+        .cloned()
+        .collect();
+
+    assert_eq!(messages.len(), 4, "{:?}", messages);
+    // Query strings are left out of the messages.
+    assert!(server.requests()[0].starts_with("GET /v2/keys/foo?"));
+    assert!(messages[0].starts_with("http://127.0.0.1:1/v2/keys/foo failed: "));
+    assert!(messages[1].starts_with(&format!(
+        "request to http://127.0.0.1:1/ failed, failing over to {}: ",
+        endpoint
+    )));
+    assert_eq!(
+        messages[2],
+        format!("{}/v2/keys/foo responded with 200 OK", server.endpoint())
+    );
+    assert_eq!(
+        messages[3],
+        format!("request succeeded on {} after 1 failed attempts", endpoint)
+    );
+}
+
+#[test]
+fn wait_healthy() {
+    let server = MockServer::new(vec![
+        (200, r#"{"health":"false"}"#),
+        (200, r#"{"health":"true"}"#),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor()
+        .run(|_| client.wait_healthy(Duration::from_secs(5), Duration::from_millis(10)))
+        .unwrap();
+
+    assert_eq!(server.requests().len(), 2);
+    assert!(server.requests()[1].starts_with("GET /health "));
+}
+
+#[test]
+fn wait_healthy_timeout() {
+    let server = MockServer::new(vec![(200, r#"{"health":"false"}"#); 10]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let error = TestClient::no_destructor()
+        .run(|_| client.wait_healthy(Duration::from_millis(50), Duration::from_millis(10)))
+        .unwrap_err();
+
+    match error {
+        Error::Unhealthy(ref endpoint) => {
+            assert_eq!(endpoint.to_string(), format!("{}/", server.endpoint()))
+        }
+        ref error => panic!("expected Error::Unhealthy, got {:?}", error),
+    }
+    // The checks were 10, 20, and 40 milliseconds apart, so no more than four were made.
+    assert!(server.requests().len() <= 4);
+}
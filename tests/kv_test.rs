@@ -1,9 +1,20 @@
-use std::time::Duration;
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
-use etcd::kv::{self, Action, GetOptions, KeyValueInfo, WatchError, WatchOptions};
-use etcd::Error;
+use futures_util::stream::StreamExt;
 
-use crate::test::TestClient;
+use etcd::kv::{
+    self, Action, CompareAndDeleteOptions, CompareAndSwapOptions, DeleteOptions, ExportEntry,
+    GetOptions, ImportOptions, KeyValueInfo, ListKeysOptions, Node, WalkOptions, WatchError,
+    WatchOptions,
+};
+use etcd::{ApiError, Client, ClientBuilder, Error, Selection};
+
+use crate::test::{MockServer, TestClient};
 
 mod test;
 
@@ -34,7 +45,7 @@ fn create_does_not_replace_existing_key() {
         Err(errors) => {
             for error in errors {
                 match error {
-                    Error::Api(ref error) => {
+                    Error::KeyAlreadyExists(ref error) => {
                         assert_eq!(error.message, "Key already exists")
                     }
                     _ => panic!("expected EtcdError due to pre-existing key"),
@@ -52,7 +63,7 @@ fn create_in_order() {
         .collect();
     let results = results.unwrap();
     let mut kvis: Vec<KeyValueInfo> = results.into_iter().map(|response| response.data).collect();
-    kvis.sort_by_key(|ref kvi| kvi.node.modified_index);
+    kvis.sort_by_key(|kvi| kvi.node.modified_index);
 
     let keys: Vec<String> = kvis.into_iter().map(|kvi| kvi.node.key.unwrap()).collect();
 
@@ -60,11 +71,35 @@ fn create_in_order() {
     assert!(keys[1] < keys[2]);
 }
 
+#[test]
+fn get_in_order() {
+    let client = TestClient::new();
+    let created_keys: Vec<String> = (1..4)
+        .map(|i| {
+            client
+                .run(|c| kv::create_in_order(c, "/test/queue", i.to_string(), None))
+                .unwrap()
+                .data
+                .node
+                .key
+                .unwrap()
+        })
+        .collect();
+
+    let res = client.run(|c| kv::get_in_order(c, "/test/queue")).unwrap();
+    let nodes = res.data.node.nodes.unwrap();
+
+    let keys: Vec<String> = nodes.iter().map(|node| node.key.clone().unwrap()).collect();
+    let values: Vec<String> = nodes.into_iter().map(|node| node.value.unwrap()).collect();
+    assert_eq!(keys, created_keys);
+    assert_eq!(values, vec!["1", "2", "3"]);
+}
+
 #[test]
 fn create_in_order_must_operate_on_a_directory() {
     let client = TestClient::new();
     client
-        .run(|c| kv::create(&c, "/test/foo", "bar", None))
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
         .unwrap();
 
     let result = client.run(|c| kv::create_in_order(c, "/test/foo", "baz", None));
@@ -80,7 +115,17 @@ fn compare_and_delete() {
     let index = res.data.node.modified_index;
 
     let res = client
-        .run(|c| kv::compare_and_delete(c, "/test/foo", Some("bar"), index))
+        .run(|c| {
+            kv::compare_and_delete(
+                c,
+                "/test/foo",
+                CompareAndDeleteOptions {
+                    prev_value: Some("bar".to_string()),
+                    prev_index: index,
+                    ..Default::default()
+                },
+            )
+        })
         .unwrap();
     assert_eq!(res.data.action, Action::CompareAndDelete);
 }
@@ -94,7 +139,16 @@ fn compare_and_delete_only_index() {
     let index = res.data.node.modified_index;
 
     let res = client
-        .run(|c| kv::compare_and_delete(c, "/test/foo", None, index))
+        .run(|c| {
+            kv::compare_and_delete(
+                c,
+                "/test/foo",
+                CompareAndDeleteOptions {
+                    prev_index: index,
+                    ..Default::default()
+                },
+            )
+        })
         .unwrap();
     assert_eq!(res.data.action, Action::CompareAndDelete);
 }
@@ -107,11 +161,120 @@ fn compare_and_delete_only_value() {
         .unwrap();
 
     let res = client
-        .run(|c| kv::compare_and_delete(c, "/test/foo", Some("bar"), None))
+        .run(|c| {
+            kv::compare_and_delete(
+                c,
+                "/test/foo",
+                CompareAndDeleteOptions {
+                    prev_value: Some("bar".to_string()),
+                    ..Default::default()
+                },
+            )
+        })
         .unwrap();
     assert_eq!(res.data.action, Action::CompareAndDelete);
 }
 
+#[test]
+fn compare_and_delete_conditions_must_match() {
+    let client = TestClient::new();
+    client
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .unwrap();
+
+    let result = client.run(|c| {
+        kv::compare_and_delete(
+            c,
+            "/test/foo",
+            CompareAndDeleteOptions {
+                prev_value: Some("other".to_string()),
+                ..Default::default()
+            },
+        )
+    });
+
+    match result {
+        Err(ref errors) => match errors[0] {
+            Error::CompareFailed(_) => {}
+            _ => panic!("expected Error::CompareFailed"),
+        },
+        _ => panic!("expected Error::CompareFailed"),
+    }
+
+    // The key must still exist.
+    client
+        .run(|c| kv::get(c, "/test/foo", GetOptions::default()))
+        .unwrap();
+}
+
+#[test]
+fn compare_and_delete_recreated_key() {
+    let client = TestClient::new();
+    let res = client
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .unwrap();
+    let index = res.data.node.modified_index;
+
+    // Another client deletes the key and creates it again with the same value.
+    client.run(|c| kv::delete(c, "/test/foo", false)).unwrap();
+    let res = client
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .unwrap();
+    let current_index = res.data.node.modified_index.unwrap();
+
+    let errors = client
+        .run(|c| {
+            kv::compare_and_delete(
+                c,
+                "/test/foo",
+                CompareAndDeleteOptions {
+                    prev_index: index,
+                    ..Default::default()
+                },
+            )
+        })
+        .unwrap_err();
+
+    match errors[0] {
+        Error::CompareFailed(ref error) => {
+            assert_eq!(error.error_code, 101);
+            assert_eq!(error.index, current_index);
+        }
+        ref error => panic!("expected Error::CompareFailed, got {:?}", error),
+    }
+
+    // The recreated key must still exist.
+    client
+        .run(|c| kv::get(c, "/test/foo", GetOptions::default()))
+        .unwrap();
+}
+
+#[test]
+fn compare_and_delete_index_mismatch() {
+    let server = MockServer::new(vec![(
+        412,
+        r#"{"errorCode":101,"message":"Compare failed","cause":"[5 != 7]","index":7}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let options = CompareAndDeleteOptions {
+        prev_index: Some(5),
+        ..Default::default()
+    };
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::compare_and_delete(&client, "/foo", options))
+        .unwrap_err();
+
+    assert!(server.requests()[0].starts_with("DELETE /v2/keys/foo?prevIndex=5 "));
+    match errors[0] {
+        Error::CompareFailed(ref error) => {
+            assert_eq!(error.index, 7);
+            assert_eq!(error.cause.as_deref(), Some("[5 != 7]"));
+        }
+        ref error => panic!("expected Error::CompareFailed, got {:?}", error),
+    }
+}
+
 #[test]
 fn compare_and_delete_requires_conditions() {
     let client = TestClient::new();
@@ -120,7 +283,8 @@ fn compare_and_delete_requires_conditions() {
         .run(|c| kv::create(c, "/test/foo", "bar", None))
         .unwrap();
 
-    let result = client.run(|c| kv::compare_and_delete(c, "/test/foo", None, None));
+    let result =
+        client.run(|c| kv::compare_and_delete(c, "/test/foo", CompareAndDeleteOptions::default()));
     match result {
         Ok(_) => panic!("expected Error::InvalidConditions"),
         Err(errors) => {
@@ -146,7 +310,19 @@ fn test_compare_and_swap() {
     let index = res.data.node.modified_index;
 
     let res = client
-        .run(|c| kv::compare_and_swap(c, "/test/foo", "baz", Some(100), Some("bar"), index))
+        .run(|c| {
+            kv::compare_and_swap(
+                c,
+                "/test/foo",
+                "baz",
+                Some(100),
+                CompareAndSwapOptions {
+                    prev_value: Some("bar".to_string()),
+                    prev_index: index,
+                    ..Default::default()
+                },
+            )
+        })
         .unwrap();
     assert_eq!(res.data.action, Action::CompareAndSwap);
 }
@@ -161,9 +337,69 @@ fn compare_and_swap_only_index() {
     let index = res.data.node.modified_index;
 
     let res = client
-        .run(|c| kv::compare_and_swap(c, "/test/foo", "baz", None, None, index))
+        .run(|c| {
+            kv::compare_and_swap(
+                c,
+                "/test/foo",
+                "baz",
+                None,
+                CompareAndSwapOptions {
+                    prev_index: index,
+                    ..Default::default()
+                },
+            )
+        })
+        .unwrap();
+    assert_eq!(res.data.action, Action::CompareAndSwap);
+}
+
+#[test]
+fn clear_ttl() {
+    let client = TestClient::new();
+
+    client
+        .run(|c| kv::set(c, "/test/foo", "bar", Some(100)))
         .unwrap();
+
+    let res = client.run(|c| kv::clear_ttl(c, "/test/foo")).unwrap();
     assert_eq!(res.data.action, Action::CompareAndSwap);
+
+    let node = client
+        .run(|c| kv::get(c, "/test/foo", GetOptions::default()))
+        .unwrap()
+        .data
+        .node;
+    assert_eq!(node.value.unwrap(), "bar");
+    assert_eq!(node.ttl, None);
+    assert_eq!(node.expiration, None);
+}
+
+#[test]
+fn clear_ttl_concurrent_write() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"bar","ttl":100,"modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (
+            412,
+            r#"{"errorCode":101,"message":"Compare failed","cause":"[5 != 6]","index":6}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::clear_ttl(&client, "/foo"))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::CompareFailed(ref error) => assert_eq!(error.index, 6),
+        ref error => panic!("expected Error::CompareFailed, got {:?}", error),
+    }
+
+    let requests = server.requests();
+    assert!(requests[0].starts_with("GET /v2/keys/foo"));
+    assert!(requests[1].starts_with("PUT /v2/keys/foo"));
 }
 
 #[test]
@@ -175,11 +411,54 @@ fn compare_and_swap() {
         .unwrap();
 
     let res = client
-        .run(|c| kv::compare_and_swap(c, "/test/foo", "baz", None, Some("bar"), None))
+        .run(|c| {
+            kv::compare_and_swap(
+                c,
+                "/test/foo",
+                "baz",
+                None,
+                CompareAndSwapOptions {
+                    prev_value: Some("bar".to_string()),
+                    ..Default::default()
+                },
+            )
+        })
         .unwrap();
     assert_eq!(res.data.action, Action::CompareAndSwap);
 }
 
+#[test]
+fn compare_and_swap_conditions_must_match() {
+    let client = TestClient::new();
+
+    let res = client
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .unwrap();
+    let index = res.data.node.modified_index;
+
+    let result = client.run(|c| {
+        kv::compare_and_swap(
+            c,
+            "/test/foo",
+            "baz",
+            None,
+            CompareAndSwapOptions {
+                prev_value: Some("other".to_string()),
+                prev_index: index,
+                ..Default::default()
+            },
+        )
+    });
+
+    match result {
+        Err(ref errors) => match errors[0] {
+            Error::CompareFailed(ref error) => assert_eq!(error.error_code, 101),
+            _ => panic!("expected Error::CompareFailed"),
+        },
+        _ => panic!("expected Error::CompareFailed"),
+    }
+}
+
 #[test]
 fn compare_and_swap_requires_conditions() {
     let client = TestClient::new();
@@ -188,7 +467,15 @@ fn compare_and_swap_requires_conditions() {
         .run(|c| kv::create(c, "/test/foo", "bar", None))
         .unwrap();
 
-    let result = client.run(|c| kv::compare_and_swap(c, "/test/foo", "baz", None, None, None));
+    let result = client.run(|c| {
+        kv::compare_and_swap(
+            c,
+            "/test/foo",
+            "baz",
+            None,
+            CompareAndSwapOptions::default(),
+        )
+    });
     match result {
         Ok(_) => panic!("expected Error::InvalidConditions"),
         Err(errors) => {
@@ -222,6 +509,122 @@ fn get() {
     assert_eq!(node.ttl.unwrap(), 60);
 }
 
+#[test]
+fn get_strong_consistency() {
+    let client = TestClient::new();
+
+    client
+        .run(|c| kv::set(c, "/test/foo", "bar", None))
+        .unwrap();
+
+    let res = client
+        .run(|c| {
+            kv::get(
+                c,
+                "/test/foo",
+                GetOptions {
+                    strong_consistency: true,
+                    ..Default::default()
+                },
+            )
+        })
+        .unwrap();
+    assert_eq!(res.data.node.value.unwrap(), "bar");
+}
+
+#[test]
+fn get_strong_consistency_recursive() {
+    let client = TestClient::new();
+
+    client
+        .run(|c| kv::set(c, "/test/dir/foo", "bar", None))
+        .unwrap();
+    client
+        .run(|c| kv::set(c, "/test/dir/foo", "baz", None))
+        .unwrap();
+
+    let options = GetOptions {
+        recursive: true,
+        strong_consistency: true,
+        ..Default::default()
+    };
+    let res = client.run(|c| kv::get(c, "/test/dir", options)).unwrap();
+    let nodes = res.data.node.nodes.unwrap();
+    assert_eq!(nodes[0].value.as_ref().unwrap(), "baz");
+}
+
+#[test]
+fn get_strong_consistency_query_parameters() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"action":"get","node":{"key":"/foo","dir":true,"modifiedIndex":5,"createdIndex":5}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let options = GetOptions {
+        recursive: true,
+        strong_consistency: true,
+        ..Default::default()
+    };
+    TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", options))
+        .unwrap();
+
+    let request = &server.requests()[0];
+    assert!(request.starts_with("GET /v2/keys/foo?"));
+    assert!(request.contains("recursive=true"));
+    assert!(request.contains("quorum=true"));
+}
+
+#[test]
+fn get_many() {
+    let client = TestClient::new();
+
+    client
+        .run(|c| kv::set(c, "/test/foo", "bar", None))
+        .unwrap();
+    client
+        .run(|c| kv::set(c, "/test/baz", "qux", None))
+        .unwrap();
+
+    let results: HashMap<_, _> = client.run(|c| {
+        kv::get_many(
+            c,
+            &["/test/foo", "/test/missing", "/test/baz"],
+            GetOptions::default(),
+        )
+        .collect()
+    });
+
+    assert_eq!(results.len(), 3);
+    let value = |key: &str| results[key].as_ref().unwrap().data.node.value.clone();
+    assert_eq!(value("/test/foo").unwrap(), "bar");
+    assert_eq!(value("/test/baz").unwrap(), "qux");
+    match results["/test/missing"] {
+        Err(ref errors) => match errors[0] {
+            Error::KeyNotFound(_) => {}
+            _ => panic!("expected Error::KeyNotFound"),
+        },
+        _ => panic!("expected Error::KeyNotFound"),
+    }
+}
+
+#[test]
+fn get_missing_key() {
+    let client = TestClient::no_destructor();
+
+    match client.run(|c| kv::get(c, "/test/foo", GetOptions::default())) {
+        Err(ref errors) => match errors[0] {
+            Error::KeyNotFound(ref error) => {
+                assert_eq!(error.cause.as_deref(), Some("/test/foo"));
+                assert_eq!(errors[0].api_error().unwrap().error_code, 100);
+            }
+            _ => panic!("expected Error::KeyNotFound"),
+        },
+        _ => panic!("expected Error::KeyNotFound"),
+    }
+}
+
 #[test]
 fn get_non_recursive() {
     let client = TestClient::new();
@@ -247,11 +650,11 @@ fn get_non_recursive() {
         .unwrap();
 
     let node = res.data.node;
-    assert_eq!(node.dir.unwrap(), true);
+    assert!(node.dir.unwrap());
 
     let nodes = node.nodes.unwrap();
     assert_eq!(nodes[0].clone().key.unwrap(), "/test/dir");
-    assert_eq!(nodes[0].clone().dir.unwrap(), true);
+    assert!(nodes[0].clone().dir.unwrap());
     assert_eq!(nodes[1].clone().key.unwrap(), "/test/foo");
     assert_eq!(nodes[1].clone().value.unwrap(), "bar");
 }
@@ -285,6 +688,46 @@ fn get_recursive() {
     );
 }
 
+#[test]
+fn get_recursive_sorted() {
+    let client = TestClient::new();
+
+    for key in &["/test/c", "/test/a/z", "/test/b", "/test/a/x", "/test/a/y"] {
+        client.run(|c| kv::set(c, key, "value", None)).unwrap();
+    }
+
+    let get_keys = || {
+        let res = client
+            .run(|c| {
+                kv::get(
+                    c,
+                    "/test",
+                    GetOptions {
+                        recursive: true,
+                        sort: true,
+                        ..Default::default()
+                    },
+                )
+            })
+            .unwrap();
+        let nodes = res.data.node.nodes.unwrap();
+        let keys: Vec<String> = nodes.iter().map(|node| node.key.clone().unwrap()).collect();
+        let child_keys: Vec<String> = nodes[0]
+            .clone()
+            .nodes
+            .unwrap()
+            .into_iter()
+            .map(|node| node.key.unwrap())
+            .collect();
+        (keys, child_keys)
+    };
+
+    let (keys, child_keys) = get_keys();
+    assert_eq!(keys, vec!["/test/a", "/test/b", "/test/c"]);
+    assert_eq!(child_keys, vec!["/test/a/x", "/test/a/y", "/test/a/z"]);
+    assert_eq!(get_keys(), (keys, child_keys));
+}
+
 #[test]
 fn get_root() {
     let client = TestClient::new();
@@ -302,51 +745,452 @@ fn get_root() {
     assert!(node.created_index.is_none());
     assert!(node.modified_index.is_none());
     assert_eq!(node.nodes.unwrap().len(), 1);
-    assert_eq!(node.dir.unwrap(), true);
+    assert!(node.dir.unwrap());
 }
 
 #[test]
-fn https() {
-    let client = TestClient::https(true);
-
-    client
-        .run(|c| kv::set(c, "/test/foo", "bar", Some(60)))
-        .unwrap();
+fn api_error_deserialization() {
+    let error: ApiError = serde_json::from_str(
+        r#"{"errorCode":101,"message":"Compare failed","cause":"[two != one]","index":8}"#,
+    )
+    .unwrap();
+    assert_eq!(error.error_code, 101);
+    assert_eq!(error.message, "Compare failed");
+    assert_eq!(error.cause.as_deref(), Some("[two != one]"));
+    assert_eq!(error.index, 8);
+    assert!(error.cluster_info.is_none());
+
+    let error: ApiError = serde_json::from_str(
+        r#"{"errorCode":209,"message":"Invalid field","cause":"invalid value for \"ttl\"","index":0}"#,
+    )
+    .unwrap();
+    assert_eq!(error.error_code, 209);
+    assert_eq!(error.cause.as_deref(), Some("invalid value for \"ttl\""));
+
+    let error: ApiError =
+        serde_json::from_str(r#"{"errorCode":300,"message":"Raft Internal Error","index":12}"#)
+            .unwrap();
+    assert_eq!(error.cause, None);
+    assert_eq!(error.index, 12);
 }
 
 #[test]
-fn https_without_valid_client_certificate() {
-    let client = TestClient::https(false);
-    let res = client.run(|c| kv::set(c, "/test/foo", "bar", Some(60)));
-    assert!(res.is_err());
+fn compare_and_swap_error_index() {
+    let server = MockServer::new(vec![(
+        412,
+        r#"{"errorCode":101,"message":"Compare failed","cause":"[two != one]","index":8}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+    let options = CompareAndSwapOptions {
+        prev_value: Some("two".to_string()),
+        prev_index: None,
+        ..Default::default()
+    };
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::compare_and_swap(&client, "/foo", "three", None, options))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::CompareFailed(ref error) => assert_eq!(error.index, 8),
+        ref error => panic!("expected Error::CompareFailed, got {:?}", error),
+    }
 }
 
 #[test]
-fn set() {
-    let client = TestClient::new();
+fn watch_recursive_query_parameter() {
+    let body = r#"{"action":"set","node":{"key":"/foo/bar","value":"baz","modifiedIndex":7,"createdIndex":7}}"#;
+    let server = MockServer::new(vec![(200, body), (200, body)]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor().run(|_| async {
+        kv::watch(&client, "/foo", WatchOptions::default())
+            .await
+            .unwrap();
+
+        let options = WatchOptions {
+            index: Some(7),
+            recursive: true,
+            ..Default::default()
+        };
+        kv::watch(&client, "/foo", options).await.unwrap();
+    });
 
-    let res = client
-        .run(|c| kv::set(c, "/test/foo", "baz", None))
+    let requests = server.requests();
+    assert!(requests[0].contains("recursive=false"));
+    assert!(requests[0].contains("wait=true"));
+    assert!(!requests[0].contains("waitIndex"));
+    assert!(requests[1].contains("recursive=true"));
+    assert!(requests[1].contains("waitIndex=7"));
+}
+
+#[test]
+fn watch_without_values() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"action":"set","node":{"key":"/foo","value":"baz","modifiedIndex":7,"createdIndex":7},"prevNode":{"key":"/foo","value":"bar","modifiedIndex":6,"createdIndex":6}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let options = WatchOptions {
+        without_values: true,
+        ..Default::default()
+    };
+    let response = TestClient::no_destructor()
+        .run(|_| kv::watch(&client, "/foo", options))
         .unwrap();
-    assert_eq!(res.data.action, Action::Set);
 
-    let node = res.data.node;
-    assert_eq!(node.value.unwrap(), "baz");
-    assert!(node.ttl.is_none());
+    assert!(server.requests()[0].contains("noValueOnSuccess=true"));
+    assert_eq!(response.data.action, Action::Set);
+    assert_eq!(response.data.node.key.unwrap(), "/foo");
+    assert_eq!(response.data.node.modified_index, Some(7));
+    assert_eq!(response.data.node.value, None);
+    assert_eq!(response.data.prev_node.unwrap().value, None);
 }
 
 #[test]
-fn set_and_refresh() {
-    let client = TestClient::new();
+fn wait_delete_absent_key() {
+    let server = MockServer::new(vec![(
+        404,
+        r#"{"errorCode":100,"message":"Key not found","cause":"/foo","index":3}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor()
+        .run(|_| kv::wait_delete(&client, "/foo", None))
+        .unwrap();
 
-    let res = client
-        .run(|c| kv::set(c, "/test/foo", "baz", Some(30)))
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn wait_delete_skips_updates() {
+    let server = MockServer::with_headers(vec![
+        (
+            200,
+            "X-Etcd-Index: 5\r\n".to_string(),
+            r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (
+            200,
+            String::new(),
+            r#"{"action":"set","node":{"key":"/foo","value":"baz","modifiedIndex":8,"createdIndex":8}}"#,
+        ),
+        (
+            200,
+            String::new(),
+            r#"{"action":"delete","node":{"key":"/foo","modifiedIndex":9,"createdIndex":8}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor()
+        .run(|_| kv::wait_delete(&client, "/foo", None))
         .unwrap();
-    assert_eq!(res.data.action, Action::Set);
 
-    let node = res.data.node;
-    assert_eq!(node.value.unwrap(), "baz");
-    assert!(node.ttl.is_some());
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests[1].contains("waitIndex=6"));
+    assert!(requests[2].contains("waitIndex=9"));
+}
+
+#[test]
+fn dry_run() {
+    let server = MockServer::new(vec![]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_dry_run(true)
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor().run(|_| async {
+        let res = kv::set(&client, "/foo", "bar", Some(60)).await.unwrap();
+        assert!(res.dry_run);
+        assert_eq!(res.data.action, Action::Set);
+        assert_eq!(res.data.node.key.as_deref(), Some("/foo"));
+        assert_eq!(res.data.node.value.as_deref(), Some("bar"));
+        assert_eq!(res.data.node.ttl, Some(60));
+        assert_eq!(res.cluster_info.etcd_index, None);
+
+        let res = kv::update(&client, "/foo", "baz", None).await.unwrap();
+        assert_eq!(res.data.action, Action::Update);
+
+        let res = kv::delete(&client, "/foo", false).await.unwrap();
+        assert!(res.dry_run);
+        assert_eq!(res.data.action, Action::Delete);
+
+        let errors = kv::compare_and_swap(&client, "/foo", "baz", None, Default::default())
+            .await
+            .unwrap_err();
+        match errors[0] {
+            Error::InvalidConditions => {}
+            ref error => panic!("expected Error::InvalidConditions, got {:?}", error),
+        }
+    });
+
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn export() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"action":"get","node":{"key":"/app","dir":true,"nodes":[{"key":"/app/config","dir":true,"nodes":[{"key":"/app/config/port","value":"80","modifiedIndex":3,"createdIndex":3}],"modifiedIndex":2,"createdIndex":2},{"key":"/app/empty","dir":true,"modifiedIndex":4,"createdIndex":4},{"key":"/app/lease","value":"a","expiration":"2030-01-01T00:00:00Z","ttl":30,"modifiedIndex":5,"createdIndex":5}],"modifiedIndex":1,"createdIndex":1}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let entries = TestClient::no_destructor()
+        .run(|_| kv::export(&client, "/app"))
+        .unwrap();
+
+    let keys: Vec<&str> = entries.iter().map(|entry| entry.key.as_str()).collect();
+    assert_eq!(
+        keys,
+        vec![
+            "/app",
+            "/app/config",
+            "/app/config/port",
+            "/app/empty",
+            "/app/lease"
+        ]
+    );
+    assert!(entries[3].dir);
+    assert_eq!(entries[3].value, None);
+    assert!(!entries[4].dir);
+    assert_eq!(entries[4].value.as_deref(), Some("a"));
+    assert_eq!(entries[4].ttl, Some(30));
+    assert_eq!(
+        entries[4].expiration.as_deref(),
+        Some("2030-01-01T00:00:00Z")
+    );
+
+    let request = &server.requests()[0];
+    assert!(request.contains("recursive=true"));
+    assert!(request.contains("quorum=true"));
+}
+
+#[test]
+fn import() {
+    let server = MockServer::new(vec![
+        (
+            201,
+            r#"{"action":"create","node":{"key":"/app","dir":true,"modifiedIndex":1,"createdIndex":1}}"#,
+        ),
+        (
+            412,
+            r#"{"errorCode":105,"message":"Key already exists","cause":"/app/a","index":1}"#,
+        ),
+        (
+            403,
+            r#"{"errorCode":102,"message":"Not a file","cause":"/app/b","index":1}"#,
+        ),
+        (
+            201,
+            r#"{"action":"create","node":{"key":"/app/c","value":"3","ttl":30,"modifiedIndex":2,"createdIndex":2}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+    let entry = |key: &str, dir, value: Option<&str>, ttl| ExportEntry {
+        dir,
+        expiration: None,
+        key: key.to_string(),
+        ttl,
+        value: value.map(str::to_string),
+    };
+    let entries = vec![
+        entry("/app", true, None, None),
+        entry("/app/a", false, Some("1"), None),
+        entry("/app/b", false, Some("2"), None),
+        entry("/app/c", false, Some("3"), Some(30)),
+    ];
+    let options = ImportOptions {
+        overwrite: false,
+        preserve_ttls: true,
+    };
+
+    let summary = TestClient::no_destructor().run(|_| kv::import(&client, entries, options));
+
+    assert_eq!(summary.created, vec!["/app", "/app/c"]);
+    assert_eq!(summary.skipped, vec!["/app/a"]);
+    assert_eq!(summary.failed.len(), 1);
+    assert_eq!(summary.failed[0].0, "/app/b");
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 4);
+    assert!(requests
+        .iter()
+        .all(|request| request.starts_with("PUT /v2/keys/app")));
+}
+
+#[test]
+fn export_and_import() {
+    let client = TestClient::new();
+
+    client.run(|c| async move {
+        kv::set(c, "/test/backup/a", "1", None).await.unwrap();
+        kv::set(c, "/test/backup/dir/b", "2", Some(100))
+            .await
+            .unwrap();
+        kv::create_dir(c, "/test/backup/empty", None).await.unwrap();
+
+        let exported = kv::export(c, "/test/backup").await.unwrap();
+        kv::delete(c, "/test/backup", true).await.unwrap();
+
+        let options = ImportOptions {
+            overwrite: false,
+            preserve_ttls: true,
+        };
+        let summary = kv::import(c, exported.clone(), options).await;
+        assert_eq!(summary.created.len(), exported.len());
+        assert!(summary.failed.is_empty());
+
+        let imported = kv::export(c, "/test/backup").await.unwrap();
+        let keys = |entries: &[ExportEntry]| {
+            entries
+                .iter()
+                .map(|entry| (entry.key.clone(), entry.dir, entry.value.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&imported), keys(&exported));
+        assert!(imported
+            .iter()
+            .find(|entry| entry.key == "/test/backup/dir/b")
+            .unwrap()
+            .ttl
+            .is_some());
+
+        let summary = kv::import(c, exported.clone(), options).await;
+        assert_eq!(summary.skipped.len(), exported.len());
+    });
+}
+
+#[test]
+fn get_hidden_node() {
+    let client = TestClient::new();
+
+    client.run(|c| async move {
+        kv::set(c, "/test/dir/visible", "1", None).await.unwrap();
+        kv::set(c, "/test/dir/_hidden", "2", None).await.unwrap();
+
+        let options = GetOptions {
+            recursive: true,
+            ..Default::default()
+        };
+        let res = kv::get(c, "/test/dir", options).await.unwrap();
+        let keys: Vec<String> = res
+            .data
+            .node
+            .nodes
+            .unwrap()
+            .into_iter()
+            .map(|node| node.key.unwrap())
+            .collect();
+        assert_eq!(keys, vec!["/test/dir/visible"]);
+
+        let res = kv::get(c, "/test/dir/_hidden", GetOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(res.data.node.value.unwrap(), "2");
+    });
+}
+
+#[test]
+fn https() {
+    let client = TestClient::https(true);
+
+    client
+        .run(|c| kv::set(c, "/test/foo", "bar", Some(60)))
+        .unwrap();
+}
+
+#[test]
+fn https_with_pem_identity() {
+    let client = TestClient::https_pem();
+
+    client
+        .run(|c| kv::set(c, "/test/foo", "bar", Some(60)))
+        .unwrap();
+}
+
+#[test]
+fn https_without_valid_client_certificate() {
+    let client = TestClient::https(false);
+    let res = client.run(|c| kv::set(c, "/test/foo", "bar", Some(60)));
+    assert!(res.is_err());
+}
+
+#[test]
+fn set() {
+    let client = TestClient::new();
+
+    let res = client
+        .run(|c| kv::set(c, "/test/foo", "baz", None))
+        .unwrap();
+    assert_eq!(res.data.action, Action::Set);
+
+    let node = res.data.node;
+    assert_eq!(node.value.unwrap(), "baz");
+    assert!(node.ttl.is_none());
+}
+
+#[test]
+fn set_returns_prev_node() {
+    let client = TestClient::new();
+
+    let res = client
+        .run(|c| kv::set(c, "/test/foo", "bar", None))
+        .unwrap();
+    assert!(res.data.prev_node.is_none());
+
+    let res = client
+        .run(|c| kv::set(c, "/test/foo", "baz", None))
+        .unwrap();
+    let prev_node = res.data.prev_node.unwrap();
+    assert_eq!(prev_node.value.unwrap(), "bar");
+    assert_eq!(res.data.node.value.unwrap(), "baz");
+
+    let res = client
+        .run(|c| kv::update(c, "/test/foo", "qux", None))
+        .unwrap();
+    assert_eq!(res.data.prev_node.unwrap().value.unwrap(), "baz");
+
+    let res = client.run(|c| kv::delete(c, "/test/foo", false)).unwrap();
+    assert_eq!(res.data.prev_node.unwrap().value.unwrap(), "qux");
+}
+
+#[test]
+fn prev_node_deserialization() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"action":"compareAndSwap","node":{"key":"/foo","value":"baz","modifiedIndex":9,"createdIndex":5},"prevNode":{"key":"/foo","value":"bar","modifiedIndex":5,"createdIndex":5}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+    let options = CompareAndSwapOptions {
+        prev_value: Some("bar".to_string()),
+        prev_index: None,
+        ..Default::default()
+    };
+
+    let res = TestClient::no_destructor()
+        .run(|_| kv::compare_and_swap(&client, "/foo", "baz", None, options))
+        .unwrap();
+
+    let prev_node = res.data.prev_node.unwrap();
+    assert_eq!(prev_node.value.as_deref(), Some("bar"));
+    assert_eq!(prev_node.modified_index, Some(5));
+}
+
+#[test]
+fn set_and_refresh() {
+    let client = TestClient::new();
+
+    let res = client
+        .run(|c| kv::set(c, "/test/foo", "baz", Some(30)))
+        .unwrap();
+    assert_eq!(res.data.action, Action::Set);
+
+    let node = res.data.node;
+    assert_eq!(node.value.unwrap(), "baz");
+    assert!(node.ttl.is_some());
 
     let res = client.run(|c| kv::refresh(c, "/test/foo", 30)).unwrap();
     assert_eq!(res.data.action, Action::Update);
@@ -356,15 +1200,71 @@ fn set_and_refresh() {
     assert!(node.ttl.is_some());
 }
 
+#[test]
+fn refresh_does_not_notify_watchers() {
+    let client = TestClient::new();
+
+    let res = client
+        .run(|c| kv::set(c, "/test/foo", "baz", Some(30)))
+        .unwrap();
+    let index = res.data.node.modified_index.unwrap();
+
+    client.run(|c| kv::refresh(c, "/test/foo", 60)).unwrap();
+
+    let res = client.run(|c| {
+        kv::watch(
+            c,
+            "/test/foo",
+            WatchOptions {
+                index: Some(index + 1),
+                timeout: Some(Duration::from_millis(100)),
+                ..Default::default()
+            },
+        )
+    });
+
+    match res {
+        Err(WatchError::Timeout) => {}
+        _ => panic!("expected WatchError::Timeout"),
+    }
+}
+
+#[test]
+fn refresh_key_without_ttl() {
+    let client = TestClient::new();
+
+    client
+        .run(|c| kv::set(c, "/test/foo", "bar", None))
+        .unwrap();
+    let res = client.run(|c| kv::refresh(c, "/test/foo", 60)).unwrap();
+
+    let node = res.data.node;
+    assert_eq!(node.value.unwrap(), "bar");
+    assert!(node.ttl.unwrap() > 0 && node.ttl.unwrap() <= 60);
+}
+
+#[test]
+fn refresh_requires_existing_key() {
+    let client = TestClient::no_destructor();
+
+    match client.run(|c| kv::refresh(c, "/test/foo", 30)) {
+        Err(ref errors) => match errors[0] {
+            Error::KeyNotFound(ref error) => assert_eq!(error.message, "Key not found"),
+            _ => panic!("expected EtcdError due to missing key"),
+        },
+        _ => panic!("expected EtcdError due to missing key"),
+    }
+}
+
 #[test]
 fn set_dir() {
     let client = TestClient::new();
 
     client.run(|c| kv::set_dir(c, "/test", None)).unwrap();
-    match client.run(|c| kv::set_dir(c, "/test", None)) {
-        Ok(_) => panic!("set_dir should fail on an existing dir"),
-        Err(_) => {}
-    }
+    assert!(
+        client.run(|c| kv::set_dir(c, "/test", None)).is_err(),
+        "set_dir should fail on an existing dir"
+    );
 
     client
         .run(|c| kv::set(c, "/test/foo", "bar", None))
@@ -396,7 +1296,7 @@ fn update_requires_existing_key() {
 
     match client.run(|c| kv::update(c, "/test/foo", "bar", None)) {
         Err(ref errors) => match errors[0] {
-            Error::Api(ref error) => assert_eq!(error.message, "Key not found"),
+            Error::KeyNotFound(ref error) => assert_eq!(error.message, "Key not found"),
             _ => panic!("expected EtcdError due to missing key"),
         },
         _ => panic!("expected EtcdError due to missing key"),
@@ -460,101 +1360,646 @@ fn create_dir() {
     assert_eq!(res.data.action, Action::Create);
 
     let node = res.data.node;
-    assert!(node.dir.is_some());
+    assert_eq!(node.dir, Some(true));
     assert!(node.value.is_none());
+    assert!(node.nodes.unwrap_or_default().is_empty());
 }
 
 #[test]
-fn delete_dir() {
+fn create_dir_with_ttl() {
+    let client = TestClient::new();
+
+    let res = client
+        .run(|c| kv::create_dir(c, "/test/dir", Some(60)))
+        .unwrap();
+
+    let node = res.data.node;
+    assert_eq!(node.dir, Some(true));
+    assert!(node.ttl.unwrap() > 0 && node.ttl.unwrap() <= 60);
+    assert!(node.expiration.is_some());
+}
+
+#[test]
+fn create_dir_does_not_replace_existing_key() {
     let client = TestClient::new();
     client
         .run(|c| kv::create_dir(c, "/test/dir", None))
         .unwrap();
-    let res = client.run(|c| kv::delete_dir(c, "/test/dir")).unwrap();
-    assert_eq!(res.data.action, Action::Delete);
+
+    match client.run(|c| kv::create_dir(c, "/test/dir", None)) {
+        Err(ref errors) => match errors[0] {
+            Error::KeyAlreadyExists(_) => {}
+            _ => panic!("expected Error::KeyAlreadyExists"),
+        },
+        _ => panic!("expected Error::KeyAlreadyExists"),
+    }
 }
 
 #[test]
-fn watch() {
+fn delete_dir() {
     let client = TestClient::new();
-    let create_response = client
-        .run(|c| kv::create(&c, "/test/foo", "bar", None))
+    client
+        .run(|c| kv::create_dir(c, "/test/dir", None))
         .unwrap();
-    let set_response = client
-        .run(|c| kv::set(c, "/test/foo", "baz", None))
+    let res = client
+        .run(|c| kv::delete_dir(c, "/test/dir", false))
         .unwrap();
-    let watch_response = client
-        .run(|c| {
-            kv::watch(
-                c,
-                "/test/foo",
-                WatchOptions {
-                    index: Some(create_response.data.node.created_index.unwrap() + 1),
-                    ..Default::default()
-                },
-            )
-        })
+    assert_eq!(res.data.action, Action::Delete);
+}
+
+#[test]
+fn delete_dir_requires_empty_dir() {
+    let client = TestClient::new();
+    client
+        .run(|c| kv::set(c, "/test/dir/foo", "bar", None))
         .unwrap();
 
-    assert_eq!(watch_response.data.node.value.unwrap(), "baz");
-    assert_eq!(
-        watch_response.data.node.modified_index.unwrap(),
-        set_response.data.node.modified_index.unwrap()
-    );
+    match client.run(|c| kv::delete_dir(c, "/test/dir", false)) {
+        Err(ref errors) => match errors[0] {
+            Error::DirectoryNotEmpty(_) => {}
+            _ => panic!("expected Error::DirectoryNotEmpty"),
+        },
+        _ => panic!("expected Error::DirectoryNotEmpty"),
+    }
 }
 
 #[test]
-fn watch_cancel() {
+fn delete_dir_recursive() {
     let client = TestClient::new();
     client
-        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .run(|c| kv::set(c, "/test/dir/foo", "bar", None))
         .unwrap();
 
-    let res = client.run(|c| {
-        kv::watch(
-            c,
-            "/test/foo",
-            WatchOptions {
-                timeout: Some(Duration::from_millis(1)),
-                ..Default::default()
-            },
-        )
-    });
+    let res = client
+        .run(|c| kv::delete_dir(c, "/test/dir", true))
+        .unwrap();
+    assert_eq!(res.data.action, Action::Delete);
+}
 
-    match res {
-        Err(WatchError::Timeout) => {}
-        _ => panic!("expected WatchError::Timeout"),
+#[test]
+fn delete_dir_query_parameters() {
+    let dir =
+        r#"{"action":"get","node":{"key":"/dir","dir":true,"modifiedIndex":4,"createdIndex":4}}"#;
+    let deleted = r#"{"action":"delete","node":{"key":"/dir","dir":true,"modifiedIndex":5,"createdIndex":4},"prevNode":{"key":"/dir","dir":true,"modifiedIndex":4,"createdIndex":4}}"#;
+    let server = MockServer::new(vec![
+        (200, dir),
+        (200, deleted),
+        (200, dir),
+        (
+            403,
+            r#"{"errorCode":108,"message":"Directory not empty","cause":"/dir","index":4}"#,
+        ),
+        (200, dir),
+        (200, deleted),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+    let runtime = TestClient::no_destructor();
+
+    runtime
+        .run(|_| kv::delete_dir(&client, "/dir", false))
+        .unwrap();
+    match runtime.run(|_| kv::delete_dir(&client, "/dir", false)) {
+        Err(ref errors) => match errors[0] {
+            Error::DirectoryNotEmpty(_) => {}
+            _ => panic!("expected Error::DirectoryNotEmpty"),
+        },
+        _ => panic!("expected Error::DirectoryNotEmpty"),
+    }
+    runtime
+        .run(|_| kv::delete_dir(&client, "/dir", true))
+        .unwrap();
+
+    let requests = server.requests();
+    assert!(requests[1].starts_with("DELETE /v2/keys/dir?dir=true "));
+    assert!(requests[3].starts_with("DELETE /v2/keys/dir?dir=true "));
+    assert!(requests[5].starts_with("DELETE /v2/keys/dir?recursive=true&dir=true "));
+}
+
+#[test]
+fn delete_dir_of_key_value_pair() {
+    let server = MockServer::with_headers(vec![(
+        200,
+        "X-Etcd-Index: 7\r\n".to_string(),
+        r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":7,"createdIndex":7}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::delete_dir(&client, "/foo", true))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::NotADirectory(ref error) => {
+            assert_eq!(error.error_code, 104);
+            assert_eq!(error.cause.as_deref(), Some("/foo"));
+            assert_eq!(error.index, 7);
+        }
+        _ => panic!("expected Error::NotADirectory"),
     }
+    assert_eq!(server.requests().len(), 1);
 }
 
 #[test]
-fn watch_index() {
+fn delete_with_options() {
     let client = TestClient::new();
-    let res = client
+    let response = client
         .run(|c| kv::set(c, "/test/foo", "bar", None))
         .unwrap();
-    let index = res.data.node.modified_index;
+    let index = response.data.node.modified_index;
+
+    let options = DeleteOptions {
+        prev_value: Some("baz".to_string()),
+        ..Default::default()
+    };
+    match client.run(|c| kv::delete_with_options(c, "/test/foo", options)) {
+        Err(ref errors) => match errors[0] {
+            Error::CompareFailed(_) => {}
+            _ => panic!("expected Error::CompareFailed"),
+        },
+        _ => panic!("expected Error::CompareFailed"),
+    }
+
+    let options = DeleteOptions {
+        prev_value: Some("bar".to_string()),
+        prev_index: index,
+        ..Default::default()
+    };
     let res = client
-        .run(|c| {
-            kv::watch(
-                c,
-                "/test/foo",
-                WatchOptions {
-                    index,
-                    ..Default::default()
-                },
-            )
-        })
+        .run(|c| kv::delete_with_options(c, "/test/foo", options))
         .unwrap();
-    let node = res.data.node;
-
-    assert_eq!(node.modified_index, index);
-    assert_eq!(node.value.unwrap(), "bar");
+    assert_eq!(res.data.action, Action::CompareAndDelete);
 }
 
 #[test]
-fn watch_recursive() {
-    let client = TestClient::new();
+fn delete_with_options_query_parameters() {
+    let deleted = r#"{"action":"delete","node":{"key":"/foo","modifiedIndex":5,"createdIndex":4}}"#;
+    let server = MockServer::new(vec![(200, deleted), (200, deleted)]);
+    let client = Client::new(&[&server.endpoint()]);
+    let runtime = TestClient::no_destructor();
+
+    runtime
+        .run(|_| kv::delete_with_options(&client, "/foo", DeleteOptions::default()))
+        .unwrap();
+    let options = DeleteOptions {
+        recursive: true,
+        dir: true,
+        prev_value: Some("bar baz".to_string()),
+        prev_index: Some(4),
+        ..Default::default()
+    };
+    runtime
+        .run(|_| kv::delete_with_options(&client, "/foo", options))
+        .unwrap();
+
+    let requests = server.requests();
+    assert!(requests[0].starts_with("DELETE /v2/keys/foo?recursive=false "));
+    assert!(requests[1]
+        .starts_with("DELETE /v2/keys/foo?recursive=true&dir=true&prevIndex=4&prevValue=bar+baz "));
+}
+
+#[test]
+fn set_and_get_bytes() {
+    let client = TestClient::new();
+    let value = [0, 159, 146, 150, 255];
+
+    let res = client
+        .run(|c| kv::set_bytes(c, "/test/foo", &value, None))
+        .unwrap();
+    assert_eq!(res.data.node.value.unwrap(), "base64:AJ+Slv8=");
+
+    let bytes = client.run(|c| kv::get_bytes(c, "/test/foo")).unwrap();
+    assert_eq!(bytes, value);
+}
+
+#[test]
+fn get_bytes_of_text_value() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"base64:AJ+Slv8=","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":6,"createdIndex":6}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+    let runtime = TestClient::no_destructor();
+
+    let bytes = runtime.run(|_| kv::get_bytes(&client, "/foo")).unwrap();
+    assert_eq!(bytes, [0, 159, 146, 150, 255]);
+
+    let errors = runtime.run(|_| kv::get_bytes(&client, "/foo")).unwrap_err();
+    match errors[0] {
+        Error::NotBinary(ref value) => assert_eq!(value, "bar"),
+        ref error => panic!("expected Error::NotBinary, got {:?}", error),
+    }
+}
+
+#[test]
+fn etcd_index_and_node_modified_index() {
+    let server = MockServer::with_headers(vec![(
+        200,
+        "X-Etcd-Index: 42\r\n".to_string(),
+        r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":7,"createdIndex":3}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let response = TestClient::no_destructor()
+        .run(|_| kv::get(&client, "/foo", GetOptions::default()))
+        .unwrap();
+
+    assert_eq!(response.etcd_index(), Some(42));
+    assert_eq!(response.data.node_modified_index(), Some(7));
+}
+
+#[test]
+fn get_or_create() {
+    let server = MockServer::new(vec![
+        (
+            201,
+            r#"{"action":"create","node":{"key":"/foo","value":"bar","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (
+            412,
+            r#"{"errorCode":105,"message":"Key already exists","cause":"/foo","index":5}"#,
+        ),
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+    let runtime = TestClient::no_destructor();
+
+    let created = runtime
+        .run(|_| kv::get_or_create(&client, "/foo", "bar", None))
+        .unwrap();
+    assert_eq!(created, ("bar".to_string(), true));
+
+    let existing = runtime
+        .run(|_| kv::get_or_create(&client, "/foo", "baz", None))
+        .unwrap();
+    assert_eq!(existing, ("bar".to_string(), false));
+
+    let requests = server.requests();
+    assert!(requests[0].starts_with("PUT /v2/keys/foo "));
+    assert!(requests[1].starts_with("PUT /v2/keys/foo "));
+    assert!(requests[2].starts_with("GET /v2/keys/foo?"));
+    assert!(server.request_bodies()[1].contains("prevExist=false"));
+}
+
+#[test]
+fn delete_prefix() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","dir":true,"nodes":[{"key":"/foo/a","value":"1","modifiedIndex":5,"createdIndex":5},{"key":"/foo/b","dir":true,"nodes":[{"key":"/foo/b/c","value":"2","modifiedIndex":6,"createdIndex":6}],"modifiedIndex":6,"createdIndex":6},{"key":"/foo/d","dir":true,"modifiedIndex":7,"createdIndex":7}],"modifiedIndex":4,"createdIndex":4}}"#,
+        ),
+        (
+            200,
+            r#"{"action":"delete","node":{"key":"/foo","dir":true,"modifiedIndex":8,"createdIndex":4}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let count = TestClient::no_destructor()
+        .run(|_| kv::delete_prefix(&client, "/foo"))
+        .unwrap();
+
+    assert_eq!(count, 2);
+    let requests = server.requests();
+    assert!(requests[0].contains("recursive=true"));
+    assert!(requests[1].starts_with("DELETE /v2/keys/foo?recursive=true "));
+}
+
+#[test]
+fn delete_prefix_missing() {
+    let server = MockServer::new(vec![(
+        404,
+        r#"{"errorCode":100,"message":"Key not found","cause":"/foo","index":5}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let count = TestClient::no_destructor()
+        .run(|_| kv::delete_prefix(&client, "/foo"))
+        .unwrap();
+
+    assert_eq!(count, 0);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn rename() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"bar","ttl":30,"modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (
+            201,
+            r#"{"action":"create","node":{"key":"/baz","value":"bar","ttl":30,"modifiedIndex":6,"createdIndex":6}}"#,
+        ),
+        (
+            200,
+            r#"{"action":"compareAndDelete","node":{"key":"/foo","modifiedIndex":7,"createdIndex":5}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor()
+        .run(|_| kv::rename(&client, "/foo", "/baz"))
+        .unwrap();
+
+    let requests = server.requests();
+    assert!(requests[0].starts_with("GET /v2/keys/foo?"));
+    assert!(requests[0].contains("quorum=true"));
+    assert!(requests[1].starts_with("PUT /v2/keys/baz "));
+    assert!(requests[2].starts_with("DELETE /v2/keys/foo?prevIndex=5 "));
+    let body = &server.request_bodies()[1];
+    assert!(body.contains("value=bar"));
+    assert!(body.contains("ttl=30"));
+    assert!(body.contains("prevExist=false"));
+}
+
+#[test]
+fn rename_concurrent_write() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"bar","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (
+            201,
+            r#"{"action":"create","node":{"key":"/baz","value":"bar","modifiedIndex":7,"createdIndex":7}}"#,
+        ),
+        (
+            412,
+            r#"{"errorCode":101,"message":"Compare failed","cause":"[5 != 6]","index":7}"#,
+        ),
+        (
+            200,
+            r#"{"action":"compareAndDelete","node":{"key":"/baz","modifiedIndex":8,"createdIndex":7}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::rename(&client, "/foo", "/baz"))
+        .unwrap_err();
+
+    assert_eq!(errors.len(), 1);
+    match errors[0] {
+        Error::CompareFailed(ref error) => assert_eq!(error.index, 7),
+        ref error => panic!("expected Error::CompareFailed, got {:?}", error),
+    }
+    // The copy is deleted again, only if it is still the one that was created.
+    assert!(server.requests()[3].starts_with("DELETE /v2/keys/baz?prevIndex=7 "));
+}
+
+#[test]
+fn rename_keeps_both_keys_after_failover() {
+    // The get goes to the first server and the create to the second. The delete of `/from` fails
+    // on the first server without a definite answer, as if its response was lost, and the second
+    // server then no longer finds the key.
+    let first = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/from","value":"bar","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (503, "Service Unavailable"),
+    ]);
+    let second = MockServer::new(vec![
+        (
+            201,
+            r#"{"action":"create","node":{"key":"/to","value":"bar","modifiedIndex":6,"createdIndex":6}}"#,
+        ),
+        (
+            404,
+            r#"{"errorCode":100,"message":"Key not found","cause":"/from","index":7}"#,
+        ),
+    ]);
+    let client = ClientBuilder::new(&[&first.endpoint(), &second.endpoint()])
+        .with_endpoint_selection(Selection::RoundRobin)
+        .build()
+        .unwrap();
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::rename(&client, "/from", "/to"))
+        .unwrap_err();
+
+    match errors[..] {
+        [Error::UnexpectedStatus(_), Error::KeyNotFound(_)] => {}
+        ref errors => panic!("expected a failed delete, got {:?}", errors),
+    }
+    assert!(first.requests()[1].starts_with("DELETE /v2/keys/from?prevIndex=5 "));
+    assert!(second.requests()[1].starts_with("DELETE /v2/keys/from?prevIndex=5 "));
+    // `/to` was not deleted.
+    assert_eq!(second.requests().len(), 2);
+}
+
+#[test]
+fn rename_to_existing_key() {
+    let client = TestClient::new();
+
+    client
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .unwrap();
+    client
+        .run(|c| kv::create(c, "/test/baz", "qux", None))
+        .unwrap();
+
+    let errors = client
+        .run(|c| kv::rename(c, "/test/foo", "/test/baz"))
+        .unwrap_err();
+    match errors[0] {
+        Error::KeyAlreadyExists(_) => {}
+        ref error => panic!("expected Error::KeyAlreadyExists, got {:?}", error),
+    }
+
+    // Neither key was changed.
+    let foo = client
+        .run(|c| kv::get(c, "/test/foo", GetOptions::default()))
+        .unwrap();
+    assert_eq!(foo.data.node.value.unwrap(), "bar");
+    let baz = client
+        .run(|c| kv::get(c, "/test/baz", GetOptions::default()))
+        .unwrap();
+    assert_eq!(baz.data.node.value.unwrap(), "qux");
+}
+
+#[test]
+fn typed_values() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"set","node":{"key":"/foo","value":"[1,2]","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"[1,2]","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/foo","value":"not json","modifiedIndex":6,"createdIndex":6}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+    let runtime = TestClient::no_destructor();
+
+    runtime
+        .run(|_| kv::set_typed(&client, "/foo", &[1, 2], None))
+        .unwrap();
+    assert_eq!(server.request_bodies()[0], "value=%5B1%2C2%5D");
+
+    let response = runtime
+        .run(|_| kv::get_typed::<Vec<u32>, _>(&client, "/foo", GetOptions::default()))
+        .unwrap();
+    assert_eq!(response.data, [1, 2]);
+
+    let errors = runtime
+        .run(|_| kv::get_typed::<Vec<u32>, _>(&client, "/foo", GetOptions::default()))
+        .unwrap_err();
+    match errors[0] {
+        Error::Serialization { ref body, .. } => assert_eq!(body, "not json"),
+        ref error => panic!("expected Error::Serialization, got {:?}", error),
+    }
+}
+
+#[test]
+fn list_keys() {
+    let tree = r#"{"action":"get","node":{"key":"/list","dir":true,"nodes":[{"key":"/list/a","value":"1","modifiedIndex":5,"createdIndex":5},{"key":"/list/b","dir":true,"nodes":[{"key":"/list/b/c","value":"2","modifiedIndex":6,"createdIndex":6}],"modifiedIndex":4,"createdIndex":4},{"key":"/list/d","value":"3","modifiedIndex":7,"createdIndex":7}],"modifiedIndex":3,"createdIndex":3}}"#;
+    let server = MockServer::new(vec![(200, tree), (200, tree)]);
+    let client = Client::new(&[&server.endpoint()]);
+    let runtime = TestClient::no_destructor();
+
+    let keys = runtime
+        .run(|_| kv::list_keys(&client, "/list", ListKeysOptions::default()))
+        .unwrap();
+    assert_eq!(keys, ["/list/a", "/list/b/c", "/list/d"]);
+
+    let options = ListKeysOptions { include_dirs: true };
+    let keys = runtime
+        .run(|_| kv::list_keys(&client, "/list", options))
+        .unwrap();
+    assert_eq!(keys, ["/list/a", "/list/b", "/list/b/c", "/list/d"]);
+
+    let requests = server.requests();
+    assert!(requests[0].starts_with("GET /v2/keys/list?"));
+    assert!(requests[0].contains("recursive=true"));
+    assert!(requests[0].contains("sorted=true"));
+}
+
+#[test]
+fn set_value_too_large() {
+    let server = MockServer::new(vec![]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_max_value_size(4)
+        .build()
+        .unwrap();
+    let runtime = TestClient::no_destructor();
+
+    for errors in vec![
+        runtime.run(|_| kv::set(&client, "/foo", "bar baz", None)),
+        runtime.run(|_| kv::create(&client, "/foo", "bar baz", None)),
+        runtime.run(|_| kv::update(&client, "/foo", "bar baz", None)),
+    ]
+    .into_iter()
+    .map(Result::unwrap_err)
+    {
+        match errors[0] {
+            Error::ValueTooLarge { size, limit } => {
+                assert_eq!(size, 7);
+                assert_eq!(limit, 4);
+            }
+            ref error => panic!("expected Error::ValueTooLarge, got {:?}", error),
+        }
+    }
+
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn watch() {
+    let client = TestClient::new();
+    let create_response = client
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .unwrap();
+    let set_response = client
+        .run(|c| kv::set(c, "/test/foo", "baz", None))
+        .unwrap();
+    let watch_response = client
+        .run(|c| {
+            kv::watch(
+                c,
+                "/test/foo",
+                WatchOptions {
+                    index: Some(create_response.data.node.created_index.unwrap() + 1),
+                    ..Default::default()
+                },
+            )
+        })
+        .unwrap();
+
+    assert_eq!(watch_response.data.node.value.unwrap(), "baz");
+    assert_eq!(
+        watch_response.data.node.modified_index.unwrap(),
+        set_response.data.node.modified_index.unwrap()
+    );
+}
+
+#[test]
+fn watch_cancel() {
+    let client = TestClient::new();
+    client
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .unwrap();
+
+    let res = client.run(|c| {
+        kv::watch(
+            c,
+            "/test/foo",
+            WatchOptions {
+                timeout: Some(Duration::from_millis(1)),
+                ..Default::default()
+            },
+        )
+    });
+
+    match res {
+        Err(WatchError::Timeout) => {}
+        _ => panic!("expected WatchError::Timeout"),
+    }
+}
+
+#[test]
+fn watch_index() {
+    let client = TestClient::new();
+    let res = client
+        .run(|c| kv::set(c, "/test/foo", "bar", None))
+        .unwrap();
+    let index = res.data.node.modified_index;
+    let res = client
+        .run(|c| {
+            kv::watch(
+                c,
+                "/test/foo",
+                WatchOptions {
+                    index,
+                    ..Default::default()
+                },
+            )
+        })
+        .unwrap();
+    let node = res.data.node;
+
+    assert_eq!(node.modified_index, index);
+    assert_eq!(node.value.unwrap(), "bar");
+}
+
+#[test]
+fn watch_recursive() {
+    let client = TestClient::new();
 
     let watch_result = client
         .run(|c| async move {
@@ -588,3 +2033,608 @@ fn watch_recursive() {
     assert_eq!(node.key.unwrap(), "/test/foo/bar");
     assert_eq!(node.value.unwrap(), "baz");
 }
+
+#[test]
+fn wait_delete() {
+    let client = TestClient::new();
+
+    client
+        .run(|c| kv::set(c, "/test/foo", "bar", None))
+        .unwrap();
+
+    match client.run(|c| kv::wait_delete(c, "/test/foo", Some(Duration::from_millis(200)))) {
+        Err(WatchError::Timeout) => {}
+        _ => panic!("expected WatchError::Timeout"),
+    }
+
+    client
+        .run(|c| async move {
+            let task_c = c.clone();
+            let delete_handle = tokio::task::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                kv::update(&task_c, "/test/foo", "baz", None).await.unwrap();
+                kv::delete(&task_c, "/test/foo", false).await.unwrap();
+            });
+
+            let result = kv::wait_delete(c, "/test/foo", Some(Duration::from_millis(1000))).await;
+
+            delete_handle.await.unwrap();
+
+            result
+        })
+        .unwrap();
+
+    client
+        .run(|c| kv::wait_delete(c, "/test/foo", None))
+        .unwrap();
+}
+
+#[test]
+fn watch_non_recursive_ignores_children() {
+    let client = TestClient::new();
+
+    client
+        .run(|c| kv::create_dir(c, "/test/foo", None))
+        .unwrap();
+
+    let watch_result = client.run(|c| async move {
+        let task_c = c.clone();
+        let set_handle = tokio::task::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            kv::set(&task_c, "/test/foo/bar", "baz", None)
+                .await
+                .unwrap();
+        });
+
+        let watch_result = kv::watch(
+            c,
+            "/test/foo",
+            WatchOptions {
+                timeout: Some(Duration::from_millis(1000)),
+                ..Default::default()
+            },
+        )
+        .await;
+
+        set_handle.await.unwrap();
+
+        watch_result
+    });
+
+    match watch_result {
+        Err(WatchError::Timeout) => {}
+        _ => panic!("expected the child change not to wake the watch"),
+    }
+}
+
+#[test]
+fn watch_stream() {
+    let client = TestClient::new();
+    let res = client
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .unwrap();
+    let index = res.data.node.modified_index.unwrap();
+
+    client
+        .run(|c| kv::set(c, "/test/foo", "baz", None))
+        .unwrap();
+    client
+        .run(|c| kv::set(c, "/test/foo", "qux", None))
+        .unwrap();
+
+    let values: Vec<String> = client.run(|c| {
+        kv::watch_stream(
+            c,
+            "/test/foo",
+            WatchOptions {
+                index: Some(index + 1),
+                ..Default::default()
+            },
+        )
+        .take(2)
+        .map(|res| res.unwrap().data.node.value.unwrap())
+        .collect()
+    });
+
+    assert_eq!(values, vec!["baz", "qux"]);
+}
+
+#[test]
+fn watch_stream_resyncs_cleared_index() {
+    let client = TestClient::new();
+    let res = client
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .unwrap();
+    let index = res.data.node.modified_index.unwrap();
+
+    // etcd only retains the most recent 1000 events.
+    client.run(|c| async move {
+        for i in 0..1001 {
+            kv::set(c, "/test/other", &i.to_string(), None)
+                .await
+                .unwrap();
+        }
+    });
+
+    let res = client.run(|c| async move {
+        let task_c = c.clone();
+        let set_handle = tokio::task::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            kv::set(&task_c, "/test/foo", "baz", None).await.unwrap();
+        });
+
+        let mut stream = Box::pin(kv::watch_stream(
+            c,
+            "/test/foo",
+            WatchOptions {
+                index: Some(index + 1),
+                timeout: Some(Duration::from_millis(1000)),
+                ..Default::default()
+            },
+        ));
+        let res = stream.next().await.unwrap();
+
+        set_handle.await.unwrap();
+
+        res
+    });
+
+    assert_eq!(res.unwrap().data.node.value.unwrap(), "baz");
+}
+
+#[test]
+fn watch_stream_reissues_timed_out_watch() {
+    let client = TestClient::new();
+    client
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .unwrap();
+
+    let res = client.run(|c| async move {
+        let task_c = c.clone();
+        let set_handle = tokio::task::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            kv::set(&task_c, "/test/foo", "baz", None).await.unwrap();
+        });
+
+        let mut stream = Box::pin(kv::watch_stream(
+            c,
+            "/test/foo",
+            WatchOptions {
+                timeout: Some(Duration::from_millis(50)),
+                ..Default::default()
+            },
+        ));
+        let res = stream.next().await.unwrap();
+
+        set_handle.await.unwrap();
+
+        res
+    });
+
+    assert_eq!(res.unwrap().data.node.value.unwrap(), "baz");
+}
+
+#[test]
+fn watch_ignores_request_timeout() {
+    let client = TestClient::new();
+    client
+        .run(|c| kv::create(c, "/test/foo", "bar", None))
+        .unwrap();
+
+    let res = client.run(|c| async move {
+        let watch_client = ClientBuilder::new(&["http://etcd:2379"])
+            .with_request_timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        let task_c = c.clone();
+        let set_handle = tokio::task::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            kv::set(&task_c, "/test/foo", "baz", None).await.unwrap();
+        });
+
+        let res = kv::watch(&watch_client, "/test/foo", WatchOptions::default()).await;
+
+        set_handle.await.unwrap();
+
+        res
+    });
+
+    assert_eq!(res.unwrap().data.node.value.unwrap(), "baz");
+}
+
+#[test]
+fn action_serialization() {
+    let actions = vec![
+        (Action::CompareAndDelete, "compareAndDelete"),
+        (Action::CompareAndSwap, "compareAndSwap"),
+        (Action::Create, "create"),
+        (Action::Delete, "delete"),
+        (Action::Expire, "expire"),
+        (Action::Get, "get"),
+        (Action::Set, "set"),
+        (Action::Update, "update"),
+        (
+            Action::Other("someFutureAction".to_string()),
+            "someFutureAction",
+        ),
+    ];
+
+    for (action, name) in actions {
+        let json = format!("\"{}\"", name);
+        assert_eq!(serde_json::to_string(&action).unwrap(), json);
+        assert_eq!(serde_json::from_str::<Action>(&json).unwrap(), action);
+        assert_eq!(action.as_str(), name);
+    }
+}
+
+#[test]
+fn node_expiration() {
+    let node = |expiration: Option<&str>, ttl| Node {
+        created_index: Some(1),
+        dir: None,
+        expiration: expiration.map(str::to_string),
+        key: Some("/foo".to_string()),
+        modified_index: Some(1),
+        nodes: None,
+        ttl,
+        value: Some("bar".to_string()),
+    };
+
+    let expiring = node(Some("2013-12-04T12:01:21.874888581-08:00"), Some(5));
+    assert_eq!(
+        expiring.expiration_time(),
+        Some(UNIX_EPOCH + Duration::new(1_386_187_281, 874_888_581))
+    );
+    // The key has long expired, so the expiration takes precedence over the TTL.
+    assert_eq!(expiring.remaining_ttl(), Some(Duration::from_secs(0)));
+
+    let utc = node(Some("1970-01-01T00:00:01Z"), None);
+    assert_eq!(
+        utc.expiration_time(),
+        Some(UNIX_EPOCH + Duration::from_secs(1))
+    );
+
+    let fraction = node(Some("1970-01-01T01:30:02.5+01:30"), None);
+    assert_eq!(
+        fraction.expiration_time(),
+        Some(UNIX_EPOCH + Duration::from_millis(2500))
+    );
+
+    let future = node(Some("2999-01-01T00:00:00+00:00"), None);
+    assert!(future.remaining_ttl().unwrap() > Duration::from_secs(3600));
+
+    let ttl_only = node(None, Some(30));
+    assert_eq!(ttl_only.expiration_time(), None);
+    assert_eq!(ttl_only.remaining_ttl(), Some(Duration::from_secs(30)));
+
+    let invalid = node(Some("yesterday"), None);
+    assert_eq!(invalid.expiration_time(), None);
+    assert_eq!(invalid.remaining_ttl(), None);
+
+    for invalid in &[
+        "2013-02-30T00:00:00Z",
+        "2013-12-04T12:01:21",
+        "2013-12-04T12:01:21.Z",
+        "2013-12-04T12:01:21+0800",
+    ] {
+        assert_eq!(node(Some(invalid), None).expiration_time(), None);
+    }
+
+    assert_eq!(node(None, None).remaining_ttl(), None);
+}
+
+#[test]
+fn node_children() {
+    let root: Node = serde_json::from_str(
+        r#"{"key":"/dir","dir":true,"nodes":[
+            {"key":"/dir/a","value":"1"},
+            {"key":"/dir/sub","dir":true,"nodes":[
+                {"key":"/dir/sub/b","value":"2"},
+                {"key":"/dir/sub/empty","dir":true},
+                {"key":"/dir/sub/deeper","dir":true,"nodes":[{"key":"/dir/sub/deeper/c","value":"3"}]}
+            ]},
+            {"key":"/dir/d","value":"4"}
+        ]}"#,
+    )
+    .unwrap();
+
+    assert!(root.is_dir());
+    let children: Vec<_> = root
+        .children()
+        .iter()
+        .map(|node| node.key.as_deref())
+        .collect();
+    assert_eq!(children, [Some("/dir/a"), Some("/dir/sub"), Some("/dir/d")]);
+    assert!(!root.children()[0].is_dir());
+    assert!(root.children()[1].is_dir());
+
+    let leaves: Vec<_> = root
+        .iter_recursive()
+        .map(|node| node.key.as_deref().unwrap())
+        .collect();
+    assert_eq!(
+        leaves,
+        ["/dir/a", "/dir/sub/b", "/dir/sub/deeper/c", "/dir/d"]
+    );
+
+    let key = &root.children()[0];
+    assert!(key.children().is_empty());
+    assert_eq!(key.iter_recursive().count(), 0);
+    let empty = &root.children()[1].children()[1];
+    assert!(empty.is_dir());
+    assert_eq!(empty.iter_recursive().count(), 0);
+}
+
+#[test]
+fn get_many_limits_concurrency() {
+    // Accepts every connection and never responds, reporting when each one was accepted.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let (accepted_tx, accepted_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut streams = Vec::new();
+        for stream in listener.incoming() {
+            streams.push(stream.unwrap());
+            if accepted_tx.send(Instant::now()).is_err() {
+                return;
+            }
+        }
+    });
+    let client = Client::new(&[&endpoint]);
+    let keys: Vec<String> = (0..20).map(|i| format!("/test/{}", i)).collect();
+    let options = GetOptions {
+        timeout: Some(Duration::from_millis(500)),
+        ..Default::default()
+    };
+
+    let start = Instant::now();
+    let results: Vec<_> = TestClient::no_destructor()
+        .run(|_| kv::get_many(&client, &keys, options).collect::<Vec<_>>());
+
+    assert_eq!(results.len(), 20);
+    let accepted: Vec<Instant> = accepted_rx.try_iter().collect();
+    assert_eq!(accepted.len(), 20);
+    let first_round = accepted
+        .iter()
+        .filter(|&&at| at.duration_since(start) < Duration::from_millis(400))
+        .count();
+    assert_eq!(first_round, 16);
+}
+
+#[test]
+fn dropping_watch_closes_connection() {
+    // Accepts a single watch request and never responds, reporting when the client hangs up.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let (closed_tx, closed_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0; 1024];
+        while stream.read(&mut buffer).map(|len| len > 0).unwrap_or(false) {}
+        closed_tx.send(()).unwrap();
+    });
+    let client = Client::new(&[&endpoint]);
+
+    TestClient::no_destructor().run(|_| async {
+        let watch = kv::watch(&client, "/foo", WatchOptions::default());
+        assert!(tokio::time::timeout(Duration::from_millis(100), watch)
+            .await
+            .is_err());
+
+        // Give the client's connection task a chance to run.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    });
+
+    closed_rx
+        .recv_timeout(Duration::from_secs(1))
+        .expect("the connection was not closed after the watch was dropped");
+}
+
+#[test]
+fn increment() {
+    let client = TestClient::new();
+
+    client.run(|c| async move {
+        assert_eq!(kv::increment(c, "/test/counter", 5).await.unwrap(), 5);
+
+        let increments = (0..4).map(|_| kv::increment(c, "/test/counter", -2));
+        futures_util::future::join_all(increments)
+            .await
+            .into_iter()
+            .for_each(|result| {
+                result.unwrap();
+            });
+
+        let res = kv::get(c, "/test/counter", GetOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(res.data.node.value.unwrap(), "-3");
+    });
+}
+
+#[test]
+fn increment_retries_on_contention() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/counter","value":"5","modifiedIndex":5,"createdIndex":5}}"#,
+        ),
+        (
+            412,
+            r#"{"errorCode":101,"message":"Compare failed","cause":"[5 != 6]","index":6}"#,
+        ),
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/counter","value":"6","modifiedIndex":6,"createdIndex":5}}"#,
+        ),
+        (
+            200,
+            r#"{"action":"compareAndSwap","node":{"key":"/counter","value":"8","modifiedIndex":7,"createdIndex":5}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let value = TestClient::no_destructor()
+        .run(|_| kv::increment(&client, "/counter", 2))
+        .unwrap();
+
+    assert_eq!(value, 8);
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[test]
+fn increment_not_an_integer() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"action":"get","node":{"key":"/counter","value":"five","modifiedIndex":5,"createdIndex":5}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::increment(&client, "/counter", 1))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::NotAnInteger(ref value) => assert_eq!(value, "five"),
+        ref error => panic!("expected Error::NotAnInteger, got {:?}", error),
+    }
+}
+
+#[test]
+fn keep_alive() {
+    let body = r#"{"action":"set","node":{"key":"/foo","value":"bar","ttl":3,"modifiedIndex":2,"createdIndex":1}}"#;
+    let server = MockServer::new(vec![
+        (200, body),
+        (
+            404,
+            r#"{"errorCode":100,"message":"Key not found","cause":"/foo","index":2}"#,
+        ),
+        (200, body),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let results: Vec<Result<(), Vec<Error>>> = TestClient::no_destructor().run(|_| {
+        kv::keep_alive(&client, "/foo", 3, Some(Duration::from_millis(10)))
+            .take(3)
+            .collect()
+    });
+
+    assert!(results[0].is_ok());
+    match results[1] {
+        Err(ref errors) => match errors[0] {
+            Error::KeyNotFound(_) => {}
+            ref error => panic!("expected Error::KeyNotFound, got {:?}", error),
+        },
+        Ok(_) => panic!("expected the refresh to fail"),
+    }
+    assert!(results[2].is_ok());
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests
+        .iter()
+        .all(|request| request.starts_with("PUT /v2/keys/foo ")));
+}
+
+#[test]
+#[should_panic]
+fn keep_alive_interval_must_be_non_zero() {
+    let client = Client::new(&["http://etcd:2379"]);
+    let _ = kv::keep_alive(&client, "/foo", 3, Some(Duration::from_secs(0)));
+}
+
+#[test]
+fn walk() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/walk","dir":true,"nodes":[{"key":"/walk/a","value":"1","modifiedIndex":1,"createdIndex":1},{"key":"/walk/b","dir":true,"modifiedIndex":2,"createdIndex":2}]}}"#,
+        ),
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/walk/b","dir":true,"nodes":[{"key":"/walk/b/c","value":"2","modifiedIndex":3,"createdIndex":3},{"key":"/walk/b/d","dir":true,"modifiedIndex":4,"createdIndex":4}]}}"#,
+        ),
+        (
+            200,
+            r#"{"action":"get","node":{"key":"/walk/b/d","dir":true,"nodes":[{"key":"/walk/b/d/e","value":"3","modifiedIndex":5,"createdIndex":5}]}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let keys: Vec<String> = TestClient::no_destructor().run(|_| {
+        kv::walk(&client, "/walk", WalkOptions { concurrency: 1 })
+            .map(|result| result.unwrap().key.unwrap())
+            .collect()
+    });
+
+    assert_eq!(
+        keys,
+        vec![
+            "/walk/a",
+            "/walk/b",
+            "/walk/b/c",
+            "/walk/b/d",
+            "/walk/b/d/e"
+        ]
+    );
+
+    let requests = server.requests();
+    assert!(requests[0].starts_with("GET /v2/keys/walk?"));
+    assert!(requests[1].starts_with("GET /v2/keys/walk/b?"));
+    assert!(requests[2].starts_with("GET /v2/keys/walk/b/d?"));
+}
+
+#[test]
+fn walk_directory_tree() {
+    let client = TestClient::new();
+
+    client.run(|c| async move {
+        kv::set(c, "/test/walk/a", "1", None).await.unwrap();
+        kv::set(c, "/test/walk/b/c", "2", None).await.unwrap();
+        kv::set(c, "/test/walk/b/d/e", "3", None).await.unwrap();
+
+        let mut keys: Vec<String> = kv::walk(c, "/test/walk", WalkOptions::default())
+            .map(|result| result.unwrap().key.unwrap())
+            .collect()
+            .await;
+        keys.sort();
+
+        assert_eq!(
+            keys,
+            vec![
+                "/test/walk/a",
+                "/test/walk/b",
+                "/test/walk/b/c",
+                "/test/walk/b/d",
+                "/test/walk/b/d/e",
+            ]
+        );
+    });
+}
+
+#[test]
+fn api_errors_include_cluster_info() {
+    let server = MockServer::with_headers(vec![(
+        400,
+        "X-Etcd-Index: 2048\r\n".to_string(),
+        r#"{"errorCode":401,"message":"The event in requested index is outdated and cleared","cause":"the requested history has been cleared [1008/4]","index":2047}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+    let options = WatchOptions {
+        index: Some(4),
+        ..Default::default()
+    };
+
+    let error = TestClient::no_destructor()
+        .run(|_| kv::watch(&client, "/foo", options))
+        .unwrap_err();
+
+    match error {
+        WatchError::Other(ref errors) => {
+            let error = errors[0].api_error().expect("expected an API error");
+            assert_eq!(error.error_code, 401);
+            assert_eq!(error.cluster_info.as_ref().unwrap().etcd_index, Some(2048));
+        }
+        _ => panic!("expected WatchError::Other"),
+    }
+}
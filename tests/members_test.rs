@@ -1,14 +1,118 @@
-use etcd::members;
+use std::time::Duration;
 
-use crate::test::TestClient;
+use etcd::{members, Client, Error};
+use futures_util::stream::StreamExt;
+
+use crate::test::{MockServer, TestClient};
 
 mod test;
 
 #[test]
 fn list() {
     let client = TestClient::no_destructor();
-    let res = client.run(|c| members::list(c)).unwrap();
+    let res = client.run(members::list).unwrap();
     let members = res.data;
     let member = &members[0];
     assert_eq!(member.name, "default");
 }
+
+#[test]
+fn watch() {
+    const ONE_MEMBER: &str = r#"{"members":[{"id":"a1","name":"infra1","peerURLs":["http://10.0.0.1:2380"],"clientURLs":["http://10.0.0.1:2379"]}]}"#;
+    const TWO_MEMBERS: &str = r#"{"members":[{"id":"b2","name":"infra2","peerURLs":["http://10.0.0.2:2380"],"clientURLs":["http://10.0.0.2:2379"]},{"id":"a1","name":"infra1","peerURLs":["http://10.0.0.1:2380"],"clientURLs":["http://10.0.0.1:2379"]}]}"#;
+    const TWO_MEMBERS_REORDERED: &str = r#"{"members":[{"id":"a1","name":"infra1","peerURLs":["http://10.0.0.1:2380"],"clientURLs":["http://10.0.0.1:2379"]},{"id":"b2","name":"infra2","peerURLs":["http://10.0.0.2:2380"],"clientURLs":["http://10.0.0.2:2379"]}]}"#;
+
+    let server = MockServer::new(vec![
+        (200, ONE_MEMBER),
+        (200, ONE_MEMBER),
+        (200, TWO_MEMBERS_REORDERED),
+        (200, TWO_MEMBERS),
+        (200, ONE_MEMBER),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+    let options = members::WatchOptions {
+        interval: Duration::from_millis(10),
+    };
+
+    let snapshots: Vec<Vec<String>> = TestClient::no_destructor().run(|_| {
+        members::watch(&client, options)
+            .take(3)
+            .map(|result| {
+                let members = result.unwrap().data;
+                members.into_iter().map(|member| member.id).collect()
+            })
+            .collect()
+    });
+
+    assert_eq!(snapshots, vec![vec!["a1"], vec!["a1", "b2"], vec!["a1"]]);
+    assert_eq!(server.requests().len(), 5);
+}
+
+#[test]
+fn update() {
+    let server = MockServer::new(vec![(204, "")]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    TestClient::no_destructor()
+        .run(|_| members::update(&client, "a1", vec!["http://10.0.0.10:2380".to_string()]))
+        .unwrap();
+
+    let request = server.requests()[0].to_lowercase();
+    assert!(request.starts_with("put /v2/members/a1 http/1.1"));
+    assert!(request.contains("content-type: application/json"));
+}
+
+#[test]
+fn update_errors() {
+    let server = MockServer::new(vec![
+        (
+            400,
+            r#"{"message":"URL scheme must be http or https: 10.0.0.10:2380"}"#,
+        ),
+        (404, r#"{"message":"membership: member not found"}"#),
+        (409, r#"{"message":"membership: peerURL exists"}"#),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+    let update = || {
+        TestClient::no_destructor()
+            .run(|_| members::update(&client, "a1", vec!["10.0.0.10:2380".to_string()]))
+            .unwrap_err()
+            .remove(0)
+    };
+
+    match update() {
+        Error::InvalidPeerUrls(ref message) => assert!(message.contains("URL scheme")),
+        error => panic!("expected Error::InvalidPeerUrls, got {:?}", error),
+    }
+    match update() {
+        Error::MemberNotFound(_) => {}
+        error => panic!("expected Error::MemberNotFound, got {:?}", error),
+    }
+    match update() {
+        Error::PeerUrlsExist(_) => {}
+        error => panic!("expected Error::PeerUrlsExist, got {:?}", error),
+    }
+}
+
+#[test]
+fn add() {
+    let server = MockServer::new(vec![(
+        201,
+        r#"{"id":"3777296169","peerURLs":["http://10.0.0.10:2380"]}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let member = TestClient::no_destructor()
+        .run(|_| members::add(&client, vec!["http://10.0.0.10:2380".to_string()]))
+        .unwrap()
+        .data;
+
+    assert_eq!(member.id, "3777296169");
+    assert_eq!(member.name, "");
+    assert_eq!(member.peer_urls, vec!["http://10.0.0.10:2380"]);
+    assert!(member.client_urls.is_empty());
+
+    let request = server.requests()[0].to_lowercase();
+    assert!(request.starts_with("post /v2/members http/1.1"));
+    assert!(request.contains("content-type: application/json"));
+}
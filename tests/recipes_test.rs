@@ -0,0 +1,204 @@
+use std::time::Duration;
+
+use etcd::kv::{self, GetOptions};
+use etcd::recipes::election;
+use etcd::recipes::lock::Lock;
+use etcd::{CacheConfig, ClientBuilder, Error};
+use futures_util::stream::StreamExt;
+use tokio::time::{sleep, timeout};
+
+use crate::test::{MockServer, TestClient};
+
+mod test;
+
+#[test]
+fn lock_acquire_and_release() {
+    let client = TestClient::new();
+
+    client.run(|c| async move {
+        let lock = Lock::acquire(c, "/test/lock", 10).await.unwrap();
+        let key = lock.key().to_string();
+
+        assert!(key.starts_with("/test/lock/"));
+        assert!(lock.is_held());
+
+        lock.release().await.unwrap();
+
+        match kv::get(c, &key, GetOptions::default()).await {
+            Err(ref errors) => match errors[0] {
+                Error::KeyNotFound(_) => {}
+                _ => panic!("expected Error::KeyNotFound"),
+            },
+            Ok(_) => panic!("expected the lock's node to be deleted"),
+        }
+    });
+}
+
+#[test]
+fn lock_is_exclusive() {
+    let client = TestClient::new();
+
+    client.run(|c| async move {
+        let first = Lock::acquire(c, "/test/lock", 10).await.unwrap();
+
+        // The lock is held, so a second acquisition waits until it is cancelled here.
+        let second = timeout(
+            Duration::from_millis(500),
+            Lock::acquire(c, "/test/lock", 10),
+        )
+        .await;
+        assert!(second.is_err());
+
+        // The cancelled acquisition's node is deleted in the background.
+        sleep(Duration::from_millis(200)).await;
+        let nodes = kv::get(c, "/test/lock", GetOptions::default())
+            .await
+            .unwrap()
+            .data
+            .node
+            .nodes
+            .unwrap_or_default();
+        assert_eq!(nodes.len(), 1);
+
+        first.release().await.unwrap();
+
+        let third = timeout(Duration::from_secs(5), Lock::acquire(c, "/test/lock", 10))
+            .await
+            .unwrap()
+            .unwrap();
+        third.release().await.unwrap();
+    });
+}
+
+#[test]
+fn lock_waits_in_order() {
+    let client = TestClient::new();
+
+    client.run(|c| async move {
+        let first = Lock::acquire(c, "/test/lock", 10).await.unwrap();
+        let second = tokio::spawn({
+            let c = c.clone();
+            async move { Lock::acquire(&c, "/test/lock", 10).await.unwrap() }
+        });
+
+        sleep(Duration::from_millis(200)).await;
+        assert!(!second.is_finished());
+
+        first.release().await.unwrap();
+        let second = timeout(Duration::from_secs(5), second)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(second.is_held());
+        second.release().await.unwrap();
+    });
+}
+
+#[test]
+fn election_campaign_and_resign() {
+    let client = TestClient::new();
+
+    client.run(|c| async move {
+        let leadership = election::campaign(c, "/test/election", "10.0.0.1:8080", 10)
+            .await
+            .unwrap();
+        assert!(leadership.is_leader());
+        assert_eq!(leadership.nomination(), "10.0.0.1:8080");
+
+        // The second candidate waits until the leader resigns.
+        let candidate = tokio::spawn({
+            let c = c.clone();
+            async move {
+                election::campaign(&c, "/test/election", "10.0.0.2:8080", 10)
+                    .await
+                    .unwrap()
+            }
+        });
+        sleep(Duration::from_millis(200)).await;
+        assert!(!candidate.is_finished());
+
+        leadership.resign().await.unwrap();
+
+        let leadership = timeout(Duration::from_secs(5), candidate)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(leadership.nomination(), "10.0.0.2:8080");
+    });
+}
+
+#[test]
+fn election_observe() {
+    let client = TestClient::new();
+
+    client.run(|c| async move {
+        let mut leaders = Box::pin(election::observe(c, "/test/election"));
+        assert_eq!(leaders.next().await.unwrap().unwrap(), None);
+
+        let leadership = election::campaign(c, "/test/election", "10.0.0.1:8080", 10)
+            .await
+            .unwrap();
+        let leader = timeout(Duration::from_secs(5), leaders.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(leader.key, leadership.key());
+        assert_eq!(leader.nomination, "10.0.0.1:8080");
+
+        // Dropping the leadership resigns.
+        drop(leadership);
+        let leader = timeout(Duration::from_secs(5), leaders.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(leader, None);
+    });
+}
+
+#[test]
+fn election_observe_with_read_cache() {
+    let leader = |index, nomination| {
+        format!(
+            r#"{{"action":"get","node":{{"key":"/test/election","dir":true,"nodes":[{{"key":"/test/election/{}","value":"{}","modifiedIndex":{},"createdIndex":{}}}],"modifiedIndex":2,"createdIndex":2}}}}"#,
+            index, nomination, index, index
+        )
+    };
+    // The leader is read again before watching for changes. Meanwhile, another client resigns the
+    // first leadership, which hands it to the next candidate.
+    let server = MockServer::with_headers(vec![
+        (200, "X-Etcd-Index: 10\r\n".to_string(), leader(10, "10.0.0.1:8080")),
+        (200, "X-Etcd-Index: 10\r\n".to_string(), leader(10, "10.0.0.1:8080")),
+        (
+            200,
+            String::new(),
+            r#"{"action":"delete","node":{"key":"/test/election/10","modifiedIndex":12,"createdIndex":10}}"#
+                .to_string(),
+        ),
+        (200, "X-Etcd-Index: 12\r\n".to_string(), leader(11, "10.0.0.2:8080")),
+    ]);
+    let client = ClientBuilder::new(&[&server.endpoint()])
+        .with_read_cache(CacheConfig {
+            ttl: Duration::from_secs(60),
+            max_entries: 10,
+        })
+        .build()
+        .unwrap();
+
+    TestClient::no_destructor().run(|_| async {
+        let mut leaders = Box::pin(election::observe(&client, "/test/election"));
+        let leader = leaders.next().await.unwrap().unwrap().unwrap();
+        assert_eq!(leader.nomination, "10.0.0.1:8080");
+
+        let leader = timeout(Duration::from_secs(5), leaders.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(leader.key, "/test/election/11");
+        assert_eq!(leader.nomination, "10.0.0.2:8080");
+    });
+}
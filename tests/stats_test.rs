@@ -1,19 +1,24 @@
-use etcd::stats;
+use std::time::Duration;
 
-use crate::test::TestClient;
+use futures_util::StreamExt;
+
+use etcd::stats::{self, LeaderStats, SelfStats, StoreStats};
+use etcd::{Client, Error};
+
+use crate::test::{MockServer, TestClient};
 
 mod test;
 
 #[test]
 fn leader_stats() {
     let client = TestClient::no_destructor();
-    client.run(|c| stats::leader_stats(&c)).unwrap();
+    client.run(stats::leader_stats).unwrap();
 }
 
 #[test]
 fn self_stats() {
     let client = TestClient::no_destructor();
-    let results = client.run(|c| stats::self_stats(&c));
+    let results = client.run(stats::self_stats);
     for result in results {
         result.unwrap();
     }
@@ -22,8 +27,145 @@ fn self_stats() {
 #[test]
 fn store_stats() {
     let client = TestClient::no_destructor();
-    let results = client.run(|c| stats::store_stats(&c));
+    let results = client.run(stats::store_stats);
     for result in results {
         result.unwrap();
     }
 }
+
+#[test]
+fn invalid_stats_include_response_body() {
+    let server = MockServer::new(vec![(200, r#"{"leader":1}"#)]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let error = TestClient::no_destructor()
+        .run(|_| stats::leader_stats(&client))
+        .unwrap_err();
+
+    match error {
+        Error::Serialization { ref body, .. } => assert_eq!(body, r#"{"leader":1}"#),
+        _ => panic!("expected Error::Serialization"),
+    }
+}
+
+#[test]
+fn leader_stats_tolerate_unknown_and_missing_fields() {
+    let stats: LeaderStats = serde_json::from_str(
+        r#"{"leader":"1","uptime":"1h","followers":{"2":{"latency":{"current":0.5,"average":0.25,"standardDeviation":0.1,"minimum":0.1,"maximum":1.5,"p99":1.0},"counts":{"fail":1,"success":20,"dropped":3},"state":"active"},"3":{"counts":{"success":7}}}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(stats.leader, "1");
+    let follower = &stats.followers["2"];
+    assert_eq!(follower.counts.fail, 1);
+    assert_eq!(follower.counts.success, 20);
+    assert_eq!(follower.latency.standard_deviation, 0.1);
+    assert_eq!(follower.latency.maximum, 1.5);
+    let follower = &stats.followers["3"];
+    assert_eq!(follower.counts.fail, 0);
+    assert_eq!(follower.counts.success, 7);
+    assert_eq!(follower.latency.average, 0.0);
+
+    let stats: LeaderStats = serde_json::from_str(r#"{"leader":"1"}"#).unwrap();
+    assert!(stats.followers.is_empty());
+}
+
+#[test]
+fn store_stats_stream() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"compareAndDeleteFail":0,"compareAndDeleteSuccess":0,"compareAndSwapFail":1,"compareAndSwapSuccess":2,"createFail":0,"createSuccess":3,"deleteFail":0,"deleteSuccess":1,"expireCount":0,"getsFail":4,"getsSuccess":10,"setsFail":0,"setsSuccess":5,"updateFail":0,"updateSuccess":0,"watchers":2}"#,
+        ),
+        (
+            200,
+            r#"{"compareAndDeleteFail":0,"compareAndDeleteSuccess":0,"compareAndSwapFail":1,"compareAndSwapSuccess":4,"createFail":0,"createSuccess":3,"deleteFail":0,"deleteSuccess":1,"expireCount":0,"getsFail":4,"getsSuccess":17,"setsFail":0,"setsSuccess":6,"updateFail":0,"updateSuccess":0,"watchers":1}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let samples = TestClient::no_destructor().run(|_| {
+        stats::store_stats_stream(&client, Duration::from_millis(10))
+            .take(2)
+            .collect::<Vec<_>>()
+    });
+
+    let first = &samples[0][0].as_ref().unwrap().data;
+    let second = &samples[1][0].as_ref().unwrap().data;
+    let delta = second.delta(first);
+    assert_eq!(delta.get_success, 7);
+    assert_eq!(delta.compare_and_swap_success, 2);
+    assert_eq!(delta.set_success, 1);
+    assert_eq!(delta.create_success, 0);
+    assert_eq!(delta.watchers, 1);
+}
+
+#[test]
+fn watch_leader() {
+    let server = MockServer::new(vec![
+        (200, r#"{"leader":"a","followers":{}}"#),
+        (200, r#"{"leader":"a","followers":{}}"#),
+        (500, ""),
+        (500, ""),
+        (200, r#"{"leader":"a","followers":{}}"#),
+        (200, r#"{"leader":"b","followers":{}}"#),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let leaders = TestClient::no_destructor().run(|_| {
+        stats::watch_leader(&client, Duration::from_millis(10))
+            .take(3)
+            .collect::<Vec<_>>()
+    });
+
+    assert_eq!(leaders[0].as_ref().unwrap(), "a");
+    assert!(leaders[1].is_err());
+    assert_eq!(leaders[2].as_ref().unwrap(), "b");
+    assert_eq!(server.requests().len(), 6);
+}
+
+#[test]
+fn self_stats_rates_from_numbers_or_strings() {
+    let stats: SelfStats = serde_json::from_str(
+        r#"{"id":"1","name":"a","leaderInfo":{"leader":"1","startTime":"","uptime":"1s"},"recvAppendRequestCnt":0,"recvBandwidthRate":"2048.5","recvPkgRate":"","sendAppendRequestCnt":3,"sendBandwidthRate":1024.25,"sendPkgRate":10,"startTime":"","state":"StateLeader"}"#,
+    )
+    .unwrap();
+
+    assert_eq!(stats.received_bandwidth_rate, Some(2048.5));
+    assert_eq!(stats.received_package_rate, None);
+    assert_eq!(stats.sent_bandwidth_rate, Some(1024.25));
+    assert_eq!(stats.sent_package_rate, Some(10.0));
+
+    let stats: SelfStats = serde_json::from_str(
+        r#"{"id":"2","name":"b","leaderInfo":{"leader":"1","startTime":"","uptime":"1s"},"recvAppendRequestCnt":5,"sendAppendRequestCnt":0,"startTime":"","state":"StateFollower"}"#,
+    )
+    .unwrap();
+
+    assert_eq!(stats.received_bandwidth_rate, None);
+    assert_eq!(stats.sent_bandwidth_rate, None);
+
+    let result = serde_json::from_str::<SelfStats>(
+        r#"{"id":"2","name":"b","leaderInfo":{"leader":"1","startTime":"","uptime":"1s"},"recvAppendRequestCnt":5,"recvBandwidthRate":"fast","sendAppendRequestCnt":0,"startTime":"","state":"StateFollower"}"#,
+    );
+    assert!(result.is_err());
+}
+
+#[test]
+fn store_stats_ratios() {
+    let mut stats: StoreStats = serde_json::from_str(
+        r#"{"compareAndDeleteFail":0,"compareAndDeleteSuccess":0,"compareAndSwapFail":1,"compareAndSwapSuccess":2,"createFail":0,"createSuccess":3,"deleteFail":0,"deleteSuccess":1,"expireCount":9,"getsFail":1,"getsSuccess":3,"setsFail":0,"setsSuccess":0,"updateFail":0,"updateSuccess":0,"watchers":2}"#,
+    )
+    .unwrap();
+
+    assert_eq!(stats.get_success_ratio(), Some(0.75));
+    assert_eq!(stats.set_success_ratio(), None);
+    assert_eq!(stats.total_operations(), 11);
+
+    stats.set_fail = 2;
+    assert_eq!(stats.set_success_ratio(), Some(0.0));
+    assert_eq!(stats.total_operations(), 13);
+
+    let idle = stats.delta(&stats);
+    assert_eq!(idle.get_success_ratio(), None);
+    assert_eq!(idle.total_operations(), 0);
+}
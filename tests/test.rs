@@ -1,5 +1,7 @@
-use std::io::Read;
-use std::{fs::File, future::Future};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use std::{fs::File, future::Future, thread};
 
 use etcd::{kv, Client, ClientBuilder};
 use reqwest::{Certificate, Identity};
@@ -14,7 +16,7 @@ pub struct TestClient {
 
 impl TestClient {
     /// Creates a new client for a test.
-    #[allow(dead_code)]
+    #[allow(dead_code, clippy::new_without_default)]
     pub fn new() -> Self {
         Self {
             client: Client::new(&["http://etcd:2379"]),
@@ -55,7 +57,27 @@ impl TestClient {
         };
 
         TestClient {
-            client: client_builder.build(),
+            client: client_builder.build().unwrap(),
+            run_destructor: true,
+            runtime: Runtime::new().expect("failed to create Tokio runtime"),
+        }
+    }
+
+    /// Creates a new HTTPS client for a test from in-memory PEM material.
+    #[allow(dead_code)]
+    pub fn https_pem() -> TestClient {
+        let ca_cert = std::fs::read("/source/tests/ssl/ca.pem").unwrap();
+        let client_cert = std::fs::read("/source/tests/ssl/client.pem").unwrap();
+        let client_key = std::fs::read("/source/tests/ssl/client-key-pkcs8.pem").unwrap();
+
+        let client = ClientBuilder::new(&["https://etcdsecure:2379"])
+            .with_root_certificates_pem(&ca_cert)
+            .with_client_identity_pem(&client_cert, &client_key)
+            .build()
+            .unwrap();
+
+        TestClient {
+            client,
             run_destructor: true,
             runtime: Runtime::new().expect("failed to create Tokio runtime"),
         }
@@ -82,3 +104,106 @@ impl Drop for TestClient {
         }
     }
 }
+
+/// A minimal HTTP server for tests that don't require a running etcd cluster.
+///
+/// Each incoming request is answered with the next of the given status codes and JSON bodies,
+/// after which the server stops accepting connections. The head of each request is recorded.
+pub struct MockServer {
+    address: SocketAddr,
+    requests: Arc<Mutex<Vec<String>>>,
+    request_bodies: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    /// Starts a server that replies with the given responses in order.
+    #[allow(dead_code)]
+    pub fn new(responses: Vec<(u16, &'static str)>) -> Self {
+        Self::with_headers(
+            responses
+                .into_iter()
+                .map(|(status, body)| (status, String::new(), body))
+                .collect(),
+        )
+    }
+
+    /// Starts a server that replies with the given responses in order, each including the given
+    /// extra header lines, such as `"X-Etcd-Cluster-Id: 1\r\n"`.
+    #[allow(dead_code)]
+    pub fn with_headers<B>(responses: Vec<(u16, String, B)>) -> Self
+    where
+        B: Into<Vec<u8>> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded_requests = requests.clone();
+        let request_bodies = Arc::new(Mutex::new(Vec::new()));
+        let recorded_bodies = request_bodies.clone();
+
+        thread::spawn(move || {
+            for ((status, headers, body), stream) in responses.into_iter().zip(listener.incoming())
+            {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = String::new();
+                let mut content_length = 0;
+
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                    head.push_str(&line);
+                }
+
+                let mut request_body = vec![0; content_length];
+                reader.read_exact(&mut request_body).unwrap();
+                recorded_requests.lock().unwrap().push(head);
+                recorded_bodies
+                    .lock()
+                    .unwrap()
+                    .push(String::from_utf8_lossy(&request_body).into_owned());
+
+                let body = body.into();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n",
+                    status,
+                    body.len(),
+                    headers,
+                )
+                .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+
+        MockServer {
+            address,
+            requests,
+            request_bodies,
+        }
+    }
+
+    /// Returns the URL of the server, for use as a client endpoint.
+    #[allow(dead_code)]
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// Returns the heads of the requests received so far.
+    #[allow(dead_code)]
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Returns the bodies of the requests received so far.
+    #[allow(dead_code)]
+    pub fn request_bodies(&self) -> Vec<String> {
+        self.request_bodies.lock().unwrap().clone()
+    }
+}
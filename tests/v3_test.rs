@@ -0,0 +1,284 @@
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use serde_json::Value;
+
+use etcd::v3::kv::{self, PutRequest, RangeRequest};
+use etcd::v3::lease;
+use etcd::v3::watch::{self, EventType, WatchRequest};
+use etcd::{Client, Error};
+
+use crate::test::{MockServer, TestClient};
+
+mod test;
+
+#[test]
+fn range() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"header":{"cluster_id":"14841639068965178418","member_id":"10276657743932975437","revision":"7","raft_term":"3"},"kvs":[{"key":"L2Zvby9h","create_revision":"5","mod_revision":"6","version":"2","value":"AAH/"},{"key":"L2Zvby9i","create_revision":"7","mod_revision":"7","version":"1"}],"more":true,"count":"3"}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let request = RangeRequest {
+        limit: 2,
+        ..RangeRequest::prefix("/foo/")
+    };
+    let response = TestClient::no_destructor()
+        .run(|_| kv::range(&client, request))
+        .unwrap();
+
+    assert!(server.requests()[0].starts_with("POST /v3/kv/range "));
+    let body: Value = serde_json::from_str(&server.request_bodies()[0]).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"key": "L2Zvby8=", "range_end": "L2ZvbzA=", "limit": 2})
+    );
+
+    let data = response.data;
+    assert_eq!(data.header.cluster_id, 14841639068965178418);
+    assert_eq!(data.header.revision, 7);
+    assert_eq!(data.kvs.len(), 2);
+    assert_eq!(data.kvs[0].key, b"/foo/a");
+    assert_eq!(data.kvs[0].value, [0, 1, 255]);
+    assert_eq!(data.kvs[0].version, 2);
+    assert_eq!(data.kvs[1].key, b"/foo/b");
+    assert!(data.kvs[1].value.is_empty());
+    assert!(data.more);
+    assert_eq!(data.count, 3);
+}
+
+#[test]
+fn range_missing_key() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"header":{"cluster_id":"1","member_id":"2","revision":"7","raft_term":"3"}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let response = TestClient::no_destructor()
+        .run(|_| kv::range(&client, RangeRequest::new("/missing")))
+        .unwrap();
+
+    let body: Value = serde_json::from_str(&server.request_bodies()[0]).unwrap();
+    assert_eq!(body, serde_json::json!({"key": "L21pc3Npbmc="}));
+    assert!(response.data.kvs.is_empty());
+    assert_eq!(response.data.count, 0);
+}
+
+#[test]
+fn range_compacted_revision() {
+    let server = MockServer::new(vec![(
+        400,
+        r#"{"error":"etcdserver: mvcc: required revision has been compacted","code":11,"message":"etcdserver: mvcc: required revision has been compacted"}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let request = RangeRequest {
+        revision: 1,
+        ..RangeRequest::new("/foo")
+    };
+    let errors = TestClient::no_destructor()
+        .run(|_| kv::range(&client, request))
+        .unwrap_err();
+
+    match errors[0] {
+        Error::V3(ref status) => {
+            assert_eq!(status.code, 11);
+            assert_eq!(
+                status.message,
+                "etcdserver: mvcc: required revision has been compacted"
+            );
+        }
+        ref error => panic!("expected Error::V3, got {:?}", error),
+    }
+}
+
+#[test]
+fn prefix_range_end() {
+    assert_eq!(RangeRequest::prefix("a").range_end, b"b");
+    assert_eq!(RangeRequest::prefix(vec![b'a', 0xff]).range_end, b"b");
+    assert_eq!(RangeRequest::prefix(vec![0xff]).range_end, [0]);
+    assert_eq!(RangeRequest::prefix("").range_end, [0]);
+}
+
+#[test]
+fn put() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"header":{"cluster_id":"1","member_id":"2","revision":"8","raft_term":"3"},"prev_kv":{"key":"L2Zvbw==","create_revision":"5","mod_revision":"6","version":"2","value":"YmFy"}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let request = PutRequest {
+        lease: 7587848943239472601,
+        prev_kv: true,
+        ..PutRequest::new("/foo", "baz")
+    };
+    let response = TestClient::no_destructor()
+        .run(|_| kv::put(&client, request))
+        .unwrap();
+
+    assert!(server.requests()[0].starts_with("POST /v3/kv/put "));
+    let body: Value = serde_json::from_str(&server.request_bodies()[0]).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"key": "L2Zvbw==", "value": "YmF6", "lease": 7587848943239472601i64, "prev_kv": true})
+    );
+
+    assert_eq!(response.data.header.revision, 8);
+    let prev_kv = response.data.prev_kv.unwrap();
+    assert_eq!(prev_kv.key, b"/foo");
+    assert_eq!(prev_kv.value, b"bar");
+    assert_eq!(prev_kv.mod_revision, 6);
+}
+
+#[test]
+fn put_without_prev_kv() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"header":{"cluster_id":"1","member_id":"2","revision":"9","raft_term":"3"}}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let response = TestClient::no_destructor()
+        .run(|_| kv::put(&client, PutRequest::new("/foo", vec![0, 255])))
+        .unwrap();
+
+    let body: Value = serde_json::from_str(&server.request_bodies()[0]).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"key": "L2Zvbw==", "value": "AP8="})
+    );
+    assert_eq!(response.data.header.revision, 9);
+    assert!(response.data.prev_kv.is_none());
+}
+
+#[test]
+fn lease_grant() {
+    let server = MockServer::new(vec![(
+        200,
+        r#"{"header":{"cluster_id":"1","member_id":"2","revision":"9","raft_term":"3"},"ID":"7587848943239472601","TTL":"60"}"#,
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let response = TestClient::no_destructor()
+        .run(|_| lease::grant(&client, 60))
+        .unwrap();
+
+    assert!(server.requests()[0].starts_with("POST /v3/lease/grant "));
+    let body: Value = serde_json::from_str(&server.request_bodies()[0]).unwrap();
+    assert_eq!(body, serde_json::json!({"TTL": 60}));
+    assert_eq!(response.data.id, 7587848943239472601);
+    assert_eq!(response.data.ttl, 60);
+}
+
+#[test]
+fn lease_keep_alive() {
+    let server = MockServer::new(vec![
+        (
+            200,
+            r#"{"result":{"header":{"cluster_id":"1","member_id":"2","revision":"9","raft_term":"3"},"ID":"7587848943239472601","TTL":"60"}}"#,
+        ),
+        (
+            200,
+            r#"{"error":{"grpc_code":14,"http_code":503,"message":"etcdserver: request timed out","http_status":"Service Unavailable"}}"#,
+        ),
+        (
+            200,
+            r#"{"result":{"header":{"cluster_id":"1","member_id":"2","revision":"9","raft_term":"3"},"ID":"7587848943239472601"}}"#,
+        ),
+    ]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let results = TestClient::no_destructor().run(|_| {
+        lease::keep_alive(
+            &client,
+            7587848943239472601,
+            Some(Duration::from_millis(10)),
+        )
+        .take(3)
+        .collect::<Vec<_>>()
+    });
+
+    assert!(server.requests()[0].starts_with("POST /v3/lease/keepalive "));
+    let body: Value = serde_json::from_str(&server.request_bodies()[0]).unwrap();
+    assert_eq!(body, serde_json::json!({"ID": 7587848943239472601i64}));
+
+    assert_eq!(results[0].as_ref().unwrap().data.ttl, 60);
+    match results[1].as_ref().unwrap_err()[0] {
+        Error::V3(ref status) => assert_eq!(status.code, 14),
+        ref error => panic!("expected Error::V3, got {:?}", error),
+    }
+    match results[2].as_ref().unwrap_err()[0] {
+        Error::V3(ref status) => assert_eq!(status.code, 5),
+        ref error => panic!("expected Error::V3, got {:?}", error),
+    }
+}
+
+#[test]
+fn watch_events() {
+    let server = MockServer::new(vec![(
+        200,
+        concat!(
+            r#"{"result":{"header":{"cluster_id":"1","member_id":"2","revision":"9","raft_term":"3"},"created":true}}"#,
+            "\n",
+            r#"{"result":{"header":{"cluster_id":"1","member_id":"2","revision":"10","raft_term":"3"},"events":[{"kv":{"key":"L2Zvby9h","create_revision":"10","mod_revision":"10","version":"1","value":"MQ=="}}]}}"#,
+            "\n",
+            r#"{"result":{"header":{"cluster_id":"1","member_id":"2","revision":"11","raft_term":"3"},"events":[{"type":"DELETE","kv":{"key":"L2Zvby9h","mod_revision":"11"}}]}}"#,
+            "\n",
+        ),
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let request = WatchRequest {
+        start_revision: 10,
+        ..WatchRequest::prefix("/foo/")
+    };
+    let responses =
+        TestClient::no_destructor().run(|_| watch::watch(&client, request).collect::<Vec<_>>());
+
+    assert!(server.requests()[0].starts_with("POST /v3/watch "));
+    let body: Value = serde_json::from_str(&server.request_bodies()[0]).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({"create_request": {"key": "L2Zvby8=", "range_end": "L2ZvbzA=", "start_revision": 10}})
+    );
+
+    assert_eq!(responses.len(), 2);
+    let put = responses[0].as_ref().unwrap();
+    assert_eq!(put.header.revision, 10);
+    assert_eq!(put.events[0].kind, EventType::Put);
+    assert_eq!(put.events[0].kv.key, b"/foo/a");
+    assert_eq!(put.events[0].kv.value, b"1");
+    let delete = responses[1].as_ref().unwrap();
+    assert_eq!(delete.events[0].kind, EventType::Delete);
+    assert_eq!(delete.events[0].kv.mod_revision, 11);
+}
+
+#[test]
+fn watch_compacted_revision() {
+    let server = MockServer::new(vec![(
+        200,
+        concat!(
+            r#"{"result":{"header":{"cluster_id":"1","member_id":"2","revision":"9","raft_term":"3"},"created":true}}"#,
+            "\n",
+            r#"{"result":{"header":{"cluster_id":"1","member_id":"2","revision":"9","raft_term":"3"},"canceled":true,"compact_revision":"5","cancel_reason":"etcdserver: mvcc: required revision has been compacted"}}"#,
+            "\n",
+        ),
+    )]);
+    let client = Client::new(&[&server.endpoint()]);
+
+    let request = WatchRequest {
+        start_revision: 2,
+        ..WatchRequest::new("/foo")
+    };
+    let responses =
+        TestClient::no_destructor().run(|_| watch::watch(&client, request).collect::<Vec<_>>());
+
+    assert_eq!(responses.len(), 1);
+    match responses[0] {
+        Err(Error::Compacted(revision)) => assert_eq!(revision, 5),
+        ref result => panic!("expected Error::Compacted, got {:?}", result),
+    }
+}